@@ -0,0 +1,108 @@
+//------------------------------------------------------------------------
+//
+//  Compares the spatial indices on a sparse and a dense school. For each
+//  backend it times filling the index, moving every entity a little, and
+//  a radius and a nearest neighbor query around every entity, and counts
+//  the queries whose answer differs from brute force.
+//
+//      cargo run --release --example spatial_index
+//
+//------------------------------------------------------------------------
+
+#![allow(non_snake_case)]
+
+use glam::vec2;
+use rand::SeedableRng;
+use schooling_fishes::cell_space_partition::CellSpacePartition;
+use schooling_fishes::kd_tree::KdTree;
+use schooling_fishes::spatial_hash::SpatialHash;
+use schooling_fishes::spatial_index::BruteForce;
+use schooling_fishes::utils::{RandInRange, SimRng};
+use schooling_fishes::{EntityBase, Obstacle, SpatialIndex};
+use std::time::Instant;
+
+const WIDTH: f32 = 1000.0;
+const HEIGHT: f32 = 800.0;
+const VIEW_DISTANCE: f32 = 50.0;
+const K: usize = 7;
+
+fn NewIndex(name: &str) -> Box<dyn SpatialIndex<Obstacle>> {
+    match name {
+        "grid" => Box::new(CellSpacePartition::new(WIDTH, HEIGHT, 7, 7)),
+        "spatial_hash" => Box::new(SpatialHash::new(WIDTH, HEIGHT, vec2(WIDTH / 7.0, HEIGHT / 7.0))),
+        "kd_tree" => Box::new(KdTree::new(WIDTH, HEIGHT)),
+        _ => Box::new(BruteForce::new(WIDTH, HEIGHT)),
+    }
+}
+
+// the radius and nearest neighbor answers around every entity
+fn Queries(index: &dyn SpatialIndex<Obstacle>, entities: &[Obstacle]) -> Vec<Vec<usize>> {
+    let mut answers = Vec::with_capacity(entities.len() * 2);
+    let mut found = vec![];
+
+    for (i, entity) in entities.iter().enumerate() {
+        index.CalculateNeighbors(entities, entity.position(), VIEW_DISTANCE, &mut found);
+        found.sort_unstable();
+        answers.push(found.clone());
+
+        index.CalculateNearestNeighbors(entities, entity.position(), K, &|j| j != i, &mut found);
+        answers.push(found.clone());
+    }
+
+    answers
+}
+
+fn main() {
+    println!("entities,index,fill_ms,move_ms,query_ms,mismatches");
+
+    for count in [100, 1000, 4000] {
+        let mut rng = SimRng::seed_from_u64(1);
+        let entities: Vec<Obstacle> = (0..count)
+            .map(|_| Obstacle::new(vec2(RandInRange(&mut rng, 0.0, WIDTH), RandInRange(&mut rng, 0.0, HEIGHT)), 1.0))
+            .collect();
+        let moved: Vec<Obstacle> = entities
+            .iter()
+            .map(|entity| {
+                let position = entity.position() + vec2(RandInRange(&mut rng, -5.0, 5.0), RandInRange(&mut rng, -5.0, 5.0));
+                Obstacle::new(position.clamp(vec2(0.0, 0.0), vec2(WIDTH, HEIGHT)), 1.0)
+            })
+            .collect();
+
+        let mut expected = None;
+
+        for name in ["brute_force", "grid", "spatial_hash", "kd_tree"] {
+            let mut index = NewIndex(name);
+
+            let start = Instant::now();
+            for (i, entity) in entities.iter().enumerate() {
+                index.add_entity(i, entity.position());
+            }
+            index.Refresh();
+            let fill = start.elapsed();
+
+            let start = Instant::now();
+            for (i, (from, to)) in entities.iter().zip(&moved).enumerate() {
+                index.UpdateEntity(i, &from.position(), &to.position());
+            }
+            index.Refresh();
+            let update = start.elapsed();
+
+            let start = Instant::now();
+            let answers = Queries(index.as_ref(), &moved);
+            let query = start.elapsed();
+
+            let expected = expected.get_or_insert_with(|| answers.clone());
+            let mismatches = answers.iter().zip(expected.iter()).filter(|(a, b)| a != b).count();
+
+            println!(
+                "{},{},{:.3},{:.3},{:.3},{}",
+                count,
+                name,
+                fill.as_secs_f64() * 1000.0,
+                update.as_secs_f64() * 1000.0,
+                query.as_secs_f64() * 1000.0,
+                mismatches
+            );
+        }
+    }
+}
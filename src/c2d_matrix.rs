@@ -79,7 +79,7 @@ impl C2DMatrix {
         self.MatrixMultiply(mat)
     }
 
-    pub(crate) fn Translate(&self, x: f32, y: f32) -> C2DMatrix {
+    pub fn Translate(&self, x: f32, y: f32) -> C2DMatrix {
         let mat = C2DMatrix {
             _11: 1.0,
             _12: 0.0,
@@ -96,7 +96,7 @@ impl C2DMatrix {
         self.MatrixMultiply(mat)
     }
 
-    pub(crate) fn TransformVector2Ds(&self, vPoint: Vec2) -> Vec2 {
+    pub fn TransformVector2Ds(&self, vPoint: Vec2) -> Vec2 {
         let tempX = (self._11 * vPoint.x) + (self._21 * vPoint.y) + (self._31);
         let tempY = (self._12 * vPoint.x) + (self._22 * vPoint.y) + (self._32);
         vec2(tempX, tempY)
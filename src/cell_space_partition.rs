@@ -1,11 +1,12 @@
-use crate::entity_traits::EntityMovable;
+use crate::entity_traits::EntityBase;
 use crate::inverted_aab_box_2d::InvertedAABBox2D;
+use crate::spatial_index::{SpatialIndex, WidenToNearest, WorldOffset, WorldShifts};
 use glam::{vec2, Vec2};
-use std::cell::RefCell;
-use std::rc::Rc;
 
+// the partition holds entities by their index in the caller's list of
+// entities, e.g. GameWorld::m_Vehicles, and is handed the list for queries
 pub struct Partition {
-    pub members: Vec<Rc<RefCell<dyn EntityMovable>>>,
+    pub members: Vec<usize>,
     pub bounding_box: InvertedAABBox2D,
 }
 
@@ -22,9 +23,6 @@ pub struct CellSpacePartition {
     // the required amount of cells in the space
     pub m_Cells: Vec<Partition>,
 
-    // this is used to store any valid neighbors when an agent searches its neighboring space
-    pub m_Neighbors: Vec<Rc<RefCell<dyn EntityMovable>>>,
-
     // the width and height of the world space the entities inhabit
     m_dSpaceWidth: f32,
     m_dSpaceHeight: f32,
@@ -35,27 +33,31 @@ pub struct CellSpacePartition {
 
     m_dCellSizeX: f32,
     m_dCellSizeY: f32,
+
+    // when set the space wraps around at its edges, so the queries find
+    // entities across an edge and measure distances the short way round
+    m_bToroidal: bool,
 }
 
 impl CellSpacePartition {
-    pub fn new(width: f32, height: f32, num_cells_x: i32, num_cells_y: i32, max_entities: i32) -> Self {
+    pub fn new(width: f32, height: f32, num_cells_x: i32, num_cells_y: i32) -> Self {
         let mut cell_space = CellSpacePartition {
             m_Cells: vec![],
-            m_Neighbors: Vec::with_capacity(max_entities as usize),
             m_dSpaceWidth: width,
             m_dSpaceHeight: height,
             m_iNumCellsX: num_cells_x,
             m_iNumCellsY: num_cells_y,
             m_dCellSizeX: width / num_cells_x as f32,
             m_dCellSizeY: height / num_cells_y as f32,
+            m_bToroidal: false,
         };
 
         for y in 0..cell_space.m_iNumCellsY {
             for x in 0..cell_space.m_iNumCellsX {
                 let left = x as f32 * cell_space.m_dCellSizeX;
-                let right = left * cell_space.m_dCellSizeX;
+                let right = left + cell_space.m_dCellSizeX;
                 let top = y as f32 * cell_space.m_dCellSizeY;
-                let bottom = top * cell_space.m_dCellSizeY;
+                let bottom = top + cell_space.m_dCellSizeY;
 
                 cell_space.m_Cells.push(Partition::new(vec2(left, top), vec2(right, bottom)));
             }
@@ -76,58 +78,163 @@ impl CellSpacePartition {
     //  method calculates an index into its appropriate cell
     //------------------------------------------------------------------------
     pub fn position_to_index(&self, position: &Vec2) -> usize {
-        let idx = (self.m_iNumCellsX as f32 * position.x / self.m_dSpaceWidth)
-            + ((self.m_iNumCellsY as f32 * position.y / self.m_dSpaceHeight) * self.m_iNumCellsX as f32);
+        // each axis is cut down to its column or row on its own, otherwise the
+        // fraction of the row spills over into the columns. A position on the
+        // far edge, or just outside the space, is kept in the last cell.
+        let x = ((self.m_iNumCellsX as f32 * position.x / self.m_dSpaceWidth) as i32).clamp(0, self.m_iNumCellsX - 1);
+        let y = ((self.m_iNumCellsY as f32 * position.y / self.m_dSpaceHeight) as i32).clamp(0, self.m_iNumCellsY - 1);
+
+        (y * self.m_iNumCellsX + x) as usize
+    }
 
-        let mut idx = idx as usize;
+    pub fn SetToroidal(&mut self, toroidal: bool) {
+        self.m_bToroidal = toroidal;
+    }
 
-        // if the entity's position is equal to Vector2D(m_dSpaceWidth, m_dSpaceHeight)
-        // then the index will overshoot. We need to check for this and adjust
-        if idx > self.m_Cells.len() - 1 {
-            idx = self.m_Cells.len() - 1;
-        }
+    pub fn IsToroidal(&self) -> bool {
+        self.m_bToroidal
+    }
+
+    // the displacement from one position to another, the short way round
+    // when the space wraps
+    pub fn Offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        WorldOffset(from, to, vec2(self.m_dSpaceWidth, self.m_dSpaceHeight), self.m_bToroidal)
+    }
 
-        idx
+    // the copies of the space a query has to look into
+    fn Shifts(&self) -> impl Iterator<Item = Vec2> + Clone {
+        WorldShifts(vec2(self.m_dSpaceWidth, self.m_dSpaceHeight), self.m_bToroidal)
     }
 
-    pub fn add_entity(&mut self, entity: Rc<RefCell<dyn EntityMovable>>) {
+    pub fn add_entity(&mut self, entity: usize, position: Vec2) {
         let sz = self.m_Cells.len();
-        let idx = self.position_to_index(&entity.borrow().position()) as usize;
+        let idx = self.position_to_index(&position);
         assert!(idx < sz);
         self.m_Cells[idx].members.push(entity);
     }
 
-    //----------------------- CalculateNeighbors ----------------------------
+    /* NOTE: the queries below only read the partition, so any number of them
+    can run at once or inside one another. Each takes the entities the
+    partition was filled from and gives back their indices, either as an
+    iterator or by filling a buffer owned by the caller, which is cleared
+    first. Results come in cell order, apart from the nearest neighbors
+    which are sorted by distance.
+    */
+
+    // the members of every cell that overlaps the box from top_left to
+    // bottom_right, or one of its shifted copies when the space wraps. A cell
+    // overlapping more than one copy still gives its members once.
+    fn MembersOverlapping(&self, top_left: Vec2, bottom_right: Vec2) -> impl Iterator<Item = usize> + '_ {
+        let shifts = self.Shifts();
+
+        self.m_Cells
+            .iter()
+            .filter(move |cell| {
+                shifts.clone().any(|shift| {
+                    cell.bounding_box
+                        .isOverlappedWith(&InvertedAABBox2D::new(top_left + shift, bottom_right + shift))
+                })
+            })
+            .flat_map(|cell| cell.members.iter().copied())
+    }
+
+    //----------------------- NeighborsWithin -------------------------------
     //
-    //  This must be called to create the vector of neighbors.This method
-    //  examines each cell within range of the target, If the
+    //  This method examines each cell within range of the target, If the
     //  cells contain entities then they are tested to see if they are situated
-    //  within the target's neighborhood region. If they are they are added to
-    //  neighbor list
+    //  within the target's neighborhood region. Those that are are returned.
     //------------------------------------------------------------------------
-    pub fn CalculateNeighbors(&mut self, target_pos: Vec2, query_radius: f32) {
-        // index into the neighbor vector
-        let mut current_neighbor: usize = 0;
-        self.m_Neighbors.clear();
+    pub fn NeighborsWithin<'a, T: EntityBase>(
+        &'a self,
+        entities: &'a [T],
+        target_pos: Vec2,
+        query_radius: f32,
+    ) -> impl Iterator<Item = usize> + 'a {
+        // the query box is the bounding box of the target's query area
+        let query_radius_squared = query_radius * query_radius;
 
-        // create the query box that is the bounding box of the target's query area
-        let query_box = InvertedAABBox2D::new(
+        self.MembersOverlapping(
             target_pos - vec2(query_radius, query_radius),
             target_pos + vec2(query_radius, query_radius),
-        );
+        )
+        .filter(move |&entity| self.Offset(target_pos, entities[entity].position()).length_squared() < query_radius_squared)
+    }
 
-        let query_radius_squared = query_radius * query_radius;
+    // fills neighbors with the entities within query_radius of target_pos
+    pub fn CalculateNeighbors<T: EntityBase>(&self, entities: &[T], target_pos: Vec2, query_radius: f32, neighbors: &mut Vec<usize>) {
+        neighbors.clear();
+        neighbors.extend(self.NeighborsWithin(entities, target_pos, query_radius));
+    }
 
-        for cur_cell in &self.m_Cells {
-            if cur_cell.bounding_box.isOverlappedWith(&query_box) && !cur_cell.members.is_empty() {
-                for entity in &cur_cell.members {
-                    if entity.borrow().position().distance_squared(target_pos) < query_radius_squared {
-                        self.m_Neighbors.push(entity.clone());
-                        current_neighbor += 1;
-                    }
-                }
-            }
-        }
+    //----------------------- EntitiesInBox ---------------------------------
+    //
+    //  the entities whose positions lie within the box from top_left to
+    //  bottom_right, edges included. When the space wraps the box may reach
+    //  over an edge and then takes in the entities on the other side.
+    //------------------------------------------------------------------------
+    pub fn EntitiesInBox<'a, T: EntityBase>(
+        &'a self,
+        entities: &'a [T],
+        top_left: Vec2,
+        bottom_right: Vec2,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let shifts = self.Shifts();
+
+        self.MembersOverlapping(top_left, bottom_right).filter(move |&entity| {
+            let position = entities[entity].position();
+            shifts.clone().any(|shift| {
+                let position = position - shift;
+                position.cmpge(top_left).all() && position.cmple(bottom_right).all()
+            })
+        })
+    }
+
+    // fills found with the entities within the box from top_left to bottom_right
+    pub fn CalculateEntitiesInBox<T: EntityBase>(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        found.clear();
+        found.extend(self.EntitiesInBox(entities, top_left, bottom_right));
+    }
+
+    //----------------------- CalculateNearestNeighbors ---------------------
+    //
+    //  fills neighbors with the k entities nearest to target_pos, nearest
+    //  first, leaving out any for which include returns false, such as the
+    //  entity at target_pos itself. Entities at the same distance are taken
+    //  in index order. The search starts with the cells around the target and
+    //  widens until it has found k or looked at every entity.
+    //------------------------------------------------------------------------
+    pub fn CalculateNearestNeighbors<T: EntityBase>(
+        &self,
+        entities: &[T],
+        target_pos: Vec2,
+        k: usize,
+        include: impl Fn(usize) -> bool,
+        neighbors: &mut Vec<usize>,
+    ) {
+        WidenToNearest(
+            k,
+            &include,
+            self.m_dCellSizeX.max(self.m_dCellSizeY),
+            self.m_Cells.iter().map(|cell| cell.members.len()).sum(),
+            |query_radius, found| self.CalculateNeighbors(entities, target_pos, query_radius, found),
+            |entity| self.Offset(target_pos, entities[entity].position()).length_squared(),
+            neighbors,
+        );
+    }
+
+    // the k entities nearest to target_pos, nearest first, as an iterator.
+    // The search has to finish before the nearest is known so this collects
+    // them first, CalculateNearestNeighbors saves the allocation.
+    pub fn NearestNeighbors<T: EntityBase>(
+        &self,
+        entities: &[T],
+        target_pos: Vec2,
+        k: usize,
+        include: impl Fn(usize) -> bool,
+    ) -> impl Iterator<Item = usize> {
+        let mut neighbors = Vec::with_capacity(k);
+        self.CalculateNearestNeighbors(entities, target_pos, k, include, &mut neighbors);
+        neighbors.into_iter()
     }
 
     //----------------------- UpdateEntity -----------------------------------
@@ -135,31 +242,244 @@ impl CellSpacePartition {
     //  Checks to see if an entity has moved cells. If so the data structure
     //  is updated accordingly
     //------------------------------------------------------------------------
-    pub fn UpdateEntity(&mut self, entity: Rc<RefCell<dyn EntityMovable>>, old_position: &Vec2) {
+    pub fn UpdateEntity(&mut self, entity: usize, old_position: &Vec2, new_position: &Vec2) {
         // if the index for the old pos and the new pos are not equal then
         // the entity has moved to another cell.
         let old_idx = self.position_to_index(old_position);
-        let new_idx = self.position_to_index(&entity.borrow().position());
+        let new_idx = self.position_to_index(new_position);
 
         if new_idx == old_idx {
             return;
         }
 
         // the entity has moved into another cell so remove it from current cell and add to new one
-        if let Some(member_index) = self.m_Cells[old_idx]
-            .members
-            .iter()
-            .position(|member| member.borrow().id() == entity.borrow().id())
-        {
+        if let Some(member_index) = self.m_Cells[old_idx].members.iter().position(|&member| member == entity) {
             self.m_Cells[old_idx].members.remove(member_index);
         }
 
-        self.m_Cells[new_idx].members.push(entity.clone());
+        self.m_Cells[new_idx].members.push(entity);
     }
 
+    #[cfg(feature = "render")]
     pub fn render_cells(&self) {
         for cell in &self.m_Cells {
             cell.bounding_box.render();
         }
     }
 }
+
+// the grid is the index the world uses unless the config picks another
+impl<T: EntityBase> SpatialIndex<T> for CellSpacePartition {
+    fn add_entity(&mut self, entity: usize, position: Vec2) {
+        CellSpacePartition::add_entity(self, entity, position);
+    }
+
+    fn UpdateEntity(&mut self, entity: usize, old_position: &Vec2, new_position: &Vec2) {
+        CellSpacePartition::UpdateEntity(self, entity, old_position, new_position);
+    }
+
+    fn EmptyCells(&mut self) {
+        CellSpacePartition::EmptyCells(self);
+    }
+
+    fn SetToroidal(&mut self, toroidal: bool) {
+        CellSpacePartition::SetToroidal(self, toroidal);
+    }
+
+    fn IsToroidal(&self) -> bool {
+        CellSpacePartition::IsToroidal(self)
+    }
+
+    fn Offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        CellSpacePartition::Offset(self, from, to)
+    }
+
+    fn CalculateNeighbors(&self, entities: &[T], target_pos: Vec2, query_radius: f32, neighbors: &mut Vec<usize>) {
+        CellSpacePartition::CalculateNeighbors(self, entities, target_pos, query_radius, neighbors);
+    }
+
+    fn CalculateEntitiesInBox(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        CellSpacePartition::CalculateEntitiesInBox(self, entities, top_left, bottom_right, found);
+    }
+
+    fn CalculateNearestNeighbors(
+        &self,
+        entities: &[T],
+        target_pos: Vec2,
+        k: usize,
+        include: &dyn Fn(usize) -> bool,
+        neighbors: &mut Vec<usize>,
+    ) {
+        CellSpacePartition::CalculateNearestNeighbors(self, entities, target_pos, k, include, neighbors);
+    }
+
+    fn Cells(&self) -> Vec<Vec<usize>> {
+        self.m_Cells.iter().map(|cell| cell.members.clone()).collect()
+    }
+
+    // the members are summed in the order they arrived in their cells, so
+    // that order is put back rather than adding the entities again
+    fn RestoreCells(&mut self, cells: &[Vec<usize>], _entities: &[T]) -> bool {
+        if cells.len() != self.m_Cells.len() {
+            return false;
+        }
+
+        for (cell, members) in self.m_Cells.iter_mut().zip(cells) {
+            cell.members.clone_from(members);
+        }
+
+        true
+    }
+
+    #[cfg(feature = "render")]
+    fn render_cells(&self) {
+        CellSpacePartition::render_cells(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell_space_partition::CellSpacePartition;
+    use crate::entity_traits::EntityBase;
+    use crate::obstacle::Obstacle;
+    use crate::utils::{RandInRange, SimRng, WrappedOffset};
+    use glam::{vec2, Vec2};
+    use rand::SeedableRng;
+
+    #[test]
+    pub fn test_neighbor_queries() {
+        let mut rng = SimRng::seed_from_u64(1);
+        let entities: Vec<Obstacle> = (0..300)
+            .map(|_| Obstacle::new(vec2(RandInRange(&mut rng, 0.0, 1000.0), RandInRange(&mut rng, 0.0, 800.0)), 1.0))
+            .collect();
+
+        let mut cell_space = CellSpacePartition::new(1000.0, 800.0, 7, 7);
+        for (index, entity) in entities.iter().enumerate() {
+            cell_space.add_entity(index, entity.position());
+        }
+
+        let target = entities[0].position();
+
+        let within: Vec<usize> = cell_space.NeighborsWithin(&entities, target, 150.0).collect();
+        let mut buffer = vec![42];
+        cell_space.CalculateNeighbors(&entities, target, 150.0, &mut buffer);
+        assert_eq!(within, buffer);
+        assert!(within.contains(&0));
+        assert!(within.iter().all(|&i| entities[i].position().distance(target) < 150.0));
+
+        let (top_left, bottom_right) = (vec2(200.0, 100.0), vec2(600.0, 500.0));
+        cell_space.CalculateEntitiesInBox(&entities, top_left, bottom_right, &mut buffer);
+        assert_eq!(
+            buffer,
+            cell_space.EntitiesInBox(&entities, top_left, bottom_right).collect::<Vec<usize>>()
+        );
+        assert!(!buffer.is_empty());
+        assert!(buffer.iter().all(|&i| {
+            let position = entities[i].position();
+            position.cmpge(top_left).all() && position.cmple(bottom_right).all()
+        }));
+
+        // nearest first, without the target itself, and k of them even when
+        // the search has to widen
+        cell_space.CalculateNearestNeighbors(&entities, target, 7, |i| i != 0, &mut buffer);
+        assert_eq!(buffer.len(), 7);
+        assert!(!buffer.contains(&0));
+        let distances: Vec<f32> = buffer.iter().map(|&i| entities[i].position().distance(target)).collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(
+            buffer,
+            cell_space
+                .NearestNeighbors(&entities, target, 7, |i| i != 0)
+                .collect::<Vec<usize>>()
+        );
+
+        cell_space.CalculateNearestNeighbors(&entities, target, 1000, |_| true, &mut buffer);
+        assert_eq!(buffer.len(), entities.len());
+    }
+
+    // every query finds exactly what checking every entity finds, in a flat
+    // space and in one that wraps, including boxes reaching over the edges
+    #[test]
+    pub fn test_queries_match_brute_force() {
+        let (width, height) = (1000.0, 800.0);
+        let mut rng = SimRng::seed_from_u64(2);
+        let mut entities: Vec<Obstacle> = (0..400)
+            .map(|_| Obstacle::new(vec2(RandInRange(&mut rng, 0.0, width), RandInRange(&mut rng, 0.0, height)), 1.0))
+            .collect();
+        // a pair either side of the seam and one on the far corner
+        entities.push(Obstacle::new(vec2(2.0, 400.0), 1.0));
+        entities.push(Obstacle::new(vec2(997.0, 400.0), 1.0));
+        entities.push(Obstacle::new(vec2(width, height), 1.0));
+
+        for toroidal in [false, true] {
+            let mut cell_space = CellSpacePartition::new(width, height, 7, 5);
+            cell_space.SetToroidal(toroidal);
+            for (index, entity) in entities.iter().enumerate() {
+                cell_space.add_entity(index, entity.position());
+            }
+
+            let offset = |from: Vec2, to: Vec2| {
+                if toroidal {
+                    WrappedOffset(from, to, vec2(width, height))
+                } else {
+                    to - from
+                }
+            };
+
+            for target_index in [0, 57, 400, 401, 402] {
+                let target = entities[target_index].position();
+
+                for radius in [10.0, 60.0, 150.0, 700.0] {
+                    let mut found: Vec<usize> = cell_space.NeighborsWithin(&entities, target, radius).collect();
+                    found.sort();
+                    let expected: Vec<usize> = (0..entities.len())
+                        .filter(|&i| offset(target, entities[i].position()).length_squared() < radius * radius)
+                        .collect();
+                    assert_eq!(found, expected, "toroidal {} target {} radius {}", toroidal, target_index, radius);
+                }
+
+                for k in [1, 5, 40] {
+                    let found: Vec<usize> = cell_space.NearestNeighbors(&entities, target, k, |i| i != target_index).collect();
+                    let mut expected: Vec<usize> = (0..entities.len()).filter(|&i| i != target_index).collect();
+                    expected.sort_by(|&a, &b| {
+                        let distance_a = offset(target, entities[a].position()).length_squared();
+                        let distance_b = offset(target, entities[b].position()).length_squared();
+                        distance_a.total_cmp(&distance_b).then(a.cmp(&b))
+                    });
+                    expected.truncate(k);
+                    assert_eq!(found, expected, "toroidal {} target {} k {}", toroidal, target_index, k);
+                }
+            }
+
+            for (top_left, bottom_right) in [
+                (vec2(200.0, 100.0), vec2(600.0, 500.0)),
+                (vec2(-50.0, 300.0), vec2(50.0, 500.0)),
+                (vec2(950.0, 750.0), vec2(1050.0, 850.0)),
+            ] {
+                let mut found: Vec<usize> = cell_space.EntitiesInBox(&entities, top_left, bottom_right).collect();
+                found.sort();
+                let shifts: Vec<Vec2> = if toroidal {
+                    [-1.0, 0.0, 1.0]
+                        .iter()
+                        .flat_map(|&y| [-1.0, 0.0, 1.0].map(|x| vec2(x * width, y * height)))
+                        .collect()
+                } else {
+                    vec![Vec2::ZERO]
+                };
+                let expected: Vec<usize> = (0..entities.len())
+                    .filter(|&i| {
+                        shifts.iter().any(|&shift| {
+                            let position = entities[i].position() + shift;
+                            position.cmpge(top_left).all() && position.cmple(bottom_right).all()
+                        })
+                    })
+                    .collect();
+                assert_eq!(found, expected, "toroidal {} box {} {}", toroidal, top_left, bottom_right);
+            }
+
+            // the pair either side of the seam only see each other when it wraps
+            let across: Vec<usize> = cell_space.NeighborsWithin(&entities, entities[400].position(), 10.0).collect();
+            assert_eq!(across.contains(&401), toroidal);
+        }
+    }
+}
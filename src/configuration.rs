@@ -1,7 +1,27 @@
+use crate::game_world::OBSTACLE_BORDER;
+use crate::spatial_index::SpatialIndexType;
+use crate::steering_behavior::NeighborhoodMode;
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 const STEERING_FORCE_TWEAKER: f32 = 200.0;
 
+// the size of the window, which is also the size of the world
+pub const SCR_WIDTH: f32 = 1000.0;
+pub const SCR_HEIGHT: f32 = 800.0;
+
+// The simulation parameters. A config file only needs to list the keys it
+// changes, anything missing keeps the value from CONFIG. Keys use the same
+// names as the fields, e.g.
+//
+//     NumAgents = 400
+//     ViewDistance = 60.0
+//
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ConfigLoader {
     pub NumAgents: i32,
     pub NumObstacles: i32,
@@ -13,6 +33,10 @@ pub struct ConfigLoader {
     //number of vertical cells used for spatial partitioning
     pub NumCellsY: i32,
 
+    //the structure the neighbors are looked up in, grid, spatial_hash,
+    //kd_tree or brute_force. The grid and hash use cells of the size above
+    pub SpatialIndex: SpatialIndexType,
+
     //how many samples the smoother will use to average a value
     pub NumSamplesForSmoothing: i32,
 
@@ -48,12 +72,38 @@ pub struct ConfigLoader {
     //to be within its neighborhood)
     pub ViewDistance: f32,
 
+    //which neighbours the flocking behaviors steer by, metric (all within
+    //ViewDistance), k_nearest (the NumNearestNeighbors nearest) or hybrid
+    //(the NumNearestNeighbors nearest within ViewDistance)
+    pub Neighborhood: NeighborhoodMode,
+    pub NumNearestNeighbors: i32,
+
     //used in obstacle avoidance
     pub MinDetectionBoxLength: f32,
 
     //used in wall avoidance
     pub WallDetectionFeelerLength: f32,
 
+    //surround the world with the walls of a tank instead of wrapping
+    //the agents around the edges
+    pub TankWalls: bool,
+
+    //let the agents see neighbors across the edges of the world, where
+    //they wrap around to, so a school isn't torn apart at an edge
+    pub ToroidalNeighbors: bool,
+
+    //the simulation advances in steps of this many seconds however long
+    //a rendered frame takes
+    pub FixedTimeStep: f32,
+
+    //the most steps run for one frame. Time beyond that is dropped so a
+    //long hitch slows the simulation down instead of stalling it
+    pub MaxSubSteps: i32,
+
+    //seed for the world's random number generator. The same seed and time
+    //step reproduce a run exactly, without one every run is different
+    pub Seed: Option<u64>,
+
     //these are the probabilities that a steering behavior will be used
     //when the prioritized dither calculate method is used
     pub prWallAvoidance: f32,
@@ -67,8 +117,13 @@ pub struct ConfigLoader {
     pub prEvade: f32,
     pub prHide: f32,
     pub prArrive: f32,
+    pub prPursuit: f32,
+    pub prOffsetPursuit: f32,
+    pub prInterpose: f32,
+    pub prFollowPath: f32,
 }
 
+// the built in parameters, used for any key a config file leaves out
 pub const CONFIG: ConfigLoader = ConfigLoader {
     NumAgents: 250,
     NumObstacles: 7,
@@ -77,6 +132,7 @@ pub const CONFIG: ConfigLoader = ConfigLoader {
 
     NumCellsX: 7,
     NumCellsY: 7,
+    SpatialIndex: SpatialIndexType::grid,
 
     NumSamplesForSmoothing: 10,
 
@@ -104,8 +160,15 @@ pub const CONFIG: ConfigLoader = ConfigLoader {
     FollowPathWeight: 1.0 * STEERING_FORCE_TWEAKER,
 
     ViewDistance: 50.0,
+    Neighborhood: NeighborhoodMode::metric,
+    NumNearestNeighbors: 7,
     MinDetectionBoxLength: 40.0,
     WallDetectionFeelerLength: 40.0,
+    TankWalls: false,
+    ToroidalNeighbors: false,
+    FixedTimeStep: 1.0 / 60.0,
+    MaxSubSteps: 5,
+    Seed: None,
 
     prWallAvoidance: 0.5,
     prObstacleAvoidance: 0.5,
@@ -118,6 +181,358 @@ pub const CONFIG: ConfigLoader = ConfigLoader {
     prEvade: 1.0,
     prHide: 0.8,
     prArrive: 0.5,
+    prPursuit: 0.8,
+    prOffsetPursuit: 0.8,
+    prInterpose: 0.8,
+    prFollowPath: 0.8,
 
     MaxTurnRatePerSecond: PI,
 };
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        CONFIG
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // the file could not be read
+    Io(PathBuf, std::io::Error),
+    // the file is not valid TOML or has a key of the wrong type or an unknown key
+    Parse(toml::de::Error),
+    // a key has a value the simulation can't run with
+    Invalid { key: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, error) => write!(f, "could not read config file {}: {}", path.display(), error),
+            ConfigError::Parse(error) => write!(f, "could not parse config: {}", error),
+            ConfigError::Invalid { key, reason } => write!(f, "invalid config value for {}: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigLoader {
+    pub fn load(path: impl AsRef<Path>) -> Result<ConfigLoader, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Io(path.to_path_buf(), error))?;
+        ConfigLoader::from_toml_str(&text)
+    }
+
+    pub fn from_toml_str(text: &str) -> Result<ConfigLoader, ConfigError> {
+        let config: ConfigLoader = toml::from_str(text).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    //------------------------------- validate -------------------------------
+    //
+    //  checks every value against the range the simulation can run with and
+    //  returns the first one that is out of it
+    //------------------------------------------------------------------------
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        fn check(ok: bool, key: &'static str, reason: &str) -> Result<(), ConfigError> {
+            if ok {
+                Ok(())
+            } else {
+                Err(ConfigError::Invalid {
+                    key,
+                    reason: reason.to_string(),
+                })
+            }
+        }
+
+        let non_negative = |value: f32| value.is_finite() && value >= 0.0;
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+        let probability = |value: f32| value > 0.0 && value <= 1.0;
+
+        check(self.NumAgents >= 1, "NumAgents", "must be at least 1, for the shark")?;
+        check(self.NumObstacles >= 0, "NumObstacles", "must not be negative")?;
+        check(non_negative(self.MinObstacleRadius), "MinObstacleRadius", "must not be negative")?;
+        check(non_negative(self.MaxObstacleRadius), "MaxObstacleRadius", "must not be negative")?;
+        check(
+            self.MinObstacleRadius <= self.MaxObstacleRadius,
+            "MaxObstacleRadius",
+            "must not be less than MinObstacleRadius",
+        )?;
+        check(
+            2.0 * (self.MaxObstacleRadius + OBSTACLE_BORDER) < SCR_WIDTH.min(SCR_HEIGHT),
+            "MaxObstacleRadius",
+            "must leave room for an obstacle inside the window",
+        )?;
+
+        check(self.NumCellsX > 0, "NumCellsX", "must be at least one cell")?;
+        check(self.NumCellsY > 0, "NumCellsY", "must be at least one cell")?;
+        check(
+            self.NumSamplesForSmoothing > 0,
+            "NumSamplesForSmoothing",
+            "must be at least one sample",
+        )?;
+
+        check(
+            positive(self.SteeringForceTweaker),
+            "SteeringForceTweaker",
+            "must be greater than zero",
+        )?;
+        check(positive(self.MaxSteeringForce), "MaxSteeringForce", "must be greater than zero")?;
+        check(positive(self.MaxSpeed), "MaxSpeed", "must be greater than zero")?;
+        check(positive(self.VehicleMass), "VehicleMass", "must be greater than zero")?;
+        check(positive(self.Scale), "Scale", "must be greater than zero")?;
+        check(
+            positive(self.MaxTurnRatePerSecond),
+            "MaxTurnRatePerSecond",
+            "must be greater than zero",
+        )?;
+
+        let weights = [
+            ("SeparationWeight", self.SeparationWeight),
+            ("AlignmentWeight", self.AlignmentWeight),
+            ("CohesionWeight", self.CohesionWeight),
+            ("ObstacleAvoidanceWeight", self.ObstacleAvoidanceWeight),
+            ("WallAvoidanceWeight", self.WallAvoidanceWeight),
+            ("WanderWeight", self.WanderWeight),
+            ("SeekWeight", self.SeekWeight),
+            ("FleeWeight", self.FleeWeight),
+            ("ArriveWeight", self.ArriveWeight),
+            ("PursuitWeight", self.PursuitWeight),
+            ("OffsetPursuitWeight", self.OffsetPursuitWeight),
+            ("InterposeWeight", self.InterposeWeight),
+            ("HideWeight", self.HideWeight),
+            ("EvadeWeight", self.EvadeWeight),
+            ("FollowPathWeight", self.FollowPathWeight),
+        ];
+
+        for (key, weight) in weights {
+            check(non_negative(weight), key, "must not be negative")?;
+        }
+
+        check(non_negative(self.ViewDistance), "ViewDistance", "must not be negative")?;
+        check(
+            self.NumNearestNeighbors > 0,
+            "NumNearestNeighbors",
+            "must be at least one neighbour",
+        )?;
+        check(
+            non_negative(self.MinDetectionBoxLength),
+            "MinDetectionBoxLength",
+            "must not be negative",
+        )?;
+        check(
+            non_negative(self.WallDetectionFeelerLength),
+            "WallDetectionFeelerLength",
+            "must not be negative",
+        )?;
+        check(positive(self.FixedTimeStep), "FixedTimeStep", "must be greater than zero")?;
+        check(self.MaxSubSteps > 0, "MaxSubSteps", "must be at least one step")?;
+
+        // the dithered summing method divides by these
+        let probabilities = [
+            ("prWallAvoidance", self.prWallAvoidance),
+            ("prObstacleAvoidance", self.prObstacleAvoidance),
+            ("prSeparation", self.prSeparation),
+            ("prAlignment", self.prAlignment),
+            ("prCohesion", self.prCohesion),
+            ("prWander", self.prWander),
+            ("prSeek", self.prSeek),
+            ("prFlee", self.prFlee),
+            ("prEvade", self.prEvade),
+            ("prHide", self.prHide),
+            ("prArrive", self.prArrive),
+            ("prPursuit", self.prPursuit),
+            ("prOffsetPursuit", self.prOffsetPursuit),
+            ("prInterpose", self.prInterpose),
+            ("prFollowPath", self.prFollowPath),
+        ];
+
+        for (key, probability_value) in probabilities {
+            check(probability(probability_value), key, "must be greater than zero and at most one")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigLoader {
+    //----------------------------- NonLiveChanges ---------------------------
+    //
+    //  returns the keys that differ from other but only take effect when a
+    //  world is created, such as the number of agents or cells
+    //------------------------------------------------------------------------
+    pub fn non_live_changes(&self, other: &ConfigLoader) -> Vec<&'static str> {
+        let mut keys = vec![];
+
+        if self.NumAgents != other.NumAgents {
+            keys.push("NumAgents");
+        }
+        if self.NumObstacles != other.NumObstacles {
+            keys.push("NumObstacles");
+        }
+        if self.MinObstacleRadius != other.MinObstacleRadius {
+            keys.push("MinObstacleRadius");
+        }
+        if self.MaxObstacleRadius != other.MaxObstacleRadius {
+            keys.push("MaxObstacleRadius");
+        }
+        if self.NumCellsX != other.NumCellsX {
+            keys.push("NumCellsX");
+        }
+        if self.NumCellsY != other.NumCellsY {
+            keys.push("NumCellsY");
+        }
+        if self.SpatialIndex != other.SpatialIndex {
+            keys.push("SpatialIndex");
+        }
+        if self.NumSamplesForSmoothing != other.NumSamplesForSmoothing {
+            keys.push("NumSamplesForSmoothing");
+        }
+        if self.Seed != other.Seed {
+            keys.push("Seed");
+        }
+        // only used to scale the built in weights
+        if self.SteeringForceTweaker != other.SteeringForceTweaker {
+            keys.push("SteeringForceTweaker");
+        }
+
+        keys
+    }
+
+    // a copy of self with the keys in overrides replaced, checked the same way
+    // as a config file
+    pub fn with_overrides(&self, overrides: &toml::Table) -> Result<ConfigLoader, ConfigError> {
+        // Seed is left out because TOML integers can't hold every u64
+        let without_seed = ConfigLoader { Seed: None, ..*self };
+        let mut table = toml::Table::try_from(without_seed).expect("every key but Seed fits in TOML");
+        table.extend(overrides.iter().map(|(key, value)| (key.clone(), value.clone())));
+
+        let mut config: ConfigLoader = table.try_into().map_err(ConfigError::Parse)?;
+        if !overrides.contains_key("Seed") {
+            config.Seed = self.Seed;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    // a copy of self with the keys that can't change while running taken from running
+    pub fn with_non_live_from(&self, running: &ConfigLoader) -> ConfigLoader {
+        ConfigLoader {
+            NumAgents: running.NumAgents,
+            NumObstacles: running.NumObstacles,
+            MinObstacleRadius: running.MinObstacleRadius,
+            MaxObstacleRadius: running.MaxObstacleRadius,
+            NumCellsX: running.NumCellsX,
+            NumCellsY: running.NumCellsY,
+            SpatialIndex: running.SpatialIndex,
+            NumSamplesForSmoothing: running.NumSamplesForSmoothing,
+            SteeringForceTweaker: running.SteeringForceTweaker,
+            Seed: running.Seed,
+            ..*self
+        }
+    }
+}
+
+// Watches a config file by polling its modification time, so a running
+// simulation can pick up edits without a restart.
+pub struct ConfigWatcher {
+    m_Path: PathBuf,
+    m_LastModified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: impl AsRef<Path>) -> ConfigWatcher {
+        let path = path.as_ref().to_path_buf();
+        let last_modified = ConfigWatcher::modified(&path);
+
+        ConfigWatcher {
+            m_Path: path,
+            m_LastModified: last_modified,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.m_Path
+    }
+
+    // returns the freshly loaded file if it has changed since the last poll
+    pub fn poll(&mut self) -> Option<Result<ConfigLoader, ConfigError>> {
+        let modified = ConfigWatcher::modified(&self.m_Path);
+
+        if modified.is_none() || modified == self.m_LastModified {
+            return None;
+        }
+
+        self.m_LastModified = modified;
+        Some(ConfigLoader::load(&self.m_Path))
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::{ConfigError, ConfigLoader, CONFIG};
+
+    #[test]
+    pub fn test_missing_keys_use_defaults() {
+        let config = ConfigLoader::from_toml_str("NumAgents = 400\nViewDistance = 60.0\n").unwrap();
+
+        assert_eq!(config.NumAgents, 400);
+        assert_eq!(config.ViewDistance, 60.0);
+        assert_eq!(config.CohesionWeight, CONFIG.CohesionWeight);
+        assert_eq!(ConfigLoader::from_toml_str("").unwrap(), CONFIG);
+    }
+
+    #[test]
+    pub fn test_sample_config_matches_defaults() {
+        assert_eq!(ConfigLoader::load("assets/config/schooling.toml").unwrap(), CONFIG);
+    }
+
+    #[test]
+    pub fn test_invalid_values() {
+        let result = ConfigLoader::from_toml_str("MinObstacleRadius = -5.0");
+        assert!(matches!(
+            result,
+            Err(ConfigError::Invalid {
+                key: "MinObstacleRadius",
+                ..
+            })
+        ));
+
+        let result = ConfigLoader::from_toml_str("MaxObstacleRadius = 500.0");
+        assert!(matches!(
+            result,
+            Err(ConfigError::Invalid {
+                key: "MaxObstacleRadius",
+                ..
+            })
+        ));
+
+        let result = ConfigLoader::from_toml_str("MinObstacleRadius = 40.0\nMaxObstacleRadius = 20.0");
+        assert!(matches!(
+            result,
+            Err(ConfigError::Invalid {
+                key: "MaxObstacleRadius",
+                ..
+            })
+        ));
+
+        let result = ConfigLoader::from_toml_str("NumAgents = 0");
+        assert!(matches!(result, Err(ConfigError::Invalid { key: "NumAgents", .. })));
+
+        let result = ConfigLoader::from_toml_str("NumCellsX = 0");
+        assert!(matches!(result, Err(ConfigError::Invalid { key: "NumCellsX", .. })));
+
+        let result = ConfigLoader::from_toml_str("NumAgentz = 10");
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+
+        let result = ConfigLoader::from_toml_str("NumAgents = \"many\"");
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+    }
+}
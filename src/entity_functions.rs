@@ -1,51 +1,68 @@
-// //------------------------- Overlapped -----------------------------------
-// //
-// //  tests to see if an entity is overlapping any of a number of entities
-// //  stored in a std container
-// //------------------------------------------------------------------------
-// template <class T, class conT>
-// bool Overlapped(const T* ob, const conT& conOb, float MinDistBetweenObstacles = 40.0)
-// {
-// typename conT::const_iterator it;
+use crate::entity_traits::EntityBase;
+use glam::Vec2;
+
+//----------------------- TwoCirclesOverlapped ---------------------------
 //
-// for (it=conOb.begin(); it != conOb.end(); ++it)
-// {
-// if (TwoCirclesOverlapped(ob->Pos(),
-// ob->BRadius()+MinDistBetweenObstacles,
-// (*it)->Pos(),
-// (*it)->BRadius()))
-// {
-// return true;
-// }
-// }
+//  Returns true if the two circles overlap
+//------------------------------------------------------------------------
+pub fn TwoCirclesOverlapped(c1: Vec2, r1: f32, c2: Vec2, r2: f32) -> bool {
+    let dist_between_centers = c1.distance(c2);
+
+    (dist_between_centers < (r1 + r2)) || (dist_between_centers < (r1 - r2).abs())
+}
+
+//------------------------- Overlapped -----------------------------------
 //
-// return false;
-// }
+//  tests to see if an entity is overlapping any of a number of entities
+//  stored in a std container
+//------------------------------------------------------------------------
+pub fn Overlapped<T: EntityBase>(ob: &dyn EntityBase, con_ob: &[T], min_dist_between_obstacles: f32) -> bool {
+    for it in con_ob {
+        if TwoCirclesOverlapped(
+            ob.position(),
+            ob.bounding_radius() + min_dist_between_obstacles,
+            it.position(),
+            it.bounding_radius(),
+        ) {
+            return true;
+        }
+    }
 
-use crate::entity_traits::EntityMovable;
-use std::cell::RefCell;
-use std::rc::Rc;
+    false
+}
+
+//----------------------- WithinRange -----------------------------------
+//
+//  true if entity is within radius of position. The bounding radius of the
+//  entity is taken into account by adding it to the range.
+//------------------------------------------------------------------------
+pub fn WithinRange(position: Vec2, entity: &dyn EntityBase, radius: f32) -> bool {
+    let to = entity.position() - position;
+    let range = radius + entity.bounding_radius();
+
+    // working in distance-squared space to avoid square roots
+    to.length_squared() < range * range
+}
 
 //----------------------- TagNeighbors ----------------------------------
 //
 //  tags any entities contained in a std container that are within the
-//  radius of the single entity parameter
+//  radius of the entity with the given id and position. The entity may be
+//  in the container itself, it never tags itself.
 //------------------------------------------------------------------------
-pub fn TagNeighbors(entity: Rc<RefCell<dyn EntityMovable>>, vec_of_entities: &RefCell<Vec<Rc<RefCell<dyn EntityMovable>>>>, radius: f32) {
+pub fn TagNeighbors<T: EntityBase>(id: i32, position: Vec2, vec_of_entities: &mut [T], radius: f32) {
     // iterate through all entities checking for range
-    for curEntity in vec_of_entities.borrow_mut().iter_mut() {
-        // first clear any current tag
-        curEntity.borrow_mut().untag();
-
-        let to = curEntity.borrow().position() - entity.borrow().position();
+    for curEntity in vec_of_entities {
+        if curEntity.id() == id {
+            continue;
+        }
 
-        // the bounding radius of the other is taken into account by adding it to the range
-        let range = radius + curEntity.borrow().bounding_radius();
+        // first clear any current tag
+        curEntity.untag();
 
-        // if entity within range, tag for further consideration. (working in
-        // distance-squared space to avoid square roots)
-        if (curEntity.borrow().id() != entity.borrow().id()) && (to.length_squared() < range * range) {
-            curEntity.borrow_mut().tag();
+        // if entity within range, tag for further consideration
+        if WithinRange(position, curEntity, radius) {
+            curEntity.tag();
         }
     }
 }
@@ -4,6 +4,7 @@ static mut NEXT_ID: i32 = -1;
 
 pub enum EntityType {
     default_entity_type = -1,
+    obstacle = 1,
 }
 pub fn next_valid_id() -> i32 {
     unsafe {
@@ -12,6 +13,14 @@ pub fn next_valid_id() -> i32 {
     }
 }
 
+// makes sure ids handed out from now on come after id, used when entities
+// are restored with the ids they were saved with
+pub fn reserve_ids_through(id: i32) {
+    unsafe {
+        NEXT_ID = NEXT_ID.max(id);
+    }
+}
+
 pub trait EntityBase {
     fn id(&self) -> i32;
 
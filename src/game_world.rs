@@ -1,32 +1,80 @@
-use crate::cell_space_partition::CellSpacePartition;
-use crate::configuration::CONFIG;
-use crate::entity_functions::TagNeighbors;
-use crate::entity_traits::{EntityBase, EntityMovable};
+use crate::configuration::ConfigLoader;
+use crate::entity_functions::Overlapped;
+use crate::entity_traits::{reserve_ids_through, EntityBase, EntityMovable};
+use crate::metrics::SchoolMetrics;
+use crate::obstacle::Obstacle;
 use crate::path::Path;
+use crate::recorder::{Recorder, RecordingError};
+use crate::snapshot::{SnapshotError, WorldSnapshot};
+use crate::spatial_index::{CreateSpatialIndex, SpatialIndex};
+use crate::steering_behavior::{NeighborhoodMode, SteeringBehavior, SteeringContext, SummingMethod};
 use crate::utils::*;
 use crate::vehicle::Vehicle;
 use crate::wall_2d::Wall2D;
 use glam::{vec2, Vec2};
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+#[cfg(feature = "render")]
 use small_gl_core::model::Model;
+#[cfg(feature = "render")]
 use small_gl_core::shader::Shader;
-use std::cell::RefCell;
 use std::f32::consts::TAU;
-use std::rc::Rc;
+
+// fraction of a fixed step that frame time may fall short by and still run it
+const STEP_TOLERANCE: f32 = 0.001;
+
+// how far the tank walls are in from the edges of the world
+const TANK_BORDER_SIZE: f32 = 20.0;
+
+// the fraction of each side of the tank its cut off corners take up
+const TANK_CORNER_SIZE: f32 = 0.2;
+
+// how close obstacles come to the edges of the world
+pub const OBSTACLE_BORDER: f32 = 10.0;
+
+// the shapes a group of followers can take up behind a leader
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Formation {
+    // followers alternate left and right of the leader in widening ranks
+    V,
+    // followers swim in single file behind the leader, like a convoy
+    Line,
+    // followers fill rows behind the leader, as square as possible
+    Grid,
+}
 
 // #[derive(Debug)]
 pub struct GameWorld {
-    //a container of all the moving entities
-    pub m_Vehicles: Vec<Rc<RefCell<Vehicle>>>,
+    //the parameters the world is running with
+    m_Config: ConfigLoader,
+
+    //a reloaded config waiting to be applied on the next update
+    m_PendingConfig: Option<ConfigLoader>,
+
+    //the seed the world's rng started from
+    m_Seed: u64,
+
+    //every random choice in the world is drawn from here, directly or through
+    //the streams it seeds for each vehicle
+    pub m_Rng: SimRng,
+
+    //a container of all the moving entities. A vehicle is referred to by its
+    //index here, which stays the same for the life of the world
+    pub m_Vehicles: Vec<Vehicle>,
+
+    //the steering of each vehicle, at the same index as the vehicle
+    pub m_Steering: Vec<SteeringBehavior>,
 
     //any obstacles
-    // m_Obstacles: RefCell<Vec<BaseEntity>>,
-    m_Obstacles: RefCell<Vec<Rc<RefCell<dyn EntityMovable>>>>,
+    pub m_Obstacles: Vec<Obstacle>,
 
     //container containing any walls in the environment
-    m_Walls: Vec<Wall2D>,
+    pub m_Walls: Vec<Wall2D>,
 
     m_bCellSpaceOn: bool,
-    pub m_pCellSpace: RefCell<CellSpacePartition>,
+    //the spatial index the neighbors are looked up in, the cell space
+    //partition unless the config picks another
+    pub m_pCellSpace: Box<dyn SpatialIndex<Vehicle>>,
 
     //any path we may create for the vehicles to follow
     m_pPath: Option<Path>,
@@ -44,6 +92,12 @@ pub struct GameWorld {
     //keeps track of the average FPS
     m_dAvFrameTime: f32,
 
+    //frame time not yet simulated, less than one fixed step after an update
+    m_dAccumulator: f32,
+
+    //writes out the vehicles after every step while a recording is running
+    m_pRecorder: Option<Recorder>,
+
     //flags to turn aids and obstacles etc on/off
     m_bShowWalls: bool,
     m_bShowObstacles: bool,
@@ -59,23 +113,124 @@ pub struct GameWorld {
 }
 
 impl GameWorld {
-    pub fn new(cx: i32, cy: i32, model: Model) -> Rc<RefCell<GameWorld>> {
+    pub fn new(cx: i32, cy: i32) -> GameWorld {
+        GameWorld::with_config(cx, cy, ConfigLoader::default())
+    }
+
+    pub fn with_config(cx: i32, cy: i32, config: ConfigLoader) -> GameWorld {
+        // without a seed pick one, it can still be read back with Seed() to replay the run
+        let seed = config.Seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let mut rng = SimRng::seed_from_u64(seed);
+
         let border = 30f32;
-        let path = Path::new(5, border, border, cx as f32 - border, cy as f32 - border, true);
-        let cell_space = CellSpacePartition::new(cx as f32, cy as f32, CONFIG.NumCellsX, CONFIG.NumCellsY, CONFIG.NumAgents);
+        let path = Path::new(&mut rng, 5, border, border, cx as f32 - border, cy as f32 - border, true);
+
+        let mut game_world = GameWorld::Empty(cx, cy, config, seed, rng, Some(path));
+
+        // setup the agents
+        for _i in 0..config.NumAgents {
+            let rng = &mut game_world.m_Rng;
+
+            //determine a random starting position. In a tank it is inside
+            //the walls, in the largest box that misses the cut off corners
+            let spread = if config.TankWalls {
+                (vec2(cx as f32, cy as f32) / 2.0 - TANK_BORDER_SIZE) * (1.0 - TANK_CORNER_SIZE)
+            } else {
+                vec2(cx as f32, cy as f32) / 2.0
+            };
+            let spawn_pos = vec2(
+                cx as f32 / 2.0 + RandomClamped(rng) * spread.x,
+                cy as f32 / 2.0 + RandomClamped(rng) * spread.y,
+            );
+            let rotation = RandFloat(rng) * TAU;
+
+            let mut steering = SteeringBehavior::new(&config, rng);
+            steering.FlockingOn();
+            steering.ObstacleAvoidanceOn();
+
+            let vehicle = Vehicle::new(
+                &config,
+                spawn_pos,
+                rotation,
+                vec2(0.0, 0.0),
+                config.VehicleMass,
+                config.MaxSteeringForce,
+                config.MaxSpeed,
+                config.MaxTurnRatePerSecond,
+                config.Scale,
+                rng,
+            );
+
+            game_world.AddVehicle(vehicle, steering);
+        }
+
+        game_world.ToggleSpacePartition();
+
+        // The "shark". A config built in code rather than loaded may not
+        // have any agents at all.
+        if !game_world.m_Vehicles.is_empty() {
+            let idx = 0usize;
+
+            game_world.m_Steering[idx].m_vWanderTarget = vec2(0.0, 0.0);
+            game_world.m_Steering[idx].FlockingOff();
+            game_world.m_Steering[idx].WanderOn();
+
+            //game_world.m_Vehicles[idx].set_scale_vec(vec2(10.0, 12.0));
+            game_world.m_Vehicles[idx].set_scale_vec(vec2(100.0, 100.0));
+            game_world.m_Vehicles[idx].set_max_speed(70.0);
+
+            for (i, steering) in game_world.m_Steering.iter_mut().enumerate() {
+                if i != idx {
+                    steering.EvadeOn(idx);
+                }
+            }
+        }
+
+        //create any obstacles or walls
+        game_world.CreateObstacles();
 
-        let game_world = GameWorld {
+        if config.TankWalls {
+            game_world.SetTankWalls(true);
+        }
+
+        game_world
+    }
+
+    // adds a vehicle steered by steering to the world and returns the index
+    // it is referred to by
+    pub fn AddVehicle(&mut self, vehicle: Vehicle, steering: SteeringBehavior) -> usize {
+        let index = self.m_Vehicles.len();
+
+        self.m_pCellSpace.add_entity(index, vehicle.position());
+        self.m_Vehicles.push(vehicle);
+        self.m_Steering.push(steering);
+
+        index
+    }
+
+    // a world with no vehicles or obstacles yet
+    fn Empty(cx: i32, cy: i32, config: ConfigLoader, seed: u64, rng: SimRng, path: Option<Path>) -> GameWorld {
+        let cell_space = CreateSpatialIndex(&config, cx as f32, cy as f32);
+
+        GameWorld {
+            m_Config: config,
+            m_PendingConfig: None,
+            m_Seed: seed,
+            m_Rng: rng,
             m_Vehicles: vec![],
-            m_Obstacles: RefCell::new(vec![]),
+            m_Steering: vec![],
+            m_Obstacles: vec![],
             m_Walls: vec![],
-            m_pCellSpace: cell_space.into(),
+            m_pCellSpace: cell_space,
             m_bCellSpaceOn: false,
-            m_pPath: Some(path),
+            m_pPath: path,
             m_bPaused: false,
             m_cxClient: cx,
             m_cyClient: cy,
             m_vCrosshair: vec2(cx as f32 / 2.0, cy as f32 / 2.0),
             m_dAvFrameTime: 0.0,
+            m_dAccumulator: 0.0,
+            m_pRecorder: None,
             m_bShowWalls: false,
             m_bShowObstacles: false,
             m_bShowPath: false,
@@ -87,66 +242,218 @@ impl GameWorld {
             m_bRenderNeighbors: false,
             m_bViewKeys: false,
             m_bShowCellSpaceInfo: false,
+        }
+    }
+
+    //------------------------------ Snapshot --------------------------------
+    //
+    //  copies out everything needed to carry on this run later
+    //------------------------------------------------------------------------
+    pub fn Snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            cx: self.m_cxClient,
+            cy: self.m_cyClient,
+            config: self.m_Config,
+            pending_config: self.m_PendingConfig,
+            seed: self.m_Seed,
+            rng: self.m_Rng.clone(),
+            crosshair: self.m_vCrosshair,
+            cell_space_on: self.m_bCellSpaceOn,
+            accumulator: self.m_dAccumulator,
+            path: self.m_pPath.clone(),
+            obstacles: self.m_Obstacles.clone(),
+            vehicles: self
+                .m_Vehicles
+                .iter()
+                .zip(&self.m_Steering)
+                .map(|(vehicle, steering)| vehicle.Snapshot(steering))
+                .collect(),
+            cells: self.m_pCellSpace.Cells(),
+        }
+    }
+
+    //---------------------------- FromSnapshot ------------------------------
+    //
+    //  rebuilds a world from a snapshot. Vehicles and obstacles keep the ids
+    //  they were saved with and the vehicles keep their indices, so the
+    //  steering targets refer to the same vehicles as before.
+    //------------------------------------------------------------------------
+    pub fn FromSnapshot(snapshot: &WorldSnapshot) -> Result<GameWorld, SnapshotError> {
+        let mut world = GameWorld::Empty(
+            snapshot.cx,
+            snapshot.cy,
+            snapshot.config,
+            snapshot.seed,
+            snapshot.rng.clone(),
+            snapshot.path.clone(),
+        );
+        world.m_PendingConfig = snapshot.pending_config;
+        world.m_vCrosshair = snapshot.crosshair;
+        world.m_bCellSpaceOn = snapshot.cell_space_on;
+        world.m_dAccumulator = snapshot.accumulator;
+        world.m_Obstacles = snapshot.obstacles.clone();
+
+        if snapshot.config.TankWalls {
+            world.CreateWalls();
+        }
+
+        let num_vehicles = snapshot.vehicles.len();
+        let check = |index: usize| -> Result<usize, SnapshotError> {
+            if index < num_vehicles {
+                Ok(index)
+            } else {
+                Err(SnapshotError::UnknownVehicle(index))
+            }
         };
 
-        let game_world = Rc::new(RefCell::new(game_world));
+        for vehicle_snapshot in &snapshot.vehicles {
+            let steering = &vehicle_snapshot.steering;
+            let (agent_a, agent_b) = steering.InterposeAgents().unzip();
+            for agent in [steering.Target(), steering.Leader(), agent_a, agent_b, steering.Hunter()] {
+                agent.map(check).transpose()?;
+            }
 
-        // setup the agents
-        for _i in 0..CONFIG.NumAgents {
-            //determine a random starting position
-            let spawn_pos = vec2(
-                cx as f32 / 2.0 + RandomClamped() * cx as f32 / 2.0,
-                cy as f32 / 2.0 + RandomClamped() * cy as f32 / 2.0,
-            );
+            world.m_Vehicles.push(Vehicle::FromSnapshot(vehicle_snapshot));
+            world.m_Steering.push(vehicle_snapshot.steering.clone());
+        }
 
-            // let sprite = model.clone();
-            // sprite.sprite_data.step_count = (i % 3) as f32;
+        for index in snapshot.cells.iter().flatten() {
+            check(*index)?;
+        }
 
-            let vehicle = Vehicle::new(
-                game_world.clone(),
-                spawn_pos,
-                RandFloat() * TAU,
-                vec2(0.0, 0.0),
-                CONFIG.VehicleMass,
-                CONFIG.MaxSteeringForce,
-                CONFIG.MaxSpeed,
-                CONFIG.MaxTurnRatePerSecond,
-                CONFIG.Scale,
-                model.clone(),
-            );
+        if !world.m_pCellSpace.RestoreCells(&snapshot.cells, &world.m_Vehicles) {
+            return Err(SnapshotError::CellCount(snapshot.cells.len()));
+        }
+
+        let ids = world.m_Vehicles.iter().map(|vehicle| vehicle.id());
+        let obstacle_ids = world.m_Obstacles.iter().map(|obstacle| obstacle.id());
+        if let Some(last_id) = ids.chain(obstacle_ids).max() {
+            reserve_ids_through(last_id);
+        }
+
+        Ok(world)
+    }
+
+    pub fn SaveSnapshot(&self, path: impl AsRef<std::path::Path>) -> Result<(), SnapshotError> {
+        self.Snapshot().save(path)
+    }
+
+    pub fn LoadSnapshot(path: impl AsRef<std::path::Path>) -> Result<GameWorld, SnapshotError> {
+        GameWorld::FromSnapshot(&WorldSnapshot::load(path)?)
+    }
+
+    //--------------------------- CreateObstacles -----------------------------
+    //
+    //  Sets up the vector of obstacles with random positions and sizes. Makes
+    //  sure the obstacles do not overlap
+    //------------------------------------------------------------------------
+    pub fn CreateObstacles(&mut self) {
+        const MIN_GAP_BETWEEN_OBSTACLES: f32 = 20.0;
+        const NUM_ALLOWABLE_TRYS: i32 = 2000;
+
+        //create a number of randomly sized tiddlywinks
+        for _o in 0..self.m_Config.NumObstacles {
+            //keep creating tiddlywinks until we find one that doesn't overlap
+            //any others. Sometimes this can get into an endless loop because the
+            //obstacle has nowhere to fit. We test for this case and exit accordingly
+            let mut num_trys = 0;
+
+            loop {
+                num_trys += 1;
+                if num_trys > NUM_ALLOWABLE_TRYS {
+                    return;
+                }
+
+                let rng = &mut self.m_Rng;
 
-            vehicle.borrow().m_pSteering.borrow_mut().FlockingOn();
-            // vehicle.borrow_mut().set_scale_vec(vec2(6.0, 6.0));
+                let radius = RandInRange(rng, self.m_Config.MinObstacleRadius, self.m_Config.MaxObstacleRadius);
 
-            game_world.borrow_mut().m_Vehicles.push(vehicle.clone());
-            game_world.borrow().m_pCellSpace.borrow_mut().add_entity(vehicle.clone());
+                // the config is checked against the window size, but a world
+                // made in code can still be too small for the obstacle
+                let room = 2.0 * (radius + OBSTACLE_BORDER);
+                if room > self.m_cxClient as f32 || room > self.m_cyClient as f32 {
+                    continue;
+                }
+
+                let position = vec2(
+                    RandInRange(rng, radius + OBSTACLE_BORDER, self.m_cxClient as f32 - radius - OBSTACLE_BORDER),
+                    RandInRange(rng, radius + OBSTACLE_BORDER, self.m_cyClient as f32 - radius - OBSTACLE_BORDER),
+                );
+
+                let obstacle = Obstacle::new(position, radius);
+
+                if !Overlapped(&obstacle, &self.m_Obstacles, MIN_GAP_BETWEEN_OBSTACLES) {
+                    //its not overlapped so we can add it
+                    self.m_Obstacles.push(obstacle);
+                    break;
+                }
+            }
         }
+    }
 
-        game_world.borrow_mut().ToggleSpacePartition();
+    //--------------------------- CreateWalls --------------------------------
+    //
+    //  creates some walls that form an enclosure for the steering agents.
+    //  used to demonstrate several of the steering behaviors. They run
+    //  clockwise round the screen so their normals face into the tank.
+    //------------------------------------------------------------------------
+    pub fn CreateWalls(&mut self) {
+        //create the walls
+        let border_size = TANK_BORDER_SIZE;
+        let corner_size = TANK_CORNER_SIZE;
+        let cx = self.m_cxClient as f32;
+        let cy = self.m_cyClient as f32;
+        let v_dist = cy - 2.0 * border_size;
+        let h_dist = cx - 2.0 * border_size;
 
-        // The "shark"
+        let walls = [
+            vec2(h_dist * corner_size + border_size, border_size),
+            vec2(cx - border_size - h_dist * corner_size, border_size),
+            vec2(cx - border_size, border_size + v_dist * corner_size),
+            vec2(cx - border_size, cy - border_size - v_dist * corner_size),
+            vec2(cx - border_size - h_dist * corner_size, cy - border_size),
+            vec2(h_dist * corner_size + border_size, cy - border_size),
+            vec2(border_size, cy - border_size - v_dist * corner_size),
+            vec2(border_size, border_size + v_dist * corner_size),
+        ];
 
-        let idx = 0usize;
+        self.m_Walls.clear();
 
-        game_world.borrow().m_Vehicles[idx].borrow().m_pSteering.borrow_mut().FlockingOff();
-        game_world.borrow().m_Vehicles[idx].borrow().m_pSteering.borrow_mut().WanderOn();
+        for w in 0..walls.len() {
+            self.m_Walls.push(Wall2D::new(walls[w], walls[(w + 1) % walls.len()]));
+        }
+    }
+
+    // puts the agents in a tank. The walls are created and every vehicle
+    // switches on wall avoidance, or the walls are removed again.
+    pub fn SetTankWalls(&mut self, on: bool) {
+        self.m_Config.TankWalls = on;
 
-        //game_world.borrow().m_Vehicles[idx].borrow_mut().set_scale_vec(vec2(10.0, 12.0));
-        game_world.borrow().m_Vehicles[idx].borrow_mut().set_scale_vec(vec2(100.0, 100.0));
-        game_world.borrow().m_Vehicles[idx].borrow_mut().set_max_speed(70.0);
+        if on {
+            self.CreateWalls();
+        } else {
+            self.m_Walls.clear();
+        }
 
-        for (i, vehicle) in game_world.borrow().m_Vehicles.iter().enumerate() {
-            if i != idx {
-                let target = game_world.borrow().m_Vehicles[idx].clone();
-                vehicle.borrow().m_pSteering.borrow_mut().EvadeOn(target);
+        for steering in &mut self.m_Steering {
+            if on {
+                steering.WallAvoidanceOn();
+            } else {
+                steering.WallAvoidanceOff();
             }
         }
+    }
 
-        //create any obstacles or walls
-        //CreateObstacles();
-        //CreateWalls();
+    pub fn Crosshair(&self) -> Vec2 {
+        self.m_vCrosshair
+    }
 
-        game_world
+    pub fn SetCrosshair(&mut self, position: Vec2) {
+        self.m_vCrosshair = position;
+    }
+
+    pub fn Config(&self) -> &ConfigLoader {
+        &self.m_Config
     }
 
     pub fn cxClient(&self) -> i32 {
@@ -157,51 +464,302 @@ impl GameWorld {
         self.m_cyClient
     }
 
-    pub fn Update(game_world: &Rc<RefCell<GameWorld>>, time_elapsed: f32) {
+    //--------------------------------- Update ---------------------------------
+    //
+    //  advances the world by a frame's worth of time in whole fixed steps of
+    //  FixedTimeStep. What is left over carries into the next frame and is
+    //  what Interpolation() reports for rendering.
+    //------------------------------------------------------------------------
+    pub fn Update(&mut self, time_elapsed: f32) {
         //  if (m_bPaused) return;
 
         //create a smoother to smooth the framerate
         // let SampleRate = 10;
         //static Smoother<float> FrameRateSmoother(SampleRate, 0.0);
 
-        game_world.borrow_mut().m_dAvFrameTime = time_elapsed; // FrameRateSmoother.Update(time_elapsed);
+        self.m_dAvFrameTime = time_elapsed; // FrameRateSmoother.Update(time_elapsed);
+        self.m_dAccumulator += time_elapsed;
+
+        let mut steps = 0;
+
+        loop {
+            let step = self.m_Config.FixedTimeStep;
+
+            // frame times that add up to a whole step should run it even
+            // when rounding leaves the sum a hair short
+            if self.m_dAccumulator < step * (1.0 - STEP_TOLERANCE) {
+                break;
+            }
 
-        for vehicle in &game_world.borrow().m_Vehicles {
-            let old_position = Vehicle::Update(vehicle, time_elapsed);
-            if game_world.borrow().m_bCellSpaceOn {
-                game_world
-                    .borrow()
-                    .m_pCellSpace
-                    .borrow_mut()
-                    .UpdateEntity(vehicle.clone() as Rc<RefCell<dyn EntityMovable>>, &old_position);
+            if steps == self.m_Config.MaxSubSteps {
+                // too far behind to catch up, drop the rest of the frame
+                self.m_dAccumulator %= step;
+                break;
             }
+
+            self.Step(step);
+            self.m_dAccumulator -= step;
+            steps += 1;
+        }
+    }
+
+    // the walls of the tank the vehicles are kept inside, None when they
+    // wrap around the edges of the world
+    pub fn Tank(&self) -> Option<&[Wall2D]> {
+        self.m_Config.TankWalls.then_some(self.m_Walls.as_slice())
+    }
+
+    //---------------------------------- Step --------------------------------
+    //
+    //  moves every vehicle on by one step of time_elapsed seconds in two
+    //  phases. First the steering forces of all the vehicles are calculated
+    //  in parallel from the world as it was at the start of the step, then
+    //  the vehicles are moved by them in order. No vehicle sees another one
+    //  part way through the step, so the result is the same whatever the
+    //  number of threads.
+    //------------------------------------------------------------------------
+    pub fn Step(&mut self, time_elapsed: f32) {
+        if let Some(config) = self.m_PendingConfig.take() {
+            self.ApplyConfig(config);
+        }
+
+        // an index that is rebuilt rather than updated catches up with the
+        // vehicles added or moved since the last step
+        self.m_pCellSpace.Refresh();
+
+        let context = SteeringContext {
+            vehicles: &self.m_Vehicles,
+            obstacles: &self.m_Obstacles,
+            walls: &self.m_Walls,
+            cell_space: self.m_pCellSpace.as_ref(),
+            crosshair: self.m_vCrosshair,
+            config: &self.m_Config,
+            time_elapsed,
+        };
+
+        let steering_forces: Vec<Vec2> = self
+            .m_Steering
+            .par_iter_mut()
+            .enumerate()
+            .map(|(index, steering)| steering.Calculate(index, &context))
+            .collect();
+
+        let tank = self.m_Config.TankWalls.then_some(self.m_Walls.as_slice());
+
+        for (index, steering_force) in steering_forces.into_iter().enumerate() {
+            let vehicle = &mut self.m_Vehicles[index];
+            let old_position = vehicle.Update(steering_force, time_elapsed, self.m_cxClient, self.m_cyClient, tank);
+            if self.m_bCellSpaceOn {
+                self.m_pCellSpace.UpdateEntity(index, &old_position, &vehicle.position());
+            }
+        }
+
+        if let Some(recorder) = self.m_pRecorder.as_mut() {
+            recorder.Record(time_elapsed, &self.m_Vehicles);
+        }
+    }
+
+    //---------------------------- StartRecording ----------------------------
+    //
+    //  records every step from now on to a file, replacing any recording
+    //  already running
+    //------------------------------------------------------------------------
+    pub fn StartRecording(&mut self, path: impl AsRef<std::path::Path>) -> Result<(), RecordingError> {
+        self.StopRecording()?;
+
+        let header = Recorder::header(self.m_cxClient, self.m_cyClient, &self.m_Vehicles);
+        self.m_pRecorder = Some(Recorder::create(path, &header)?);
+
+        Ok(())
+    }
+
+    // finishes the recording, returning how many frames it holds or None
+    // if nothing was being recorded
+    pub fn StopRecording(&mut self) -> Result<Option<usize>, RecordingError> {
+        self.m_pRecorder.take().map(Recorder::Finish).transpose()
+    }
+
+    pub fn IsRecording(&self) -> bool {
+        self.m_pRecorder.is_some()
+    }
+
+    //------------------------------ ReloadConfig ----------------------------
+    //
+    //  queues new parameters to be applied to every vehicle at the start of
+    //  the next Update. Keys that can only be set when the world is created
+    //  are kept at their running values and returned so the caller can
+    //  report them.
+    //------------------------------------------------------------------------
+    pub fn ReloadConfig(&mut self, config: ConfigLoader) -> Vec<&'static str> {
+        let ignored = config.non_live_changes(&self.m_Config);
+
+        self.m_PendingConfig = Some(config.with_non_live_from(&self.m_Config));
+
+        ignored
+    }
+
+    fn ApplyConfig(&mut self, config: ConfigLoader) {
+        let old = self.m_Config;
+
+        // only touch the vehicle properties that changed so vehicles set up
+        // differently, like the shark, keep their own values otherwise
+        for (vehicle, steering) in self.m_Vehicles.iter_mut().zip(&mut self.m_Steering) {
+            if config.MaxSpeed != old.MaxSpeed {
+                vehicle.max_speed = config.MaxSpeed;
+            }
+            if config.MaxSteeringForce != old.MaxSteeringForce {
+                vehicle.max_force = config.MaxSteeringForce;
+            }
+            if config.VehicleMass != old.VehicleMass {
+                vehicle.mass = config.VehicleMass;
+            }
+            if config.MaxTurnRatePerSecond != old.MaxTurnRatePerSecond {
+                vehicle.max_turn_rate = config.MaxTurnRatePerSecond;
+            }
+            if config.Scale != old.Scale {
+                vehicle.set_scale_float(config.Scale);
+            }
+            if config.Neighborhood != old.Neighborhood {
+                steering.SetNeighborhood(config.Neighborhood);
+            }
+            if config.NumNearestNeighbors != old.NumNearestNeighbors {
+                steering.SetNumNearestNeighbors(config.NumNearestNeighbors as usize);
+            }
+
+            steering.ApplyConfig(&config);
+        }
+
+        self.m_Config = config;
+
+        if config.TankWalls != old.TankWalls {
+            self.SetTankWalls(config.TankWalls);
+        }
+        if config.ToroidalNeighbors != old.ToroidalNeighbors {
+            self.m_pCellSpace.SetToroidal(config.ToroidalNeighbors);
         }
     }
 
     pub fn ToggleSpacePartition(&mut self) {
         self.m_bCellSpaceOn = !self.m_bCellSpaceOn;
 
-        for vehicle in &self.m_Vehicles {
-            vehicle.borrow().m_pSteering.borrow_mut().m_bCellSpaceOn = self.m_bCellSpaceOn;
+        for steering in &mut self.m_Steering {
+            steering.m_bCellSpaceOn = self.m_bCellSpaceOn;
         }
 
         if self.m_bCellSpaceOn {
-            self.m_pCellSpace.borrow_mut().EmptyCells();
+            self.m_pCellSpace.EmptyCells();
 
-            for vehicle in &self.m_Vehicles {
-                self.m_pCellSpace.borrow_mut().add_entity(vehicle.clone());
+            for (index, vehicle) in self.m_Vehicles.iter().enumerate() {
+                self.m_pCellSpace.add_entity(index, vehicle.position());
             }
         } else {
             self.m_bShowCellSpaceInfo = false;
         }
     }
 
-    pub fn TagVehiclesWithinViewRange(&self, pVehicle: Rc<RefCell<Vehicle>>, range: f32) {
-        let dyn_vehicle: Rc<RefCell<dyn EntityMovable>> = pVehicle as Rc<RefCell<dyn EntityMovable>>;
-        TagNeighbors(dyn_vehicle, &self.m_Obstacles, range);
+    //------------------------------ FormationOffsets ------------------------
+    //
+    //  returns the offsets, in the leader's local space, for the given number
+    //  of followers. The leader faces along +x so followers get negative x.
+    //------------------------------------------------------------------------
+    pub fn FormationOffsets(formation: Formation, num_followers: usize, spacing: f32) -> Vec<Vec2> {
+        let mut offsets = Vec::with_capacity(num_followers);
+
+        match formation {
+            Formation::V => {
+                for i in 0..num_followers {
+                    let rank = (i / 2 + 1) as f32;
+                    let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    offsets.push(vec2(-rank * spacing, side * rank * spacing));
+                }
+            }
+            Formation::Line => {
+                for i in 0..num_followers {
+                    offsets.push(vec2(-((i + 1) as f32) * spacing, 0.0));
+                }
+            }
+            Formation::Grid => {
+                let columns = (num_followers as f32).sqrt().ceil().max(1.0) as usize;
+                let half_width = (columns - 1) as f32 / 2.0;
+
+                for i in 0..num_followers {
+                    let row = (i / columns + 1) as f32;
+                    let column = (i % columns) as f32;
+                    offsets.push(vec2(-row * spacing, (column - half_width) * spacing));
+                }
+            }
+        }
+
+        offsets
+    }
+
+    // has the followers take up the formation behind the leader using offset
+    // pursuit. Offset pursuit comes after flocking in priority, so the
+    // followers stop flocking apart from keeping separated, or it would get
+    // none of their steering force. They still evade the shark and avoid
+    // obstacles and walls.
+    pub fn SetFormation(&mut self, leader: usize, followers: &[usize], formation: Formation, spacing: f32) {
+        let offsets = GameWorld::FormationOffsets(formation, followers.len(), spacing);
+
+        for (&follower, offset) in followers.iter().zip(offsets) {
+            let steering = &mut self.m_Steering[follower];
+            steering.FlockingOff();
+            steering.SeparationOn();
+            steering.OffsetPursuitOn(leader, offset);
+        }
+    }
+
+    // switches every vehicle in the world over to the given summing method
+    pub fn SetSummingMethod(&mut self, method: SummingMethod) {
+        for steering in &mut self.m_Steering {
+            steering.SetSummingMethod(method);
+        }
+    }
+
+    // switches every vehicle in the world over to the given neighborhood
+    pub fn SetNeighborhood(&mut self, mode: NeighborhoodMode) {
+        for steering in &mut self.m_Steering {
+            steering.SetNeighborhood(mode);
+        }
+    }
+
+    // the school statistics for the vehicles as they are now, with fish
+    // closer than ViewDistance counted in the same subgroup. Distances only
+    // wrap around when the fish are not in a tank.
+    pub fn Metrics(&self) -> SchoolMetrics {
+        let positions: Vec<Vec2> = self.m_Vehicles.iter().map(|vehicle| vehicle.position()).collect();
+        let headings: Vec<Vec2> = self.m_Vehicles.iter().map(|vehicle| vehicle.heading()).collect();
+        let toroidal = !self.m_Config.TankWalls;
+
+        SchoolMetrics::Compute(
+            &positions,
+            &headings,
+            self.m_Config.ViewDistance,
+            self.m_cxClient,
+            self.m_cyClient,
+            toroidal,
+        )
+    }
+
+    // how far the world is between its last two steps, from 0 to 1
+    pub fn Interpolation(&self) -> f32 {
+        (self.m_dAccumulator / self.m_Config.FixedTimeStep).clamp(0.0, 1.0)
+    }
+
+    pub fn Seed(&self) -> u64 {
+        self.m_Seed
+    }
+
+    // reseeds the steering rng of every vehicle, which drives wander and
+    // dithering. Each vehicle gets its own stream derived from the seed.
+    pub fn ReseedSteering(&mut self, seed: u64) {
+        for (i, steering) in self.m_Steering.iter_mut().enumerate() {
+            steering.SetRng(SimRng::seed_from_u64(seed.wrapping_add(i as u64)));
+        }
     }
 
-    pub fn render(&self, shader: &Shader) {
+    #[cfg(feature = "render")]
+    pub fn render(&self, shader: &Shader, model: &Model) {
         for wall in &self.m_Walls {
             wall.Render(true);
         }
@@ -210,10 +768,12 @@ impl GameWorld {
         // gdi->Circle(m_Obstacles[ob]->Pos(), m_Obstacles[ob]->BRadius());
         // }
 
+        let alpha = self.Interpolation();
+
         let mut first = true;
         //render the agents
         for vehicle in &self.m_Vehicles {
-            vehicle.borrow_mut().render(shader);
+            vehicle.render(shader, model, alpha, self.m_cxClient, self.m_cyClient);
 
             //render cell partitioning stuff
             if self.m_bShowCellSpaceInfo && first {
@@ -247,7 +807,471 @@ impl GameWorld {
         // }
 
         if self.m_bShowCellSpaceInfo {
-            self.m_pCellSpace.borrow().render_cells();
+            self.m_pCellSpace.render_cells();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::ConfigLoader;
+    use crate::entity_traits::{EntityBase, EntityMovable};
+    use crate::game_world::{Formation, GameWorld};
+    use crate::snapshot::WorldSnapshot;
+    use crate::spatial_index::SpatialIndexType;
+    use crate::steering_behavior::{NeighborhoodMode, SteeringContext, SummingMethod};
+    use crate::transformations::PointToWorldSpace;
+    use glam::{vec2, Vec2};
+
+    #[test]
+    pub fn test_headless_update() {
+        let mut game_world = GameWorld::new(1000, 800);
+        let start: Vec<_> = game_world.m_Vehicles.iter().map(|v| v.position()).collect();
+
+        for _ in 0..1000 {
+            game_world.Update(1.0 / 60.0);
+        }
+
+        for (vehicle, start) in game_world.m_Vehicles.iter().zip(start) {
+            let position = vehicle.position();
+
+            assert!(position.is_finite() && vehicle.velocity().is_finite());
+            assert!((0.0..=1000.0).contains(&position.x) && (0.0..=800.0).contains(&position.y));
+            assert_ne!(position, start);
+        }
+    }
+
+    #[test]
+    pub fn test_seeded_worlds_match() {
+        let config = ConfigLoader {
+            NumAgents: 60,
+            Seed: Some(7),
+            ..ConfigLoader::default()
+        };
+
+        let mut world_a = GameWorld::with_config(1000, 800, config);
+        let mut world_b = GameWorld::with_config(1000, 800, config);
+
+        for _ in 0..300 {
+            world_a.Update(1.0 / 60.0);
+            world_b.Update(1.0 / 60.0);
+        }
+
+        for (a, b) in world_a.m_Vehicles.iter().zip(&world_b.m_Vehicles) {
+            assert_eq!(a.position().to_array().map(f32::to_bits), b.position().to_array().map(f32::to_bits));
+            assert_eq!(a.velocity().to_array().map(f32::to_bits), b.velocity().to_array().map(f32::to_bits));
+        }
+    }
+
+    #[test]
+    pub fn test_steering_ignores_thread_count() {
+        let config = ConfigLoader {
+            NumAgents: 200,
+            NumObstacles: 5,
+            Seed: Some(13),
+            ..ConfigLoader::default()
+        };
+
+        let run = |threads: usize, cell_space: bool| {
+            let mut world = GameWorld::with_config(1000, 800, config);
+            if !cell_space {
+                world.ToggleSpacePartition();
+            }
+            world.SetSummingMethod(SummingMethod::dithered);
+
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| {
+                for _ in 0..200 {
+                    world.Step(1.0 / 60.0);
+                }
+            });
+
+            world
+                .m_Vehicles
+                .iter()
+                .map(|vehicle| {
+                    (
+                        vehicle.position().to_array().map(f32::to_bits),
+                        vehicle.velocity().to_array().map(f32::to_bits),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for cell_space in [true, false] {
+            let single = run(1, cell_space);
+            assert_eq!(single, run(2, cell_space));
+            assert_eq!(single, run(8, cell_space));
+        }
+    }
+
+    // the brute force flocking behaviors, that look at every vehicle, steer
+    // the same as the ones working from the cell space's neighbors. Each
+    // vehicle's steering is calculated both ways from the same state of a
+    // running school. Only the order the neighbors are added up in differs,
+    // so the forces agree to well within a percent of the max force, where
+    // a neighbor missed or added would be far out.
+    #[test]
+    pub fn test_cell_space_matches_brute_force() {
+        let config = ConfigLoader {
+            NumAgents: 200,
+            Seed: Some(8),
+            ..ConfigLoader::default()
+        };
+
+        let mut world = GameWorld::with_config(1000, 800, config);
+        assert!(world.m_bCellSpaceOn);
+
+        for step in 0..300 {
+            world.Step(1.0 / 60.0);
+            if step % 10 != 0 {
+                continue;
+            }
+
+            let context = SteeringContext {
+                vehicles: &world.m_Vehicles,
+                obstacles: &world.m_Obstacles,
+                walls: &world.m_Walls,
+                cell_space: world.m_pCellSpace.as_ref(),
+                crosshair: world.m_vCrosshair,
+                config: &world.m_Config,
+                time_elapsed: 1.0 / 60.0,
+            };
+
+            for (index, steering) in world.m_Steering.iter().enumerate() {
+                let mut partitioned = steering.clone();
+                let mut brute_force = steering.clone();
+                brute_force.m_bCellSpaceOn = false;
+
+                let a = partitioned.Calculate(index, &context);
+                let b = brute_force.Calculate(index, &context);
+                assert!(
+                    (a - b).length() < config.MaxSteeringForce / 100.0,
+                    "step {step} vehicle {index}: {a} {b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_fixed_step_ignores_frame_rate() {
+        let config = ConfigLoader {
+            NumAgents: 40,
+            Seed: Some(3),
+            ..ConfigLoader::default()
+        };
+
+        let mut fast = GameWorld::with_config(1000, 800, config);
+        let mut slow = GameWorld::with_config(1000, 800, config);
+
+        for _ in 0..120 {
+            fast.Update(1.0 / 60.0);
+        }
+        for _ in 0..40 {
+            slow.Update(1.0 / 20.0);
+        }
+
+        for (a, b) in fast.m_Vehicles.iter().zip(&slow.m_Vehicles) {
+            assert_eq!(a.position(), b.position());
+        }
+
+        // a long hitch runs at most MaxSubSteps steps and leaves less than a step behind
+        slow.Update(5.0);
+        assert!(slow.Interpolation() < 1.0);
+    }
+
+    #[test]
+    pub fn test_snapshot_restore_continues_run() {
+        let config = ConfigLoader {
+            NumAgents: 150,
+            NumObstacles: 4,
+            Seed: Some(11),
+            ..ConfigLoader::default()
+        };
+
+        let mut world = GameWorld::with_config(1000, 800, config);
+        world.m_Steering[3].SetSummingMethod(SummingMethod::dithered);
+        for _ in 0..300 {
+            world.Step(1.0 / 60.0);
+        }
+
+        let bytes = world.Snapshot().to_bytes().unwrap();
+        let mut restored = GameWorld::FromSnapshot(&WorldSnapshot::from_bytes(&bytes).unwrap()).unwrap();
+
+        for _ in 0..100 {
+            world.Step(1.0 / 60.0);
+            restored.Step(1.0 / 60.0);
         }
+
+        for (a, b) in world.m_Vehicles.iter().zip(&restored.m_Vehicles) {
+            assert_eq!(a.id(), b.id());
+            assert_eq!(a.position(), b.position());
+            assert_eq!(a.SmoothedHeading(), b.SmoothedHeading());
+        }
+        assert_eq!(restored.m_Steering[3].SummingMethod(), SummingMethod::dithered);
+        assert_eq!(restored.m_Steering[5].Target(), Some(0));
+    }
+
+    // the k nearest are picked the same whether the cell space finds them or
+    // every vehicle is looked at, so the runs match, and they steer the
+    // school differently from the radius
+    #[test]
+    pub fn test_neighborhood_modes() {
+        let config = ConfigLoader {
+            NumAgents: 100,
+            NumObstacles: 3,
+            NumNearestNeighbors: 5,
+            Seed: Some(23),
+            ..ConfigLoader::default()
+        };
+
+        let run = |mode: NeighborhoodMode, cell_space: bool| {
+            let mut world = GameWorld::with_config(1000, 800, config);
+            world.SetNeighborhood(mode);
+            if !cell_space {
+                world.ToggleSpacePartition();
+            }
+            for _ in 0..150 {
+                world.Step(1.0 / 60.0);
+            }
+            world.m_Vehicles.iter().map(|vehicle| vehicle.position()).collect::<Vec<_>>()
+        };
+
+        for mode in [NeighborhoodMode::k_nearest, NeighborhoodMode::hybrid] {
+            assert_eq!(run(mode, true), run(mode, false), "{:?}", mode);
+        }
+        assert_ne!(run(NeighborhoodMode::metric, true), run(NeighborhoodMode::k_nearest, true));
+
+        let config = ConfigLoader::from_toml_str("Neighborhood = \"hybrid\"\nNumNearestNeighbors = 6\n").unwrap();
+        let world = GameWorld::with_config(1000, 800, ConfigLoader { NumAgents: 10, ..config });
+        assert_eq!(world.m_Steering[1].Neighborhood(), NeighborhoodMode::hybrid);
+        assert_eq!(world.m_Steering[1].NumNearestNeighbors(), 6);
+    }
+
+    // the indices without cells give their neighbors in index order, so
+    // swapping one for another doesn't change the run at all, and a world
+    // using one carries on the same from a snapshot
+    #[test]
+    pub fn test_spatial_indices_agree() {
+        let run = |index: SpatialIndexType, toroidal: bool| {
+            let config = ConfigLoader {
+                NumAgents: 120,
+                NumObstacles: 4,
+                Seed: Some(17),
+                SpatialIndex: index,
+                ToroidalNeighbors: toroidal,
+                ..ConfigLoader::default()
+            };
+
+            let mut world = GameWorld::with_config(1000, 800, config);
+            for _ in 0..150 {
+                world.Step(1.0 / 60.0);
+            }
+            world
+        };
+        let positions = |world: &GameWorld| world.m_Vehicles.iter().map(|vehicle| vehicle.position()).collect::<Vec<_>>();
+
+        for toroidal in [false, true] {
+            let brute_force = positions(&run(SpatialIndexType::brute_force, toroidal));
+            assert_eq!(brute_force, positions(&run(SpatialIndexType::spatial_hash, toroidal)));
+            assert_eq!(brute_force, positions(&run(SpatialIndexType::kd_tree, toroidal)));
+        }
+
+        let mut world = run(SpatialIndexType::kd_tree, false);
+        let mut restored = GameWorld::FromSnapshot(&world.Snapshot()).unwrap();
+        for _ in 0..50 {
+            world.Step(1.0 / 60.0);
+            restored.Step(1.0 / 60.0);
+        }
+        assert_eq!(positions(&world), positions(&restored));
+    }
+
+    #[test]
+    pub fn test_metrics_of_running_school() {
+        let config = ConfigLoader {
+            NumAgents: 50,
+            Seed: Some(2),
+            ..ConfigLoader::default()
+        };
+
+        let mut world = GameWorld::with_config(1000, 800, config);
+        for _ in 0..300 {
+            world.Step(1.0 / 60.0);
+        }
+
+        let metrics = world.Metrics();
+        assert!((0.0..=1.0).contains(&metrics.polarization));
+        assert!((0.0..=1.0).contains(&metrics.milling));
+        assert!(metrics.mean_nearest_neighbour > 0.0);
+        assert!((1..=50).contains(&metrics.subgroups));
+    }
+
+    #[test]
+    pub fn test_reload_config() {
+        let mut world = GameWorld::new(1000, 800);
+        let shark_speed = world.m_Vehicles[0].max_speed();
+
+        let mut config = *world.Config();
+        config.CohesionWeight = 0.0;
+        config.ViewDistance = 80.0;
+        config.MaxSteeringForce = 100.0;
+        config.NumCellsX = 12;
+
+        let ignored = world.ReloadConfig(config);
+        assert_eq!(ignored, vec!["NumCellsX"]);
+
+        world.Update(1.0 / 60.0);
+
+        assert_eq!(world.Config().ViewDistance, 80.0);
+        assert_eq!(world.Config().NumCellsX, 7);
+
+        for (vehicle, steering) in world.m_Vehicles.iter().zip(&world.m_Steering) {
+            assert_eq!(vehicle.max_force(), 100.0);
+            assert_eq!(steering.ViewDistance(), 80.0);
+        }
+
+        // unchanged keys leave vehicles with their own values alone
+        assert_eq!(world.m_Vehicles[0].max_speed(), shark_speed);
+    }
+
+    #[test]
+    pub fn test_reload_keeps_neighborhoods() {
+        let mut world = GameWorld::new(1000, 800);
+        world.m_Steering[3].SetNeighborhood(NeighborhoodMode::hybrid);
+        world.m_Steering[3].SetNumNearestNeighbors(3);
+
+        // a reload that leaves the neighborhood alone leaves the vehicle's own
+        let mut config = *world.Config();
+        config.CohesionWeight = 0.0;
+        world.ReloadConfig(config);
+        world.Update(1.0 / 60.0);
+
+        assert_eq!(world.m_Steering[3].Neighborhood(), NeighborhoodMode::hybrid);
+        assert_eq!(world.m_Steering[3].NumNearestNeighbors(), 3);
+
+        // one that changes it applies to every vehicle
+        config.Neighborhood = NeighborhoodMode::k_nearest;
+        world.ReloadConfig(config);
+        world.Update(1.0 / 60.0);
+
+        for steering in &world.m_Steering {
+            assert_eq!(steering.Neighborhood(), NeighborhoodMode::k_nearest);
+        }
+        assert_eq!(world.m_Steering[3].NumNearestNeighbors(), 3);
+        assert_eq!(world.m_Steering[4].NumNearestNeighbors(), config.NumNearestNeighbors as usize);
+    }
+
+    #[test]
+    pub fn test_tank_walls_contain_school() {
+        let config = ConfigLoader {
+            NumAgents: 60,
+            Seed: Some(4),
+            TankWalls: true,
+            ..ConfigLoader::default()
+        };
+
+        let mut world = GameWorld::with_config(1000, 800, config);
+        assert_eq!(world.Tank().unwrap().len(), 8);
+
+        // on or in front of every wall, the corners included, from the
+        // moment they are spawned
+        let inside = |world: &GameWorld| {
+            for vehicle in &world.m_Vehicles {
+                let position = vehicle.position();
+                for wall in world.Tank().unwrap() {
+                    assert!((position - wall.From()).dot(wall.Normal()) > -0.001, "{position}");
+                }
+            }
+        };
+
+        inside(&world);
+        for _ in 0..600 {
+            world.Step(1.0 / 60.0);
+            inside(&world);
+        }
+
+        world.SetTankWalls(false);
+        assert!(world.Tank().is_none());
+    }
+
+    #[test]
+    pub fn test_followers_keep_formation() {
+        let config = ConfigLoader {
+            NumAgents: 30,
+            Seed: Some(5),
+            ..ConfigLoader::default()
+        };
+
+        let mut world = GameWorld::with_config(1000, 800, config);
+        let (leader, followers) = (1, [2, 3, 4, 5]);
+
+        // the leader cruises slowly along x, the followers start scattered
+        // behind it
+        let mut place = |index: usize, position, velocity| {
+            let vehicle = &mut world.m_Vehicles[index];
+            world.m_pCellSpace.UpdateEntity(index, &vehicle.position(), &position);
+            vehicle.position = position;
+            vehicle.velocity = velocity;
+        };
+        place(leader, vec2(300.0, 400.0), vec2(20.0, 0.0));
+        for (i, &follower) in followers.iter().enumerate() {
+            place(follower, vec2(150.0, 250.0 + 100.0 * i as f32), Vec2::ZERO);
+        }
+        world.m_Steering[leader].FlockingOff();
+
+        world.SetFormation(leader, &followers, Formation::V, 30.0);
+
+        // offset pursuit doesn't take over the evade target
+        for &follower in &followers {
+            assert_eq!(world.m_Steering[follower].Leader(), Some(leader));
+            assert_eq!(world.m_Steering[follower].Target(), Some(0));
+        }
+
+        let distances = |world: &GameWorld| -> Vec<f32> {
+            let leader = &world.m_Vehicles[leader];
+            followers
+                .iter()
+                .map(|&follower| {
+                    let offset = world.m_Steering[follower].GetOffset();
+                    let place = PointToWorldSpace(offset, leader.heading(), leader.side(), leader.position());
+                    world.m_Vehicles[follower].position().distance(place)
+                })
+                .collect()
+        };
+
+        assert!(distances(&world).iter().all(|&distance| distance > 50.0));
+
+        for _ in 0..450 {
+            world.Step(1.0 / 60.0);
+        }
+
+        let places = distances(&world);
+        assert!(places.iter().all(|&distance| distance < 10.0), "{places:?}");
+
+        // then the shark swims into the formation and they break it to evade
+        let position = world.m_Vehicles[leader].position() - vec2(60.0, 0.0);
+        let shark = &mut world.m_Vehicles[0];
+        world.m_pCellSpace.UpdateEntity(0, &shark.position(), &position);
+        shark.position = position;
+        shark.velocity = vec2(70.0, 0.0);
+
+        for _ in 0..150 {
+            world.Step(1.0 / 60.0);
+        }
+
+        let places = distances(&world);
+        assert!(places.iter().any(|&distance| distance > 20.0), "{places:?}");
+    }
+
+    #[test]
+    pub fn test_formation_offsets() {
+        let v = GameWorld::FormationOffsets(Formation::V, 3, 10.0);
+        assert_eq!(v, vec![vec2(-10.0, 10.0), vec2(-10.0, -10.0), vec2(-20.0, 20.0)]);
+
+        let line = GameWorld::FormationOffsets(Formation::Line, 2, 10.0);
+        assert_eq!(line, vec![vec2(-10.0, 0.0), vec2(-20.0, 0.0)]);
+
+        let grid = GameWorld::FormationOffsets(Formation::Grid, 4, 10.0);
+        assert_eq!(grid, vec![vec2(-10.0, -5.0), vec2(-10.0, 5.0), vec2(-20.0, -5.0), vec2(-20.0, 5.0)]);
     }
 }
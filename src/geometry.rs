@@ -0,0 +1,64 @@
+//------------------------------------------------------------------------
+//
+//  Name:   geometry.h
+//
+//  Desc:   useful 2D geometry functions
+//
+//  Original Author: Mat Buckland (fup@ai-junkie.com)
+//
+//------------------------------------------------------------------------
+
+use glam::Vec2;
+
+//-------------------- LineIntersection2D-------------------------
+//
+//	Given 2 lines in 2D space AB, CD this returns the distance along AB
+//  to the point of intersection and the point itself, or None if the
+//  segments do not intersect.
+//-----------------------------------------------------------------
+pub fn LineIntersection2D(A: Vec2, B: Vec2, C: Vec2, D: Vec2) -> Option<(f32, Vec2)> {
+    let rTop = (A.y - C.y) * (D.x - C.x) - (A.x - C.x) * (D.y - C.y);
+    let rBot = (B.x - A.x) * (D.y - C.y) - (B.y - A.y) * (D.x - C.x);
+
+    let sTop = (A.y - C.y) * (B.x - A.x) - (A.x - C.x) * (B.y - A.y);
+    let sBot = (B.x - A.x) * (D.y - C.y) - (B.y - A.y) * (D.x - C.x);
+
+    if rBot == 0.0 || sBot == 0.0 {
+        //lines are parallel
+        return None;
+    }
+
+    let r = rTop / rBot;
+    let s = sTop / sBot;
+
+    if (r > 0.0) && (r < 1.0) && (s > 0.0) && (s < 1.0) {
+        let dist = A.distance(B) * r;
+        let point = A + r * (B - A);
+
+        return Some((dist, point));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::LineIntersection2D;
+    use glam::vec2;
+
+    #[test]
+    pub fn test_line_intersection() {
+        let hit = LineIntersection2D(vec2(0.0, 0.0), vec2(10.0, 0.0), vec2(4.0, -5.0), vec2(4.0, 5.0));
+        let (dist, point) = hit.unwrap();
+        assert_eq!(dist, 4.0);
+        assert_eq!(point, vec2(4.0, 0.0));
+
+        // segments that would only meet if extended
+        let miss = LineIntersection2D(vec2(0.0, 0.0), vec2(3.0, 0.0), vec2(4.0, -5.0), vec2(4.0, 5.0));
+        assert!(miss.is_none());
+
+        // parallel
+        let parallel = LineIntersection2D(vec2(0.0, 0.0), vec2(10.0, 0.0), vec2(0.0, 1.0), vec2(10.0, 1.0));
+        assert!(parallel.is_none());
+    }
+}
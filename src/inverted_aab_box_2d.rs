@@ -1,4 +1,5 @@
 use glam::Vec2;
+#[cfg(feature = "render")]
 use small_gl_core::gl;
 
 #[derive(Debug)]
@@ -41,6 +42,7 @@ impl InvertedAABBox2D {
         self.m_vBottomRight.x
     }
 
+    #[cfg(feature = "render")]
     pub fn render(&self) {
         let _box_verts = [
             self.left(),
@@ -0,0 +1,178 @@
+//------------------------------------------------------------------------
+//
+//  KdTree
+//
+//  a 2d tree over the entities' positions, kept as one array. The entity
+//  at the middle of a range splits it in two along x or y, alternating
+//  with depth: those before it are no further along that axis and those
+//  after it no nearer. Moving an entity only records its new position,
+//  the tree is built again by Refresh once they have all moved.
+//
+//------------------------------------------------------------------------
+
+use crate::entity_traits::EntityBase;
+use crate::spatial_index::{BoxSearch, SearchBox, SearchNearest, SearchRadius, SpatialIndex, WorldOffset};
+use glam::{vec2, Vec2};
+use std::f32::consts::PI;
+
+pub struct KdTree {
+    // the position of each entity by its index, None if it isn't held
+    m_Positions: Vec<Option<Vec2>>,
+
+    // the entities and their positions in tree order
+    m_Tree: Vec<(usize, Vec2)>,
+
+    // set when the positions have changed since the tree was built
+    m_bDirty: bool,
+
+    m_vWorld: Vec2,
+    m_bToroidal: bool,
+}
+
+impl KdTree {
+    pub fn new(width: f32, height: f32) -> KdTree {
+        KdTree {
+            m_Positions: vec![],
+            m_Tree: vec![],
+            m_bDirty: false,
+            m_vWorld: vec2(width, height),
+            m_bToroidal: false,
+        }
+    }
+
+    // orders nodes so the middle one splits them along axis, then does the
+    // same for each half along the other axis
+    fn Build(nodes: &mut [(usize, Vec2)], axis: usize) {
+        if nodes.len() <= 1 {
+            return;
+        }
+
+        let middle = nodes.len() / 2;
+        nodes.select_nth_unstable_by(middle, |a, b| a.1[axis].total_cmp(&b.1[axis]).then(a.0.cmp(&b.0)));
+
+        let (before, after) = nodes.split_at_mut(middle);
+        KdTree::Build(before, 1 - axis);
+        KdTree::Build(&mut after[1..], 1 - axis);
+    }
+
+    fn SearchNodes(nodes: &[(usize, Vec2)], axis: usize, top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        if nodes.is_empty() {
+            return;
+        }
+
+        let middle = nodes.len() / 2;
+        let (entity, position) = nodes[middle];
+
+        if position.cmpge(top_left).all() && position.cmple(bottom_right).all() {
+            found.push(entity);
+        }
+
+        // only look into the halves the box reaches
+        if top_left[axis] <= position[axis] {
+            KdTree::SearchNodes(&nodes[..middle], 1 - axis, top_left, bottom_right, found);
+        }
+        if bottom_right[axis] >= position[axis] {
+            KdTree::SearchNodes(&nodes[middle + 1..], 1 - axis, top_left, bottom_right, found);
+        }
+    }
+}
+
+impl BoxSearch for KdTree {
+    fn World(&self) -> Vec2 {
+        self.m_vWorld
+    }
+
+    fn Toroidal(&self) -> bool {
+        self.m_bToroidal
+    }
+
+    fn Search<T: EntityBase>(&self, _entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        assert!(!self.m_bDirty, "KdTree queried before Refresh");
+        KdTree::SearchNodes(&self.m_Tree, 0, top_left, bottom_right, found);
+    }
+
+    // the radius that would hold k entities if they were spread evenly
+    fn StartRadius(&self, k: usize) -> f32 {
+        if self.m_Tree.is_empty() {
+            return self.m_vWorld.length();
+        }
+
+        (self.m_vWorld.x * self.m_vWorld.y * k as f32 / (self.m_Tree.len() as f32 * PI)).sqrt()
+    }
+
+    fn NumEntities(&self) -> usize {
+        self.m_Tree.len()
+    }
+}
+
+impl<T: EntityBase> SpatialIndex<T> for KdTree {
+    fn add_entity(&mut self, entity: usize, position: Vec2) {
+        if entity >= self.m_Positions.len() {
+            self.m_Positions.resize(entity + 1, None);
+        }
+
+        self.m_Positions[entity] = Some(position);
+        self.m_bDirty = true;
+    }
+
+    fn UpdateEntity(&mut self, entity: usize, _old_position: &Vec2, new_position: &Vec2) {
+        if let Some(position) = self.m_Positions.get_mut(entity).and_then(Option::as_mut) {
+            *position = *new_position;
+            self.m_bDirty = true;
+        }
+    }
+
+    fn Refresh(&mut self) {
+        if !self.m_bDirty {
+            return;
+        }
+
+        self.m_Tree.clear();
+        self.m_Tree.extend(
+            self.m_Positions
+                .iter()
+                .enumerate()
+                .filter_map(|(entity, position)| position.map(|position| (entity, position))),
+        );
+        KdTree::Build(&mut self.m_Tree, 0);
+
+        self.m_bDirty = false;
+    }
+
+    fn EmptyCells(&mut self) {
+        self.m_Positions.clear();
+        self.m_Tree.clear();
+        self.m_bDirty = false;
+    }
+
+    fn SetToroidal(&mut self, toroidal: bool) {
+        self.m_bToroidal = toroidal;
+    }
+
+    fn IsToroidal(&self) -> bool {
+        self.m_bToroidal
+    }
+
+    fn Offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        WorldOffset(from, to, self.m_vWorld, self.m_bToroidal)
+    }
+
+    fn CalculateNeighbors(&self, entities: &[T], target_pos: Vec2, query_radius: f32, neighbors: &mut Vec<usize>) {
+        SearchRadius(self, entities, target_pos, query_radius, neighbors);
+    }
+
+    fn CalculateEntitiesInBox(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        SearchBox(self, entities, top_left, bottom_right, found);
+    }
+
+    fn CalculateNearestNeighbors(
+        &self,
+        entities: &[T],
+        target_pos: Vec2,
+        k: usize,
+        include: &dyn Fn(usize) -> bool,
+        neighbors: &mut Vec<usize>,
+    ) {
+        SearchNearest(self, entities, target_pos, k, include, neighbors);
+    }
+}
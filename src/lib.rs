@@ -0,0 +1,54 @@
+#![allow(dead_code)]
+#![allow(non_snake_case)]
+#![allow(non_camel_case_types)]
+#![allow(unused_assignments)]
+#![allow(clippy::zero_ptr)]
+#![allow(clippy::assign_op_pattern)]
+
+//! The schooling model from Programming Game AI by Example as a library.
+//!
+//! The simulation core has no graphics dependency. The OpenGL drawing code is
+//! only compiled with the `render` feature, which the viewer binary uses. A
+//! crate that only runs the simulation depends on this one with
+//! `default-features = false`, and then neither GLFW nor `small_gl_core` is
+//! built.
+
+pub mod c2d_matrix;
+pub mod cell_space_partition;
+pub mod configuration;
+pub mod entity_functions;
+pub mod entity_traits;
+pub mod game_world;
+pub mod geometry;
+pub mod inverted_aab_box_2d;
+pub mod kd_tree;
+pub mod metrics;
+pub mod obstacle;
+pub mod path;
+pub mod recorder;
+pub mod smoother;
+pub mod snapshot;
+pub mod spatial_hash;
+pub mod spatial_index;
+pub mod steering_behavior;
+pub mod sweep;
+pub mod transformations;
+pub mod utils;
+pub mod vehicle;
+pub mod wall_2d;
+
+pub use crate::c2d_matrix::C2DMatrix;
+pub use crate::cell_space_partition::CellSpacePartition;
+pub use crate::configuration::{ConfigLoader, CONFIG};
+pub use crate::entity_traits::{EntityBase, EntityMovable, EntitySteerable};
+pub use crate::game_world::{Formation, GameWorld};
+pub use crate::metrics::SchoolMetrics;
+pub use crate::obstacle::Obstacle;
+pub use crate::path::Path;
+pub use crate::recorder::{Player, Recorder, Recording, RecordingError};
+pub use crate::snapshot::{SnapshotError, WorldSnapshot};
+pub use crate::spatial_index::{SpatialIndex, SpatialIndexType};
+pub use crate::steering_behavior::{BehaviorType, Deceleration, NeighborhoodMode, SteeringBehavior, SummingMethod};
+pub use crate::sweep::{Sweep, SweepError};
+pub use crate::vehicle::Vehicle;
+pub use crate::wall_2d::Wall2D;
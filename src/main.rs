@@ -8,262 +8,216 @@
 #![allow(clippy::zero_ptr)]
 #![allow(clippy::assign_op_pattern)]
 
-mod c2d_matrix;
-mod cell_space_partition;
-mod configuration;
-mod entity_functions;
-mod entity_traits;
-mod game_world;
-mod inverted_aab_box_2d;
-mod path;
+#[cfg(feature = "render")]
 mod shapes;
-mod smoother;
-mod steering_behavior;
-mod transformations;
-mod utils;
-mod vehicle;
-mod wall_2d;
+#[cfg(feature = "render")]
+mod viewer;
 
+#[cfg(feature = "render")]
 extern crate glfw;
 
-use crate::game_world::GameWorld;
-use crate::shapes::mesh_plane::build_vertexes_and_indices;
-use glam::{vec3, Mat4};
-use glfw::{Action, Context, Key};
-use log::error;
-use small_gl_core::camera::{Camera, CameraMovement};
-use small_gl_core::gl;
-use small_gl_core::mesh::{Color, Mesh};
-use small_gl_core::model::ModelBuilder;
-use small_gl_core::shader::Shader;
-use small_gl_core::texture::{Texture, TextureConfig};
-use std::rc::Rc;
-
-const SCR_WIDTH: f32 = 1000.0;
-const SCR_HEIGHT: f32 = 800.0;
-
-struct State {
-    camera: Camera,
-    run: bool,
-    delta_time: f32,
-    frame_time: f32,
-    firstMouse: bool,
-    lastX: f32,
-    lastY: f32,
-    window_scale: (f32, f32),
-    viewport_width: f32,
-    viewport_height: f32,
-}
+#[cfg(feature = "render")]
+use schooling_fishes::configuration::ConfigWatcher;
+use schooling_fishes::configuration::{SCR_HEIGHT, SCR_WIDTH};
+use schooling_fishes::metrics::MetricsLog;
+#[cfg(feature = "render")]
+use schooling_fishes::Player;
+use schooling_fishes::{ConfigLoader, EntityBase, GameWorld, Recording, SchoolMetrics, Sweep};
+use std::io::Write;
 
-fn error_callback(err: glfw::Error, description: String) {
-    error!("GLFW error {:?}: {:?}", err, description);
-}
+// fixed steps run by --headless unless --frames is given
+const HEADLESS_FRAMES: usize = 1000;
+
+// where the viewer saves and loads snapshots unless --save is given
+const DEFAULT_SNAPSHOT: &str = "schooling.snapshot";
 
 fn main() {
-    let mut glfw = glfw::init(error_callback).unwrap();
-
-    glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
-    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
-
-    // for Apple
-    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
-
-    let (mut window, events) = glfw
-        .create_window(SCR_WIDTH as u32, SCR_HEIGHT as u32, "LearnOpenGL", glfw::WindowMode::Windowed)
-        .expect("Failed to create GLFW window.");
-
-    // Turn on all GLFW polling so that we can receive all WindowEvents
-    window.set_all_polling(true);
-    window.make_current();
-
-    // Initialize glad: load all OpenGL function pointers
-    // --------------------------------------------------
-    gl::load(|e| glfw.get_proc_address_raw(e) as *const std::os::raw::c_void);
-
-    // perspective setting
-    // //let camera = Camera::camera_vec3(vec3(300.0, 300.0, 500.0));
-    let camera = Camera::camera_vec3_up_yaw_pitch(
-        // vec3(400.0, 400.0, 700.0), for current x,y world
-        vec3(0.0, 170.0, 500.0), // for xz world
-        vec3(0.0, 1.0, 0.0),
-        -90.0, // seems camera starts by looking down the x-axis, so needs to turn left to see the plane
-        -20.0,
-    );
-
-    // let camera = Camera::camera_vec3_up_yaw_pitch(
-    //     vec3(400.0, -200.0, 50.0),
-    //     vec3(0.0, 1.0, 0.0),
-    //     -90.0, // seems camera starts by looking down the x-axis, so needs to turn left to see the plane
-    //     90.0);
-
-    // for ortho perspective
-    // let camera = Camera::camera_vec3(vec3(0.0, 0.0, 55.0));
-
-    // Initialize the world state
-    let mut state = State {
-        camera,
-        run: true,
-        delta_time: 0.0,
-        frame_time: 0.0,
-        firstMouse: true,
-        lastX: SCR_WIDTH / 2.0,
-        lastY: SCR_HEIGHT / 2.0,
-        window_scale: window.get_content_scale(),
-        viewport_width: SCR_WIDTH,
-        viewport_height: SCR_HEIGHT,
-    };
+    let args: Vec<String> = std::env::args().collect();
+
+    // steering is calculated on rayon's pool, one thread per core unless limited
+    if let Some(threads) = arg_value(&args, "--threads") {
+        match threads.parse() {
+            Ok(threads) => or_exit(rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()),
+            Err(_) => {
+                eprintln!("--threads expects a whole number, got {}", threads);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    let shader_texture = Shader::new("assets/shaders/camera_texture.vert", "assets/shaders/camera_texture.frag").unwrap();
+    if let Some(path) = arg_value(&args, "--sweep") {
+        run_sweep(or_exit(Sweep::load(path)), arg_value(&args, "--out"));
+        return;
+    }
 
-    let wavy_shader = Shader::new("assets/shaders/wavy_texture.vert", "assets/shaders/wavy_texture.frag").unwrap();
+    let mut config = match arg_value(&args, "--config") {
+        Some(path) => or_exit(ConfigLoader::load(path)),
+        None => ConfigLoader::default(),
+    };
 
-    let model_shader = Shader::new("assets/shaders/basic_model.vert", "assets/shaders/basic_model.frag").unwrap();
-    let wiggle_shader = Shader::new("assets/shaders/wiggle_shader.vert", "assets/shaders/wiggle_shader.frag").unwrap();
+    if let Some(path) = arg_value(&args, "--replay") {
+        replay(&args, or_exit(Recording::load(path)), &config);
+        return;
+    }
 
-    let model_shader = Rc::new(model_shader);
+    if let Some(seed) = arg_value(&args, "--seed") {
+        match seed.parse() {
+            Ok(seed) => config.Seed = Some(seed),
+            Err(_) => {
+                eprintln!("--seed expects a whole number, got {}", seed);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    let water_texture = Rc::new(Texture::new("assets/images/water_texture.png", &TextureConfig::new().set_flipv(true)).unwrap());
+    let frames = match arg_value(&args, "--frames") {
+        Some(frames) => match frames.parse() {
+            Ok(frames) => frames,
+            Err(_) => {
+                eprintln!("--frames expects a whole number, got {}", frames);
+                std::process::exit(1);
+            }
+        },
+        None => HEADLESS_FRAMES,
+    };
 
-    let sand_texture = Rc::new(Texture::new("assets/images/ground_0024_color_1k.jpg", &TextureConfig::new()).unwrap());
+    // a restored world carries its own config and seed
+    let mut game_world = match arg_value(&args, "--restore") {
+        Some(path) => or_exit(GameWorld::LoadSnapshot(path)),
+        None => GameWorld::with_config(SCR_WIDTH as i32, SCR_HEIGHT as i32, config),
+    };
 
-    let (vertices, indices) = build_vertexes_and_indices(500, 500, Color::white());
-    let surface_mesh = Mesh::new(vertices.clone(), indices.clone(), &water_texture, false);
-    let bottom_mesh = Mesh::new(vertices, indices, &sand_texture, false);
+    if let Some(path) = arg_value(&args, "--record") {
+        or_exit(game_world.StartRecording(path));
+    }
 
-    let big_fish = "assets/models/BarramundiFish/glTF/BarramundiFish.gltf";
-    let fish_model = ModelBuilder::new("big_fish", big_fish).build().unwrap();
+    #[cfg(feature = "render")]
+    if !args.iter().any(|arg| arg == "--headless") {
+        let config_watcher = arg_value(&args, "--config").map(ConfigWatcher::new);
+        let snapshot_path = arg_value(&args, "--save").unwrap_or(DEFAULT_SNAPSHOT);
+        viewer::run(viewer::Scene::Live {
+            game_world,
+            config_watcher,
+            snapshot_path: snapshot_path.into(),
+        });
+        return;
+    }
 
-    let game_world = GameWorld::new(state.viewport_width as i32, state.viewport_height as i32, fish_model.clone());
+    run_headless(&mut game_world, frames, arg_value(&args, "--metrics"));
 
-    unsafe {
-        gl::Enable(gl::DEPTH_TEST);
-        gl::Enable(gl::BLEND);
-        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    if let Some(path) = arg_value(&args, "--save") {
+        or_exit(game_world.SaveSnapshot(path));
     }
 
-    // render loop
-    while !window.should_close() {
-        let current_time = glfw.get_time() as f32;
-        if state.run {
-            state.delta_time = current_time - state.frame_time;
-        } else {
-            state.delta_time = 0.0;
-        }
-        state.frame_time = current_time;
+    let recorded = or_exit(game_world.StopRecording());
+    if let Some(frames) = recorded {
+        eprintln!("recorded {} frames", frames);
 
-        glfw.poll_events();
-        for (_, event) in glfw::flush_messages(&events) {
-            handle_window_event(&mut window, event, &mut state);
+        if let (Some(path), Some(csv_path)) = (arg_value(&args, "--record"), arg_value(&args, "--csv")) {
+            or_exit(or_exit(Recording::load(path)).export_csv(csv_path));
         }
+    }
+}
 
-        unsafe {
-            gl::ClearColor(0.0, 0.02, 0.45, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-        }
+// plays a recording in the viewer, or writes it out as CSV with --csv and
+// its metrics with --metrics
+fn replay(args: &[String], recording: Recording, config: &ConfigLoader) {
+    let csv_path = arg_value(args, "--csv");
+    let metrics_path = arg_value(args, "--metrics");
 
-        let view = state.camera.get_view_matrix();
-        let projection = Mat4::perspective_rh_gl(
-            state.camera.zoom.to_radians(),
-            state.viewport_width / state.viewport_height,
-            0.1,
-            2000.0,
-        );
-        // let projection = Mat4::orthographic_rh_gl(0.0, 600.0, 0.0, 600.0, 0.1, 100.0);
-        // let projection = Mat4::orthographic_rh_gl(0.0, 1000.0, 0.0, 1000.0, 0.0, 1000.0);
-
-        GameWorld::Update(&game_world, state.delta_time);
-
-        // bottom
-        shader_texture.use_shader_with(&projection, &view);
-        shader_texture.set_float("alpha", 1.0);
-        bottom_mesh.render(&shader_texture, vec3(-2500.0, -5.0, -2500.0), 0.0, vec3(10.0, 1.0, 10.0));
-
-        // fish
-        wiggle_shader.use_shader_with(&projection, &view);
-        wiggle_shader.set_vec3("nosePos", &vec3(0.0, 0.0, -0.3));
-        wiggle_shader.set_float("time", state.frame_time);
-        game_world.borrow().render(&wiggle_shader);
-
-        // surface
-        wavy_shader.use_shader_with(&projection, &view);
-        wavy_shader.set_float("alpha", 0.4);
-        wavy_shader.set_float("current_time", current_time);
-        surface_mesh.render(&wavy_shader, vec3(-750.0, 100.0, -750.0), 0.0, vec3(3.0, 1.0, 3.0));
-
-        window.swap_buffers();
+    if let Some(csv_path) = csv_path {
+        or_exit(recording.export_csv(csv_path));
     }
-}
 
-//
-// GLFW maps callbacks to events.
-//
-fn handle_window_event(window: &mut glfw::Window, event: glfw::WindowEvent, state: &mut State) {
-    match event {
-        glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
-        glfw::WindowEvent::FramebufferSize(width, height) => {
-            framebuffer_size_event(window, state, width, height);
-        }
-        glfw::WindowEvent::Key(Key::W, _, _, _) => {
-            state.camera.process_keyboard(CameraMovement::Forward, state.delta_time);
-        }
-        glfw::WindowEvent::Key(Key::S, _, _, _) => {
-            state.camera.process_keyboard(CameraMovement::Backward, state.delta_time);
-        }
-        glfw::WindowEvent::Key(Key::A, _, _, _) => {
-            state.camera.process_keyboard(CameraMovement::Left, state.delta_time);
-        }
-        glfw::WindowEvent::Key(Key::D, _, _, _) => {
-            state.camera.process_keyboard(CameraMovement::Right, state.delta_time);
-        }
-        glfw::WindowEvent::Key(Key::Q, _, _, _) => {
-            state.camera.process_keyboard(CameraMovement::Up, state.delta_time);
-        }
-        glfw::WindowEvent::Key(Key::Z, _, _, _) => {
-            state.camera.process_keyboard(CameraMovement::Down, state.delta_time);
-        }
-        glfw::WindowEvent::CursorPos(xpos, ypos) => mouse_handler(state, xpos, ypos),
-        glfw::WindowEvent::Scroll(xoffset, ysoffset) => scroll_handler(state, xoffset, ysoffset),
-        _evt => {
-            // println!("WindowEvent: {:?}", _evt);
+    if let Some(metrics_path) = metrics_path {
+        let mut log = or_exit(MetricsLog::create(metrics_path));
+        let (cx, cy) = (recording.header.cx, recording.header.cy);
+
+        for (step, frame) in recording.frames.iter().enumerate() {
+            let metrics = SchoolMetrics::FromSamples(&frame.samples, config.ViewDistance, cx, cy, !config.TankWalls);
+            or_exit(log.Write(step, frame.time, &metrics));
         }
+        or_exit(log.Finish());
+    }
+
+    if csv_path.is_some() || metrics_path.is_some() {
+        return;
+    }
+
+    #[cfg(feature = "render")]
+    viewer::run(viewer::Scene::Replay(Player::new(recording)));
+
+    #[cfg(not(feature = "render"))]
+    {
+        eprintln!("playing a recording needs the viewer, build with the render feature or export it with --csv or --metrics");
+        std::process::exit(1);
     }
 }
 
-// glfw: whenever the window size changed (by OS or user resize) this event fires.
-// ---------------------------------------------------------------------------------------------
-fn framebuffer_size_event(_window: &mut glfw::Window, state: &mut State, width: i32, height: i32) {
-    println!("resize: width, height: {}, {}", width, height);
-    set_view_port(state, width, height);
+// runs every combination of a sweep with every seed, writing a row of
+// metrics per run to out or to stdout
+fn run_sweep(sweep: Sweep, out: Option<&str>) {
+    let mut writer: Box<dyn Write> = match out {
+        Some(path) => Box::new(std::io::BufWriter::new(or_exit(std::fs::File::create(path)))),
+        None => Box::new(std::io::stdout()),
+    };
+
+    or_exit(sweep.WriteCsvHeader(&mut writer));
+
+    let mut run = 0;
+    for combination in 0..sweep.Combinations().len() {
+        for &seed in sweep.Seeds() {
+            run += 1;
+            eprintln!("run {} of {}: combination {} seed {}", run, sweep.NumRuns(), combination, seed);
+
+            let mean = sweep.RunOne(combination, seed, SCR_WIDTH as i32, SCR_HEIGHT as i32);
+            or_exit(sweep.WriteCsvRow(&mut writer, combination, seed, &mean));
+            or_exit(writer.flush());
+        }
+    }
 }
 
-fn set_view_port(state: &mut State, width: i32, height: i32) {
-    unsafe {
-        gl::Viewport(0, 0, width, height);
+// the value of a result, or exits after printing the error
+fn or_exit<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
     }
+}
 
-    state.viewport_width = width as f32 / state.window_scale.0;
-    state.viewport_height = height as f32 / state.window_scale.1;
+// the value following a flag, e.g. the path in "--config params.toml"
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|value| value.as_str())
 }
 
-fn mouse_handler(state: &mut State, xposIn: f64, yposIn: f64) {
-    let xpos = xposIn as f32;
-    let ypos = yposIn as f32;
+// steps the simulation without a window and prints where every vehicle ended
+// up, writing the school metrics after every step to metrics_path if given
+fn run_headless(game_world: &mut GameWorld, frames: usize, metrics_path: Option<&str>) {
+    eprintln!("seed {}", game_world.Seed());
 
-    if state.firstMouse {
-        state.lastX = xpos;
-        state.lastY = ypos;
-        state.firstMouse = false;
-    }
+    let mut metrics_log = metrics_path.map(|path| or_exit(MetricsLog::create(path)));
 
-    let xoffset = xpos - state.lastX;
-    let yoffset = state.lastY - ypos; // reversed since y-coordinates go from bottom to top
+    let time_step = game_world.Config().FixedTimeStep;
+    for step in 0..frames {
+        game_world.Step(time_step);
 
-    state.lastX = xpos;
-    state.lastY = ypos;
+        if let Some(log) = metrics_log.as_mut() {
+            let metrics = game_world.Metrics();
+            or_exit(log.Write(step, (step + 1) as f32 * time_step, &metrics));
+        }
+    }
 
-    state.camera.process_mouse_movement(xoffset, yoffset, true);
-}
+    if let Some(log) = metrics_log {
+        or_exit(log.Finish());
+    }
 
-fn scroll_handler(state: &mut State, _xoffset: f64, yoffset: f64) {
-    state.camera.process_mouse_scroll(yoffset as f32);
+    for vehicle in &game_world.m_Vehicles {
+        println!("{}, {}, {}", vehicle.id(), vehicle.position().x, vehicle.position().y);
+    }
 }
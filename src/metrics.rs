@@ -0,0 +1,269 @@
+//------------------------------------------------------------------------
+//
+//  Collective behaviour metrics. These are the usual numbers for telling
+//  schooling, swarming and milling apart:
+//
+//      polarization   length of the mean heading. 1 when every fish swims
+//                     the same way, near 0 when headings cancel out
+//      milling        length of the mean angular momentum about the
+//                     centroid. Near 1 when the school circles its centre
+//      nearest        mean distance from each fish to its nearest neighbour
+//      extent         distance from the centroid to the farthest fish
+//      subgroups      number of groups left after linking every pair of
+//                     fish closer than the link distance
+//
+//  When the world wraps around distances are measured the short way round
+//  and the centroid is the circular mean along each axis. In a tank they
+//  are plain straight line distances and the centroid is the usual mean.
+//
+//------------------------------------------------------------------------
+
+use crate::recorder::VehicleSample;
+use crate::spatial_index::WorldOffset;
+use glam::{vec2, Vec2};
+use std::f32::consts::TAU;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct SchoolMetrics {
+    pub polarization: f32,
+    pub milling: f32,
+    pub mean_nearest_neighbour: f32,
+    pub extent: f32,
+    pub subgroups: usize,
+}
+
+impl SchoolMetrics {
+    pub const CSV_HEADER: &'static str = "polarization,milling,nearest_neighbour,extent,subgroups";
+
+    //------------------------------- Compute --------------------------------
+    //
+    //  positions and headings are for the same fish in the same order, the
+    //  world is cx by cy, wrapping around when toroidal, and fish closer
+    //  than link_distance share a subgroup
+    //------------------------------------------------------------------------
+    pub fn Compute(positions: &[Vec2], headings: &[Vec2], link_distance: f32, cx: i32, cy: i32, toroidal: bool) -> SchoolMetrics {
+        assert_eq!(positions.len(), headings.len(), "a heading is needed for every position");
+
+        let count = positions.len();
+        if count == 0 {
+            return SchoolMetrics::default();
+        }
+
+        let world = vec2(cx as f32, cy as f32);
+        let centroid = if toroidal {
+            CircularMean(positions, world)
+        } else {
+            positions.iter().sum::<Vec2>() / count as f32
+        };
+
+        let mut heading_sum = Vec2::ZERO;
+        let mut momentum_sum = 0.0;
+        let mut extent = 0.0f32;
+
+        for (position, heading) in positions.iter().zip(headings) {
+            heading_sum += *heading;
+
+            let offset = WorldOffset(centroid, *position, world, toroidal);
+            let distance = offset.length();
+            extent = extent.max(distance);
+
+            // the turning component of the heading about the centroid
+            if distance > 0.0 {
+                momentum_sum += offset.perp_dot(*heading) / distance;
+            }
+        }
+
+        let mut groups = UnionFind::new(count);
+        let mut nearest_sum = 0.0;
+        let link_distance_sq = link_distance * link_distance;
+
+        for i in 0..count {
+            let mut nearest_sq = f32::MAX;
+
+            for j in 0..count {
+                if i == j {
+                    continue;
+                }
+
+                let distance_sq = WorldOffset(positions[i], positions[j], world, toroidal).length_squared();
+                nearest_sq = nearest_sq.min(distance_sq);
+
+                if j > i && distance_sq < link_distance_sq {
+                    groups.Union(i, j);
+                }
+            }
+
+            if count > 1 {
+                nearest_sum += nearest_sq.sqrt();
+            }
+        }
+
+        SchoolMetrics {
+            polarization: heading_sum.length() / count as f32,
+            milling: momentum_sum.abs() / count as f32,
+            mean_nearest_neighbour: if count > 1 { nearest_sum / count as f32 } else { 0.0 },
+            extent,
+            subgroups: groups.NumSets(),
+        }
+    }
+
+    // the metrics of a recorded frame
+    pub fn FromSamples(samples: &[VehicleSample], link_distance: f32, cx: i32, cy: i32, toroidal: bool) -> SchoolMetrics {
+        let positions: Vec<Vec2> = samples.iter().map(|sample| sample.position).collect();
+        let headings: Vec<Vec2> = samples.iter().map(|sample| sample.heading).collect();
+        SchoolMetrics::Compute(&positions, &headings, link_distance, cx, cy, toroidal)
+    }
+
+    // the values in the order of CSV_HEADER
+    pub fn CsvRow(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.polarization, self.milling, self.mean_nearest_neighbour, self.extent, self.subgroups
+        )
+    }
+}
+
+// streams one row of metrics per step to a CSV file
+pub struct MetricsLog {
+    m_Writer: BufWriter<File>,
+}
+
+impl MetricsLog {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<MetricsLog> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "step,time,{}", SchoolMetrics::CSV_HEADER)?;
+        Ok(MetricsLog { m_Writer: writer })
+    }
+
+    pub fn Write(&mut self, step: usize, time: f32, metrics: &SchoolMetrics) -> io::Result<()> {
+        writeln!(self.m_Writer, "{},{},{}", step, time, metrics.CsvRow())
+    }
+
+    pub fn Finish(mut self) -> io::Result<()> {
+        self.m_Writer.flush()
+    }
+}
+
+// the mean position treating each axis as a circle, so a school straddling
+// an edge has its centroid at the edge rather than in the middle of the world
+fn CircularMean(positions: &[Vec2], world: Vec2) -> Vec2 {
+    let axis_mean = |coordinate: fn(&Vec2) -> f32, size: f32| {
+        let (sin, cos) = positions.iter().fold((0.0f32, 0.0f32), |(sin, cos), position| {
+            let angle = coordinate(position) / size * TAU;
+            (sin + angle.sin(), cos + angle.cos())
+        });
+
+        (sin.atan2(cos) / TAU * size).rem_euclid(size)
+    };
+
+    vec2(axis_mean(|p| p.x, world.x), axis_mean(|p| p.y, world.y))
+}
+
+// disjoint sets with path halving, used to count subgroups
+struct UnionFind {
+    m_Parents: Vec<usize>,
+    m_iNumSets: usize,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        UnionFind {
+            m_Parents: (0..size).collect(),
+            m_iNumSets: size,
+        }
+    }
+
+    fn Find(&mut self, mut i: usize) -> usize {
+        while self.m_Parents[i] != i {
+            self.m_Parents[i] = self.m_Parents[self.m_Parents[i]];
+            i = self.m_Parents[i];
+        }
+        i
+    }
+
+    fn Union(&mut self, a: usize, b: usize) {
+        let a = self.Find(a);
+        let b = self.Find(b);
+
+        if a != b {
+            self.m_Parents[a] = b;
+            self.m_iNumSets -= 1;
+        }
+    }
+
+    fn NumSets(&self) -> usize {
+        self.m_iNumSets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::SchoolMetrics;
+    use glam::{vec2, Vec2};
+    use std::f32::consts::TAU;
+
+    #[test]
+    pub fn test_polarized_school() {
+        let positions: Vec<Vec2> = (0..10).map(|i| vec2(100.0 + i as f32 * 10.0, 200.0)).collect();
+        let headings = vec![vec2(0.0, 1.0); 10];
+
+        let metrics = SchoolMetrics::Compute(&positions, &headings, 15.0, 1000, 800, true);
+
+        assert!((metrics.polarization - 1.0).abs() < 0.0001);
+        assert!((metrics.mean_nearest_neighbour - 10.0).abs() < 0.0001);
+        assert!((metrics.extent - 45.0).abs() < 0.01);
+        assert_eq!(metrics.subgroups, 1);
+    }
+
+    #[test]
+    pub fn test_milling_school() {
+        let centre = vec2(500.0, 400.0);
+        let mut positions = vec![];
+        let mut headings = vec![];
+
+        for i in 0..36 {
+            let angle = i as f32 / 36.0 * TAU;
+            let radial = vec2(angle.cos(), angle.sin());
+            positions.push(centre + radial * 100.0);
+            headings.push(radial.perp());
+        }
+
+        let metrics = SchoolMetrics::Compute(&positions, &headings, 50.0, 1000, 800, true);
+
+        assert!(metrics.polarization < 0.0001);
+        assert!((metrics.milling - 1.0).abs() < 0.0001);
+        assert!((metrics.extent - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    pub fn test_subgroups_across_the_edge() {
+        // the first pair straddles the left and right edges
+        let positions = vec![vec2(1.0, 400.0), vec2(998.0, 400.0), vec2(500.0, 100.0), vec2(505.0, 100.0)];
+        let headings = vec![vec2(1.0, 0.0); 4];
+
+        let metrics = SchoolMetrics::Compute(&positions, &headings, 10.0, 1000, 800, true);
+
+        assert_eq!(metrics.subgroups, 2);
+        assert!((metrics.mean_nearest_neighbour - 4.0).abs() < 0.001);
+    }
+
+    #[test]
+    pub fn test_subgroups_in_a_tank() {
+        // in a tank the pair at the left and right walls are far apart
+        let positions = vec![vec2(1.0, 400.0), vec2(998.0, 400.0), vec2(500.0, 100.0), vec2(505.0, 100.0)];
+        let headings = vec![vec2(1.0, 0.0); 4];
+
+        let metrics = SchoolMetrics::Compute(&positions, &headings, 10.0, 1000, 800, false);
+
+        assert_eq!(metrics.subgroups, 3);
+        assert!((metrics.mean_nearest_neighbour - (10.0 + 499.0f32.hypot(300.0) + 493.0f32.hypot(300.0)) / 4.0).abs() < 0.01);
+
+        // and the centroid is the plain mean, not one on the edge
+        let metrics = SchoolMetrics::Compute(&positions[..2], &headings[..2], 10.0, 1000, 800, false);
+        assert_eq!(metrics.subgroups, 2);
+        assert!((metrics.extent - 498.5).abs() < 0.01);
+    }
+}
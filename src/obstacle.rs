@@ -0,0 +1,80 @@
+//------------------------------------------------------------------------
+//
+//  Name:   Obstacle.h
+//
+//  Desc:   Simple obstacle class
+//
+//  Original Author: Mat Buckland 2002
+//
+//------------------------------------------------------------------------
+
+use crate::entity_traits::{next_valid_id, EntityBase, EntityType};
+use glam::{vec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub id: i32,
+    pub entity_type: i32,
+    pub tag: bool,
+    pub position: Vec2,
+    pub scale: Vec2,
+    pub bounding_radius: f32,
+}
+
+impl Obstacle {
+    pub fn new(position: Vec2, radius: f32) -> Self {
+        Obstacle {
+            id: next_valid_id(),
+            entity_type: EntityType::obstacle as i32,
+            tag: false,
+            position,
+            scale: vec2(1.0, 1.0),
+            bounding_radius: radius,
+        }
+    }
+}
+
+impl EntityBase for Obstacle {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn entity_type(&self) -> i32 {
+        self.entity_type
+    }
+
+    fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    fn bounding_radius(&self) -> f32 {
+        self.bounding_radius
+    }
+
+    fn tag(&mut self) {
+        self.tag = true;
+    }
+
+    fn untag(&mut self) {
+        self.tag = false;
+    }
+
+    fn is_tagged(&self) -> bool {
+        self.tag
+    }
+
+    fn scale(&self) -> Vec2 {
+        self.scale
+    }
+
+    // the bounding radius grows and shrinks with the scale
+    fn set_scale_vec(&mut self, val: Vec2) {
+        self.bounding_radius *= val.x.max(val.y) / self.scale.x.max(self.scale.y);
+        self.scale = val;
+    }
+
+    fn set_scale_float(&mut self, val: f32) {
+        self.set_scale_vec(vec2(val, val));
+    }
+}
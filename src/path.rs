@@ -1,9 +1,12 @@
 use crate::transformations::Vec2DRotateAroundOrigin;
-use crate::utils::{min, RandInRange};
+use crate::utils::{min, RandInRange, SimRng};
 use glam::{vec2, Vec2};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "render")]
+use small_gl_core::gl;
 use std::f32::consts::TAU;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Path {
     m_WayPoints: Vec<Vec2>,
 
@@ -15,19 +18,67 @@ pub struct Path {
 }
 
 impl Path {
-    pub fn new(num_way_points: i32, min_x: f32, min_y: f32, max_x: f32, max_y: f32, looped: bool) -> Self {
+    pub fn new(rng: &mut SimRng, num_way_points: i32, min_x: f32, min_y: f32, max_x: f32, max_y: f32, looped: bool) -> Self {
         let mut path = Path {
             m_WayPoints: vec![],
             curWaypoint: 0,
             m_bLooped: looped,
         };
-        path.CreateRandomPath(num_way_points, min_x, min_y, max_x, max_y);
+        path.CreateRandomPath(rng, num_way_points, min_x, min_y, max_x, max_y);
         path
     }
 
-    pub fn LoopOn(&mut self) {}
+    //returns the current waypoint
+    pub fn CurrentWaypoint(&self) -> Vec2 {
+        assert!(!self.m_WayPoints.is_empty(), "Path has no waypoints");
+        self.m_WayPoints[self.curWaypoint]
+    }
+
+    //returns true if the end of the list has been reached. A looped path
+    //is never finished.
+    pub fn Finished(&self) -> bool {
+        !self.m_bLooped && self.curWaypoint + 1 >= self.m_WayPoints.len()
+    }
+
+    //moves the iterator on to the next waypoint in the list
+    pub fn SetNextWaypoint(&mut self) {
+        assert!(!self.m_WayPoints.is_empty(), "Path has no waypoints");
+
+        if self.curWaypoint + 1 < self.m_WayPoints.len() {
+            self.curWaypoint += 1;
+        } else if self.m_bLooped {
+            self.curWaypoint = 0;
+        }
+    }
+
+    pub fn LoopOn(&mut self) {
+        self.m_bLooped = true;
+    }
+
+    pub fn LoopOff(&mut self) {
+        self.m_bLooped = false;
+    }
+
+    pub fn isLooped(&self) -> bool {
+        self.m_bLooped
+    }
+
+    //methods for setting the path with either another Path or a list of vectors
+    pub fn Set(&mut self, new_path: Vec<Vec2>) {
+        self.m_WayPoints = new_path;
+        self.curWaypoint = 0;
+    }
 
-    fn CreateRandomPath(&mut self, num_way_points: i32, min_x: f32, min_y: f32, max_x: f32, max_y: f32) {
+    pub fn Clear(&mut self) {
+        self.m_WayPoints.clear();
+        self.curWaypoint = 0;
+    }
+
+    pub fn GetPath(&self) -> &[Vec2] {
+        &self.m_WayPoints
+    }
+
+    pub fn CreateRandomPath(&mut self, rng: &mut SimRng, num_way_points: i32, min_x: f32, min_y: f32, max_x: f32, max_y: f32) {
         self.m_WayPoints.clear();
 
         let midX = (max_x + min_x) / 2.0;
@@ -38,7 +89,7 @@ impl Path {
         let spacing = TAU / num_way_points as f32;
 
         for i in 0..num_way_points {
-            let RadialDist = RandInRange(smaller * 0.2, smaller);
+            let RadialDist = RandInRange(rng, smaller * 0.2, smaller);
 
             let mut temp = vec2(RadialDist, 0.0);
 
@@ -53,7 +104,59 @@ impl Path {
         self.curWaypoint = 0; // m_WayPoints.begin();
     }
 
-    pub(crate) fn Render(&self) {
-        todo!()
+    #[cfg(feature = "render")]
+    pub fn Render(&self) {
+        let color = [0.0, 0.8, 0.0];
+
+        let mut segments: Vec<(Vec2, Vec2)> = self.m_WayPoints.windows(2).map(|pair| (pair[0], pair[1])).collect();
+
+        if self.m_bLooped && self.m_WayPoints.len() > 2 {
+            segments.push((self.m_WayPoints[self.m_WayPoints.len() - 1], self.m_WayPoints[0]));
+        }
+
+        for (from, to) in segments {
+            // same world to xz-plane mapping the vehicles are drawn with
+            let start = [from.x - 400.0, 0.0, from.y - 400.0];
+            let end = [to.x - 400.0, 0.0, to.y - 400.0];
+
+            unsafe {
+                gl::LineWidth(1.0);
+                gl::Begin(gl::LINES);
+                gl::Color3fv(color.as_ptr());
+                gl::Vertex3fv(start.as_ptr());
+                gl::Vertex3fv(end.as_ptr());
+                gl::End();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path::Path;
+    use glam::vec2;
+
+    #[test]
+    pub fn test_waypoints() {
+        let mut path = Path::default();
+        path.Set(vec![vec2(0.0, 0.0), vec2(10.0, 0.0), vec2(10.0, 10.0)]);
+
+        assert_eq!(path.CurrentWaypoint(), vec2(0.0, 0.0));
+        assert!(!path.Finished());
+
+        path.SetNextWaypoint();
+        path.SetNextWaypoint();
+        assert_eq!(path.CurrentWaypoint(), vec2(10.0, 10.0));
+        assert!(path.Finished());
+
+        // an open path stays on its last waypoint
+        path.SetNextWaypoint();
+        assert_eq!(path.CurrentWaypoint(), vec2(10.0, 10.0));
+
+        // a looped path goes back to the start and never finishes
+        path.LoopOn();
+        assert!(!path.Finished());
+        path.SetNextWaypoint();
+        assert_eq!(path.CurrentWaypoint(), vec2(0.0, 0.0));
     }
 }
@@ -0,0 +1,412 @@
+//------------------------------------------------------------------------
+//
+//  Trajectory recording. A Recorder hooked into GameWorld writes every
+//  vehicle's id, position, velocity and heading after each fixed step to
+//  a compact binary file. A Recording reads the file back for export to
+//  CSV, and a Player steps through one for replay in the viewer.
+//
+//  File layout, all bincode: the version, a RecordingHeader, then one
+//  Frame per simulation step until the end of the file.
+//
+//------------------------------------------------------------------------
+
+use crate::entity_traits::{EntityBase, EntityMovable};
+use crate::utils::{LerpHeading, LerpWrapped};
+use crate::vehicle::Vehicle;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+// bumped whenever the layout changes so an old file is refused rather
+// than read back wrong
+pub const RECORDING_VERSION: u32 = 1;
+
+// what is needed to draw each vehicle that does not change from frame to
+// frame, in the same order as the samples of every frame
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VehicleInfo {
+    pub id: i32,
+    pub scale: Vec2,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    // the size of the world, positions wrap around at these
+    pub cx: i32,
+    pub cy: i32,
+    pub vehicles: Vec<VehicleInfo>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VehicleSample {
+    pub id: i32,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub heading: Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    // simulated seconds since recording started
+    pub time: f32,
+    pub samples: Vec<VehicleSample>,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    // the file could not be read or written
+    Io(PathBuf, std::io::Error),
+    // the bytes are not a recording
+    Format(bincode::Error),
+    // the file was written by a different version of the layout
+    Version(u32),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(path, error) => write!(f, "could not access recording {}: {}", path.display(), error),
+            RecordingError::Format(error) => write!(f, "could not decode recording: {}", error),
+            RecordingError::Version(version) => write!(
+                f,
+                "recording is version {} but this build reads version {}",
+                version, RECORDING_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+//------------------------------------------------------------------------
+//
+//  Recorder
+//
+//  streams frames to disk as they are taken so long runs don't have to fit
+//  in memory. A write error stops the recording and is kept for Finish.
+//------------------------------------------------------------------------
+pub struct Recorder {
+    m_Path: PathBuf,
+    m_Writer: Option<BufWriter<File>>,
+    m_Error: Option<RecordingError>,
+    m_dTime: f32,
+    m_iNumFrames: usize,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, header: &RecordingHeader) -> Result<Recorder, RecordingError> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::create(&path).map_err(|error| RecordingError::Io(path.clone(), error))?;
+
+        let mut writer = BufWriter::new(file);
+        bincode::serialize_into(&mut writer, &(RECORDING_VERSION, header)).map_err(RecordingError::Format)?;
+
+        Ok(Recorder {
+            m_Path: path,
+            m_Writer: Some(writer),
+            m_Error: None,
+            m_dTime: 0.0,
+            m_iNumFrames: 0,
+        })
+    }
+
+    pub fn header(cx: i32, cy: i32, vehicles: &[Vehicle]) -> RecordingHeader {
+        RecordingHeader {
+            cx,
+            cy,
+            vehicles: vehicles
+                .iter()
+                .map(|vehicle| VehicleInfo {
+                    id: vehicle.id(),
+                    scale: vehicle.scale(),
+                    height: vehicle.Height(),
+                })
+                .collect(),
+        }
+    }
+
+    // writes the state of every vehicle after a step of time_elapsed seconds
+    pub fn Record(&mut self, time_elapsed: f32, vehicles: &[Vehicle]) {
+        self.m_dTime += time_elapsed;
+
+        let Some(writer) = self.m_Writer.as_mut() else {
+            return;
+        };
+
+        let frame = Frame {
+            time: self.m_dTime,
+            samples: vehicles
+                .iter()
+                .map(|vehicle| VehicleSample {
+                    id: vehicle.id(),
+                    position: vehicle.position(),
+                    velocity: vehicle.velocity(),
+                    heading: vehicle.heading(),
+                })
+                .collect(),
+        };
+
+        match bincode::serialize_into(writer, &frame) {
+            Ok(()) => self.m_iNumFrames += 1,
+            Err(error) => {
+                self.m_Writer = None;
+                self.m_Error = Some(RecordingError::Format(error));
+            }
+        }
+    }
+
+    pub fn NumFrames(&self) -> usize {
+        self.m_iNumFrames
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.m_Path
+    }
+
+    // flushes the file and reports the first error the recording ran into
+    pub fn Finish(mut self) -> Result<usize, RecordingError> {
+        if let Some(error) = self.m_Error.take() {
+            return Err(error);
+        }
+
+        if let Some(mut writer) = self.m_Writer.take() {
+            writer.flush().map_err(|error| RecordingError::Io(self.m_Path.clone(), error))?;
+        }
+
+        Ok(self.m_iNumFrames)
+    }
+}
+
+//------------------------------------------------------------------------
+//
+//  Recording
+//
+//  a whole recording read back into memory
+//------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> Result<Recording, RecordingError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|error| RecordingError::Io(path.to_path_buf(), error))?;
+        Recording::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Recording, RecordingError> {
+        let mut reader = Cursor::new(bytes);
+
+        let version: u32 = bincode::deserialize_from(&mut reader).map_err(RecordingError::Format)?;
+        if version != RECORDING_VERSION {
+            return Err(RecordingError::Version(version));
+        }
+
+        let header: RecordingHeader = bincode::deserialize_from(&mut reader).map_err(RecordingError::Format)?;
+
+        let mut frames = vec![];
+        while (reader.position() as usize) < bytes.len() {
+            frames.push(bincode::deserialize_from(&mut reader).map_err(RecordingError::Format)?);
+        }
+
+        Ok(Recording { header, frames })
+    }
+
+    // one row per vehicle per frame
+    pub fn write_csv(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writeln!(writer, "frame,time,id,x,y,vx,vy,hx,hy")?;
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            for sample in &frame.samples {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{},{},{}",
+                    index,
+                    frame.time,
+                    sample.id,
+                    sample.position.x,
+                    sample.position.y,
+                    sample.velocity.x,
+                    sample.velocity.y,
+                    sample.heading.x,
+                    sample.heading.y
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn export_csv(&self, path: impl AsRef<Path>) -> Result<(), RecordingError> {
+        let path = path.as_ref();
+        let io_error = |error| RecordingError::Io(path.to_path_buf(), error);
+
+        let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+        self.write_csv(&mut writer).map_err(io_error)?;
+        writer.flush().map_err(io_error)
+    }
+
+    // the simulated time covered by the recording
+    pub fn Duration(&self) -> f32 {
+        self.frames.last().map_or(0.0, |frame| frame.time)
+    }
+}
+
+//------------------------------------------------------------------------
+//
+//  Player
+//
+//  plays a recording back in real time with pause and scrubbing. Between
+//  frames the samples are interpolated the same way live vehicles are.
+//------------------------------------------------------------------------
+pub struct Player {
+    m_Recording: Recording,
+    m_dTime: f32,
+    m_bPaused: bool,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        let start = recording.frames.first().map_or(0.0, |frame| frame.time);
+
+        Player {
+            m_Recording: recording,
+            m_dTime: start,
+            m_bPaused: false,
+        }
+    }
+
+    pub fn Recording(&self) -> &Recording {
+        &self.m_Recording
+    }
+
+    // moves playback on by time_elapsed unless paused, stopping at the end
+    pub fn Update(&mut self, time_elapsed: f32) {
+        if self.m_bPaused {
+            return;
+        }
+
+        self.m_dTime += time_elapsed;
+
+        if self.m_dTime >= self.m_Recording.Duration() {
+            self.m_dTime = self.m_Recording.Duration();
+            self.m_bPaused = true;
+        }
+    }
+
+    pub fn TogglePause(&mut self) {
+        self.m_bPaused = !self.m_bPaused;
+    }
+
+    pub fn IsPaused(&self) -> bool {
+        self.m_bPaused
+    }
+
+    // the index of the frame at or before the playback time
+    pub fn CurrentFrame(&self) -> usize {
+        self.m_Recording
+            .frames
+            .partition_point(|frame| frame.time <= self.m_dTime)
+            .saturating_sub(1)
+    }
+
+    // jumps to a frame, pausing so it stays on screen
+    pub fn Seek(&mut self, frame: usize) {
+        if let Some(frame) = self.m_Recording.frames.get(frame).or(self.m_Recording.frames.last()) {
+            self.m_dTime = frame.time;
+        }
+        self.m_bPaused = true;
+    }
+
+    // scrubs forwards or backwards a number of frames
+    pub fn StepFrames(&mut self, frames: i32) {
+        let frame = self.CurrentFrame() as i64 + frames as i64;
+        self.Seek(frame.max(0) as usize);
+    }
+
+    // the samples at the playback time, blended between the frames either side
+    pub fn Samples(&self) -> Vec<VehicleSample> {
+        let frames = &self.m_Recording.frames;
+        let header = &self.m_Recording.header;
+
+        if frames.is_empty() {
+            return vec![];
+        }
+
+        let index = self.CurrentFrame();
+        let current = &frames[index];
+
+        let Some(next) = frames.get(index + 1) else {
+            return current.samples.clone();
+        };
+
+        let alpha = ((self.m_dTime - current.time) / (next.time - current.time)).clamp(0.0, 1.0);
+
+        current
+            .samples
+            .iter()
+            .zip(&next.samples)
+            .map(|(from, to)| VehicleSample {
+                id: to.id,
+                position: LerpWrapped(from.position, to.position, alpha, header.cx, header.cy),
+                velocity: from.velocity.lerp(to.velocity, alpha),
+                heading: LerpHeading(from.heading, to.heading, alpha),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::ConfigLoader;
+    use crate::entity_traits::EntityBase;
+    use crate::game_world::GameWorld;
+    use crate::recorder::{Player, Recording};
+
+    #[test]
+    pub fn test_record_and_replay() {
+        let config = ConfigLoader {
+            NumAgents: 20,
+            Seed: Some(5),
+            ..ConfigLoader::default()
+        };
+        let path = std::env::temp_dir().join(format!("schooling_recording_{}.rec", std::process::id()));
+
+        let mut world = GameWorld::with_config(1000, 800, config);
+        world.StartRecording(&path).unwrap();
+        for _ in 0..30 {
+            world.Step(1.0 / 60.0);
+        }
+        assert_eq!(world.StopRecording().unwrap(), Some(30));
+
+        let recording = Recording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recording.frames.len(), 30);
+        assert_eq!(recording.header.vehicles.len(), 20);
+        for (sample, vehicle) in recording.frames[29].samples.iter().zip(&world.m_Vehicles) {
+            assert_eq!(sample.id, vehicle.id());
+            assert_eq!(sample.position, vehicle.position());
+        }
+
+        let mut csv = vec![];
+        recording.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 30 * 20 + 1);
+
+        let mut player = Player::new(recording);
+        player.StepFrames(10);
+        assert!(player.IsPaused());
+        assert_eq!(player.CurrentFrame(), 10);
+        for (sample, recorded) in player.Samples().iter().zip(&player.Recording().frames[10].samples) {
+            assert_eq!(sample.position, recorded.position);
+        }
+        player.StepFrames(-100);
+        assert_eq!(player.CurrentFrame(), 0);
+    }
+}
@@ -12,9 +12,10 @@
 //
 //------------------------------------------------------------------------
 
+use serde::{Deserialize, Serialize};
 use std::ops::{AddAssign, Div};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Smoother<T> {
     //this holds the history
     m_History: Vec<T>,
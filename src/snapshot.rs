@@ -0,0 +1,138 @@
+//------------------------------------------------------------------------
+//
+//  Saved copies of a GameWorld. A snapshot holds everything needed to
+//  carry on a run exactly where it left off: each vehicle's motion,
+//  heading smoother and steering state, the obstacles, the config and
+//  the world's rng. Restoring one and stepping it gives the same
+//  trajectories the saved world would have had.
+//
+//  GameWorld::Snapshot and GameWorld::FromSnapshot convert to and from
+//  a live world, the methods here read and write the files.
+//
+//------------------------------------------------------------------------
+
+use crate::configuration::ConfigLoader;
+use crate::obstacle::Obstacle;
+use crate::path::Path;
+use crate::smoother::Smoother;
+use crate::steering_behavior::SteeringBehavior;
+use crate::utils::SimRng;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+// written at the front of every file and bumped whenever the layout below
+// changes, so an old file is refused rather than read back wrong
+pub const SNAPSHOT_VERSION: u32 = 8;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub cx: i32,
+    pub cy: i32,
+    pub config: ConfigLoader,
+    pub pending_config: Option<ConfigLoader>,
+    pub seed: u64,
+    pub rng: SimRng,
+    pub crosshair: Vec2,
+    pub cell_space_on: bool,
+    pub accumulator: f32,
+    pub path: Option<Path>,
+    pub obstacles: Vec<Obstacle>,
+    pub vehicles: Vec<VehicleSnapshot>,
+
+    // the indices of the vehicles in each cell of the space partition, in
+    // the order they were added. Neighbors are summed in this order so it
+    // has to be kept for the restored run to match. Empty for the spatial
+    // indices without cells, whose order only depends on the positions.
+    pub cells: Vec<Vec<usize>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VehicleSnapshot {
+    pub id: i32,
+    pub entity_type: i32,
+    pub tag: bool,
+    pub position: Vec2,
+    pub scale: Vec2,
+    pub bounding_radius: f32,
+    pub velocity: Vec2,
+    pub heading: Vec2,
+    pub side_vec: Vec2,
+    pub mass: f32,
+    pub max_speed: f32,
+    pub max_force: f32,
+    pub max_turn_rate: f32,
+
+    // the behavior flags, weights, wander target, summing method, the
+    // indices of the vehicles being pursued, evaded and so on, and the
+    // vehicle's own rng stream
+    pub steering: SteeringBehavior,
+
+    pub heading_smoother: Smoother<Vec2>,
+    pub smoothed_heading: Vec2,
+    pub smoothing_on: bool,
+    pub prev_position: Vec2,
+    pub prev_heading: Vec2,
+    pub height: f32,
+    pub time_elapsed: f32,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    // the file could not be read or written
+    Io(PathBuf, std::io::Error),
+    // the bytes are not a snapshot
+    Format(bincode::Error),
+    // the file was written by a different version of the layout
+    Version(u32),
+    // a steering target or cell refers to a vehicle that is not in the snapshot
+    UnknownVehicle(usize),
+    // the cells don't match the partition the config describes
+    CellCount(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(path, error) => write!(f, "could not access snapshot file {}: {}", path.display(), error),
+            SnapshotError::Format(error) => write!(f, "could not decode snapshot: {}", error),
+            SnapshotError::Version(version) => write!(
+                f,
+                "snapshot is version {} but this build reads version {}",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::UnknownVehicle(index) => write!(f, "snapshot refers to vehicle {} which it does not contain", index),
+            SnapshotError::CellCount(count) => write!(f, "snapshot has {} cells which does not match its config", count),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl WorldSnapshot {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        bincode::serialize(&(SNAPSHOT_VERSION, self)).map_err(SnapshotError::Format)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<WorldSnapshot, SnapshotError> {
+        let version: u32 = bincode::deserialize(bytes).map_err(SnapshotError::Format)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(version));
+        }
+
+        let (_, snapshot): (u32, WorldSnapshot) = bincode::deserialize(bytes).map_err(SnapshotError::Format)?;
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()?).map_err(|error| SnapshotError::Io(path.to_path_buf(), error))
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<WorldSnapshot, SnapshotError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|error| SnapshotError::Io(path.to_path_buf(), error))?;
+        WorldSnapshot::from_bytes(&bytes)
+    }
+}
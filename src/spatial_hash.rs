@@ -0,0 +1,158 @@
+//------------------------------------------------------------------------
+//
+//  SpatialHash
+//
+//  cells of a fixed size keyed by their column and row in a hash map. A
+//  cell only exists while something is in it, so the entities can be
+//  anywhere, not just inside the world, and a sparse school costs no more
+//  than its occupied cells. The world size is only used when it wraps.
+//
+//------------------------------------------------------------------------
+
+use crate::entity_traits::EntityBase;
+use crate::spatial_index::{BoxSearch, SearchBox, SearchNearest, SearchRadius, SpatialIndex, WorldOffset};
+use glam::{vec2, Vec2};
+use std::collections::HashMap;
+
+pub struct SpatialHash {
+    m_Cells: HashMap<(i32, i32), Vec<usize>>,
+
+    // the number of entities in all the cells
+    m_iNumEntities: usize,
+
+    m_vCellSize: Vec2,
+    m_vWorld: Vec2,
+    m_bToroidal: bool,
+}
+
+impl SpatialHash {
+    pub fn new(width: f32, height: f32, cell_size: Vec2) -> SpatialHash {
+        SpatialHash {
+            m_Cells: HashMap::new(),
+            m_iNumEntities: 0,
+            m_vCellSize: cell_size,
+            m_vWorld: vec2(width, height),
+            m_bToroidal: false,
+        }
+    }
+
+    // the column and row of the cell a position is in
+    fn Key(&self, position: Vec2) -> (i32, i32) {
+        let cell = (position / self.m_vCellSize).floor();
+        (cell.x as i32, cell.y as i32)
+    }
+
+    pub fn NumOccupiedCells(&self) -> usize {
+        self.m_Cells.len()
+    }
+}
+
+impl BoxSearch for SpatialHash {
+    fn World(&self) -> Vec2 {
+        self.m_vWorld
+    }
+
+    fn Toroidal(&self) -> bool {
+        self.m_bToroidal
+    }
+
+    fn Search<T: EntityBase>(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        let (left, top) = self.Key(top_left);
+        let (right, bottom) = self.Key(bottom_right);
+
+        let in_box = |entity: &usize| {
+            let position = entities[*entity].position();
+            position.cmpge(top_left).all() && position.cmple(bottom_right).all()
+        };
+
+        // a box covering more cells than are occupied is quicker to answer
+        // by going through the occupied ones
+        let num_keys = (right as i64 - left as i64 + 1) * (bottom as i64 - top as i64 + 1);
+        if num_keys > self.m_Cells.len() as i64 {
+            for ((x, y), members) in &self.m_Cells {
+                if (left..=right).contains(x) && (top..=bottom).contains(y) {
+                    found.extend(members.iter().filter(|entity| in_box(entity)));
+                }
+            }
+        } else {
+            for y in top..=bottom {
+                for x in left..=right {
+                    if let Some(members) = self.m_Cells.get(&(x, y)) {
+                        found.extend(members.iter().filter(|entity| in_box(entity)));
+                    }
+                }
+            }
+        }
+    }
+
+    fn StartRadius(&self, _k: usize) -> f32 {
+        self.m_vCellSize.max_element()
+    }
+
+    fn NumEntities(&self) -> usize {
+        self.m_iNumEntities
+    }
+}
+
+impl<T: EntityBase> SpatialIndex<T> for SpatialHash {
+    fn add_entity(&mut self, entity: usize, position: Vec2) {
+        let key = self.Key(position);
+        self.m_Cells.entry(key).or_default().push(entity);
+        self.m_iNumEntities += 1;
+    }
+
+    fn UpdateEntity(&mut self, entity: usize, old_position: &Vec2, new_position: &Vec2) {
+        let old_key = self.Key(*old_position);
+        let new_key = self.Key(*new_position);
+
+        if new_key == old_key {
+            return;
+        }
+
+        // empty cells are dropped so only the occupied ones are kept
+        if let Some(members) = self.m_Cells.get_mut(&old_key) {
+            members.retain(|&member| member != entity);
+            if members.is_empty() {
+                self.m_Cells.remove(&old_key);
+            }
+        }
+
+        self.m_Cells.entry(new_key).or_default().push(entity);
+    }
+
+    fn EmptyCells(&mut self) {
+        self.m_Cells.clear();
+        self.m_iNumEntities = 0;
+    }
+
+    fn SetToroidal(&mut self, toroidal: bool) {
+        self.m_bToroidal = toroidal;
+    }
+
+    fn IsToroidal(&self) -> bool {
+        self.m_bToroidal
+    }
+
+    fn Offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        WorldOffset(from, to, self.m_vWorld, self.m_bToroidal)
+    }
+
+    fn CalculateNeighbors(&self, entities: &[T], target_pos: Vec2, query_radius: f32, neighbors: &mut Vec<usize>) {
+        SearchRadius(self, entities, target_pos, query_radius, neighbors);
+    }
+
+    fn CalculateEntitiesInBox(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        SearchBox(self, entities, top_left, bottom_right, found);
+    }
+
+    fn CalculateNearestNeighbors(
+        &self,
+        entities: &[T],
+        target_pos: Vec2,
+        k: usize,
+        include: &dyn Fn(usize) -> bool,
+        neighbors: &mut Vec<usize>,
+    ) {
+        SearchNearest(self, entities, target_pos, k, include, neighbors);
+    }
+}
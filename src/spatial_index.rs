@@ -0,0 +1,500 @@
+//------------------------------------------------------------------------
+//
+//  Spatial indices. The neighbor queries of the steering go through the
+//  SpatialIndex trait, and the SpatialIndex key of the config picks the
+//  structure that answers them:
+//
+//      grid           CellSpacePartition, NumCellsX by NumCellsY cells
+//                     laid over the world
+//      spatial_hash   cells of the same size kept in a hash map, so only
+//                     the occupied ones cost anything and the entities
+//                     may go anywhere
+//      kd_tree        a k-d tree, rebuilt after the entities have moved
+//      brute_force    looks at every entity. The others are tested
+//                     against it
+//
+//  Every backend finds the same entities. The grid gives them in cell
+//  order, which depends on the order they arrived in their cells, the
+//  others in index order, which depends only on where they are.
+//
+//------------------------------------------------------------------------
+
+use crate::cell_space_partition::CellSpacePartition;
+use crate::configuration::ConfigLoader;
+use crate::entity_traits::EntityBase;
+use crate::kd_tree::KdTree;
+use crate::spatial_hash::SpatialHash;
+use crate::utils::WrappedOffset;
+use glam::{vec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SpatialIndexType {
+    grid,
+    spatial_hash,
+    kd_tree,
+    brute_force,
+}
+
+/* NOTE: an index holds entities by their index in the caller's list of
+entities, e.g. GameWorld::m_Vehicles, and is handed the list for queries.
+The queries only read the index so any number can run at once. Each fills
+a buffer owned by the caller, which is cleared first.
+*/
+pub trait SpatialIndex<T: EntityBase>: Send + Sync {
+    fn add_entity(&mut self, entity: usize, position: Vec2);
+
+    // tells the index an entity has moved
+    fn UpdateEntity(&mut self, entity: usize, old_position: &Vec2, new_position: &Vec2);
+
+    // called once the entities have been added or moved, before they are
+    // queried again. Only an index that is rebuilt rather than updated in
+    // place has anything to do here.
+    fn Refresh(&mut self) {}
+
+    // removes every entity
+    fn EmptyCells(&mut self);
+
+    fn SetToroidal(&mut self, toroidal: bool);
+
+    fn IsToroidal(&self) -> bool;
+
+    // the displacement from one position to another, the short way round
+    // when the space wraps
+    fn Offset(&self, from: Vec2, to: Vec2) -> Vec2;
+
+    // fills neighbors with the entities within query_radius of target_pos
+    fn CalculateNeighbors(&self, entities: &[T], target_pos: Vec2, query_radius: f32, neighbors: &mut Vec<usize>);
+
+    // fills found with the entities within the box from top_left to
+    // bottom_right, edges included
+    fn CalculateEntitiesInBox(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>);
+
+    // fills neighbors with the k entities nearest to target_pos, nearest
+    // first, leaving out any for which include returns false. Entities at
+    // the same distance are taken in index order.
+    fn CalculateNearestNeighbors(
+        &self,
+        entities: &[T],
+        target_pos: Vec2,
+        k: usize,
+        include: &dyn Fn(usize) -> bool,
+        neighbors: &mut Vec<usize>,
+    );
+
+    // the members of each cell in the order they arrived, for a snapshot to
+    // keep. Only an index whose results come in that order has any.
+    fn Cells(&self) -> Vec<Vec<usize>> {
+        vec![]
+    }
+
+    // puts back what Cells gave, or adds the entities where they are when
+    // the index has no cells. False if the cells don't fit this index.
+    fn RestoreCells(&mut self, cells: &[Vec<usize>], entities: &[T]) -> bool {
+        if !cells.is_empty() {
+            return false;
+        }
+
+        self.EmptyCells();
+        for (index, entity) in entities.iter().enumerate() {
+            self.add_entity(index, entity.position());
+        }
+        self.Refresh();
+
+        true
+    }
+
+    #[cfg(feature = "render")]
+    fn render_cells(&self) {}
+}
+
+// an index of the type the config asks for over a world of width by height
+pub fn CreateSpatialIndex<T: EntityBase + 'static>(config: &ConfigLoader, width: f32, height: f32) -> Box<dyn SpatialIndex<T>> {
+    let mut index: Box<dyn SpatialIndex<T>> = match config.SpatialIndex {
+        SpatialIndexType::grid => Box::new(CellSpacePartition::new(width, height, config.NumCellsX, config.NumCellsY)),
+        SpatialIndexType::spatial_hash => Box::new(SpatialHash::new(
+            width,
+            height,
+            vec2(width / config.NumCellsX as f32, height / config.NumCellsY as f32),
+        )),
+        SpatialIndexType::kd_tree => Box::new(KdTree::new(width, height)),
+        SpatialIndexType::brute_force => Box::new(BruteForce::new(width, height)),
+    };
+
+    index.SetToroidal(config.ToroidalNeighbors);
+    index
+}
+
+// the displacement from one position to another in a world of the given
+// size, the short way round when it wraps
+pub fn WorldOffset(from: Vec2, to: Vec2, world: Vec2, toroidal: bool) -> Vec2 {
+    if toroidal {
+        WrappedOffset(from, to, world)
+    } else {
+        to - from
+    }
+}
+
+// the copies of the world a query has to look into. Just the world itself,
+// or when it wraps the eight around it as well so a query reaching over an
+// edge comes back in on the other side.
+pub fn WorldShifts(world: Vec2, toroidal: bool) -> impl Iterator<Item = Vec2> + Clone {
+    let range = if toroidal { -1..=1 } else { 0..=0 };
+
+    range
+        .clone()
+        .flat_map(move |y| range.clone().map(move |x| vec2(x as f32 * world.x, y as f32 * world.y)))
+}
+
+//------------------------- WidenToNearest -------------------------------
+//
+//  the nearest neighbor search every index uses. Neighbors within a radius
+//  are looked for, starting at start_radius and doubling it until at least
+//  k are found or the radius takes in all num_entities the index holds,
+//  however far outside the world they are. They are then sorted by
+//  distance and cut down to k. within fills its buffer with the entities in
+//  a radius, distance_squared measures the distance to one of them.
+//------------------------------------------------------------------------
+pub(crate) fn WidenToNearest(
+    k: usize,
+    include: &dyn Fn(usize) -> bool,
+    start_radius: f32,
+    num_entities: usize,
+    within: impl Fn(f32, &mut Vec<usize>),
+    distance_squared: impl Fn(usize) -> f32,
+    neighbors: &mut Vec<usize>,
+) {
+    neighbors.clear();
+    if k == 0 {
+        return;
+    }
+
+    let mut query_radius = start_radius;
+
+    loop {
+        within(query_radius, neighbors);
+        let found_all = neighbors.len() >= num_entities;
+        neighbors.retain(|&entity| include(entity));
+
+        // with k found inside the radius none outside it can be nearer. An
+        // infinite radius takes in all but entities at no real position.
+        if neighbors.len() >= k || found_all || query_radius.is_infinite() {
+            break;
+        }
+
+        query_radius *= 2.0;
+    }
+
+    neighbors.sort_by(|&a, &b| distance_squared(a).total_cmp(&distance_squared(b)).then(a.cmp(&b)));
+    neighbors.truncate(k);
+}
+
+/* NOTE: the backends other than the grid only need to find the entities in
+a box of a world that doesn't wrap, which BoxSearch asks of them. The
+queries below are built on that, looking into each shifted copy of the
+world when it wraps and giving the results in index order.
+*/
+pub(crate) trait BoxSearch {
+    fn World(&self) -> Vec2;
+
+    fn Toroidal(&self) -> bool;
+
+    // adds the entities within the box, edges included, to found
+    fn Search<T: EntityBase>(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>);
+
+    // a radius to start the nearest neighbor search from
+    fn StartRadius(&self, k: usize) -> f32;
+
+    // how many entities the index holds
+    fn NumEntities(&self) -> usize;
+}
+
+pub(crate) fn SearchBox<T: EntityBase>(index: &impl BoxSearch, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+    found.clear();
+    for shift in WorldShifts(index.World(), index.Toroidal()) {
+        index.Search(entities, top_left + shift, bottom_right + shift, found);
+    }
+
+    // a box wider than the world finds an entity in more than one copy
+    found.sort_unstable();
+    found.dedup();
+}
+
+pub(crate) fn SearchRadius<T: EntityBase>(
+    index: &impl BoxSearch,
+    entities: &[T],
+    target_pos: Vec2,
+    query_radius: f32,
+    neighbors: &mut Vec<usize>,
+) {
+    let (world, toroidal) = (index.World(), index.Toroidal());
+    let query_radius_squared = query_radius * query_radius;
+
+    SearchBox(
+        index,
+        entities,
+        target_pos - vec2(query_radius, query_radius),
+        target_pos + vec2(query_radius, query_radius),
+        neighbors,
+    );
+    neighbors
+        .retain(|&entity| WorldOffset(target_pos, entities[entity].position(), world, toroidal).length_squared() < query_radius_squared);
+}
+
+pub(crate) fn SearchNearest<T: EntityBase>(
+    index: &impl BoxSearch,
+    entities: &[T],
+    target_pos: Vec2,
+    k: usize,
+    include: &dyn Fn(usize) -> bool,
+    neighbors: &mut Vec<usize>,
+) {
+    let (world, toroidal) = (index.World(), index.Toroidal());
+
+    WidenToNearest(
+        k,
+        include,
+        index.StartRadius(k),
+        index.NumEntities(),
+        |query_radius, found| SearchRadius(index, entities, target_pos, query_radius, found),
+        |entity| WorldOffset(target_pos, entities[entity].position(), world, toroidal).length_squared(),
+        neighbors,
+    );
+}
+
+//------------------------------------------------------------------------
+//
+//  BruteForce
+//
+//  checks every entity it holds for every query. Slow with many entities
+//  but too simple to be wrong, so it is what the others are tested against.
+//------------------------------------------------------------------------
+pub struct BruteForce {
+    m_Members: Vec<usize>,
+    m_vWorld: Vec2,
+    m_bToroidal: bool,
+}
+
+impl BruteForce {
+    pub fn new(width: f32, height: f32) -> BruteForce {
+        BruteForce {
+            m_Members: vec![],
+            m_vWorld: vec2(width, height),
+            m_bToroidal: false,
+        }
+    }
+}
+
+impl BoxSearch for BruteForce {
+    fn World(&self) -> Vec2 {
+        self.m_vWorld
+    }
+
+    fn Toroidal(&self) -> bool {
+        self.m_bToroidal
+    }
+
+    fn Search<T: EntityBase>(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        found.extend(self.m_Members.iter().copied().filter(|&entity| {
+            let position = entities[entity].position();
+            position.cmpge(top_left).all() && position.cmple(bottom_right).all()
+        }));
+    }
+
+    // everything is looked at anyway, so in one go
+    fn StartRadius(&self, _k: usize) -> f32 {
+        self.m_vWorld.length()
+    }
+
+    fn NumEntities(&self) -> usize {
+        self.m_Members.len()
+    }
+}
+
+impl<T: EntityBase> SpatialIndex<T> for BruteForce {
+    fn add_entity(&mut self, entity: usize, _position: Vec2) {
+        self.m_Members.push(entity);
+    }
+
+    fn UpdateEntity(&mut self, _entity: usize, _old_position: &Vec2, _new_position: &Vec2) {}
+
+    fn EmptyCells(&mut self) {
+        self.m_Members.clear();
+    }
+
+    fn SetToroidal(&mut self, toroidal: bool) {
+        self.m_bToroidal = toroidal;
+    }
+
+    fn IsToroidal(&self) -> bool {
+        self.m_bToroidal
+    }
+
+    fn Offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        WorldOffset(from, to, self.m_vWorld, self.m_bToroidal)
+    }
+
+    fn CalculateNeighbors(&self, entities: &[T], target_pos: Vec2, query_radius: f32, neighbors: &mut Vec<usize>) {
+        SearchRadius(self, entities, target_pos, query_radius, neighbors);
+    }
+
+    fn CalculateEntitiesInBox(&self, entities: &[T], top_left: Vec2, bottom_right: Vec2, found: &mut Vec<usize>) {
+        SearchBox(self, entities, top_left, bottom_right, found);
+    }
+
+    fn CalculateNearestNeighbors(
+        &self,
+        entities: &[T],
+        target_pos: Vec2,
+        k: usize,
+        include: &dyn Fn(usize) -> bool,
+        neighbors: &mut Vec<usize>,
+    ) {
+        SearchNearest(self, entities, target_pos, k, include, neighbors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell_space_partition::CellSpacePartition;
+    use crate::entity_traits::EntityBase;
+    use crate::kd_tree::KdTree;
+    use crate::obstacle::Obstacle;
+    use crate::spatial_hash::SpatialHash;
+    use crate::spatial_index::{BruteForce, SpatialIndex};
+    use crate::utils::{RandInRange, SimRng};
+    use glam::{vec2, Vec2};
+    use rand::SeedableRng;
+
+    // every backend finds what brute force finds, for a sparse and a dense
+    // school, flat and wrapping, before and after the entities have moved
+    #[test]
+    pub fn test_backends_match_brute_force() {
+        let (width, height) = (1000.0, 800.0);
+        let mut rng = SimRng::seed_from_u64(5);
+
+        for count in [30, 1500] {
+            let mut entities: Vec<Obstacle> = (0..count)
+                .map(|_| Obstacle::new(vec2(RandInRange(&mut rng, 0.0, width), RandInRange(&mut rng, 0.0, height)), 1.0))
+                .collect();
+
+            for toroidal in [false, true] {
+                let mut indices: Vec<Box<dyn SpatialIndex<Obstacle>>> = vec![
+                    Box::new(BruteForce::new(width, height)),
+                    Box::new(CellSpacePartition::new(width, height, 7, 7)),
+                    Box::new(SpatialHash::new(width, height, vec2(60.0, 45.0))),
+                    Box::new(KdTree::new(width, height)),
+                ];
+                for index in &mut indices {
+                    index.SetToroidal(toroidal);
+                    for (i, entity) in entities.iter().enumerate() {
+                        index.add_entity(i, entity.position());
+                    }
+                    index.Refresh();
+                }
+
+                for moved in [false, true] {
+                    if moved {
+                        for (i, entity) in entities.iter_mut().enumerate() {
+                            let old_position = entity.position();
+                            let new_position = vec2(
+                                (old_position.x + RandInRange(&mut rng, -80.0, 80.0)).rem_euclid(width),
+                                (old_position.y + RandInRange(&mut rng, -80.0, 80.0)).rem_euclid(height),
+                            );
+                            entity.position = new_position;
+                            for index in &mut indices {
+                                index.UpdateEntity(i, &old_position, &new_position);
+                            }
+                        }
+                        for index in &mut indices {
+                            index.Refresh();
+                        }
+                    }
+
+                    let queries = |index: &dyn SpatialIndex<Obstacle>| {
+                        let mut results: Vec<Vec<usize>> = vec![];
+                        let mut found = vec![];
+                        for target_index in [0, count / 2, count - 1] {
+                            let target = entities[target_index].position();
+                            for radius in [15.0, 50.0, 200.0] {
+                                index.CalculateNeighbors(&entities, target, radius, &mut found);
+                                found.sort();
+                                results.push(found.clone());
+                            }
+                            for k in [1, 7, 40] {
+                                index.CalculateNearestNeighbors(&entities, target, k, &|i| i != target_index, &mut found);
+                                results.push(found.clone());
+                            }
+                        }
+                        for (top_left, bottom_right) in [(vec2(100.0, 100.0), vec2(400.0, 300.0)), (vec2(-40.0, 700.0), vec2(60.0, 820.0))]
+                        {
+                            index.CalculateEntitiesInBox(&entities, top_left, bottom_right, &mut found);
+                            found.sort();
+                            results.push(found.clone());
+                        }
+                        results
+                    };
+
+                    let expected = queries(indices[0].as_ref());
+                    for index in &indices[1..] {
+                        assert_eq!(
+                            queries(index.as_ref()),
+                            expected,
+                            "{} entities toroidal {} moved {}",
+                            count,
+                            toroidal,
+                            moved
+                        );
+                    }
+                    assert_eq!(
+                        indices[1].Offset(Vec2::ZERO, vec2(990.0, 0.0)).x,
+                        if toroidal { -10.0 } else { 990.0 }
+                    );
+                }
+            }
+        }
+    }
+
+    // the nearest neighbors are found however far outside the world they
+    // are, for the spatial hash that's where they may well be
+    #[test]
+    pub fn test_nearest_outside_world() {
+        let entities: Vec<Obstacle> = [vec2(10.0, 10.0), vec2(20.0, 10.0), vec2(5000.0, 4000.0), vec2(-3000.0, 20.0)]
+            .into_iter()
+            .map(|position| Obstacle::new(position, 1.0))
+            .collect();
+
+        let indices: Vec<Box<dyn SpatialIndex<Obstacle>>> = vec![
+            Box::new(BruteForce::new(100.0, 100.0)),
+            Box::new(CellSpacePartition::new(100.0, 100.0, 4, 4)),
+            Box::new(SpatialHash::new(100.0, 100.0, vec2(10.0, 10.0))),
+            Box::new(KdTree::new(100.0, 100.0)),
+        ];
+
+        let mut found = vec![];
+        for mut index in indices {
+            for (i, entity) in entities.iter().enumerate() {
+                index.add_entity(i, entity.position());
+            }
+            index.Refresh();
+
+            index.CalculateNearestNeighbors(&entities, entities[0].position(), 3, &|i| i != 0, &mut found);
+            assert_eq!(found, vec![1, 3, 2]);
+
+            // asking for more than there are gives them all
+            index.CalculateNearestNeighbors(&entities, entities[0].position(), 10, &|i| i != 0, &mut found);
+            assert_eq!(found, vec![1, 3, 2]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "KdTree queried before Refresh")]
+    pub fn test_kd_tree_needs_refresh() {
+        let entities = vec![Obstacle::new(vec2(10.0, 10.0), 1.0)];
+        let mut index = KdTree::new(100.0, 100.0);
+        SpatialIndex::<Obstacle>::add_entity(&mut index, 0, entities[0].position());
+
+        let mut found = vec![];
+        index.CalculateNeighbors(&entities, vec2(0.0, 0.0), 50.0, &mut found);
+    }
+}
@@ -1,17 +1,21 @@
 //--------------------------- Constants ----------------------------------
 
-use crate::configuration::CONFIG;
-use crate::entity_traits::{EntityBase, EntityMovable, EntitySteerable};
+use crate::configuration::ConfigLoader;
+use crate::entity_functions::WithinRange;
+use crate::entity_traits::{EntityBase, EntityMovable};
+use crate::geometry::LineIntersection2D;
+use crate::obstacle::Obstacle;
 use crate::path::Path;
-use crate::transformations::PointToWorldSpace;
-use crate::utils::{min, RandFloat, RandInRange, RandomClamped};
+use crate::spatial_index::SpatialIndex;
+use crate::transformations::{PointToLocalSpace, PointToWorldSpace, Vec2DRotateAroundOrigin, VectorToWorldSpace};
+use crate::utils::{min, RandFloat, RandInRange, RandomClamped, SimRng, Truncate};
 use crate::vehicle::Vehicle;
 use crate::wall_2d::Wall2D;
 use glam::{vec2, Vec2};
-use std::cell::{Ref, RefCell};
-use std::f32::consts::TAU;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{FRAC_PI_2, TAU};
 use std::ops::Div;
-use std::rc::Rc;
 
 //the radius of the constraining circle for the wander behavior
 const WANDER_RAD: f32 = 1.2;
@@ -23,20 +27,31 @@ const WANDER_JITTER_PER_SEC: f32 = 80.0;
 const WAYPOINT_SEEK_DIST: f32 = 20.0;
 
 //------------------------------------------------------------------------
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Deceleration {
     slow = 3,
     normal = 2,
     fast = 1,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SummingMethod {
     weighted_average,
     prioritized,
     dithered,
 }
 
+// which of the other vehicles the flocking behaviors steer by
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NeighborhoodMode {
+    // every vehicle within view distance
+    metric,
+    // the nearest few however far away they are
+    k_nearest,
+    // the nearest few of those within view distance
+    hybrid,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum BehaviorType {
     none = 0x00000,
@@ -58,13 +73,59 @@ pub enum BehaviorType {
     offset_pursuit = 0x10000,
 }
 
+//------------------------------------------------------------------------
+//
+//  SteeringContext
+//
+//  the world as every vehicle's steering sees it during a step of
+//  GameWorld::Step. It is read only and shared by all the vehicles while
+//  their forces are calculated in parallel, so a behavior only ever writes
+//  to its own SteeringBehavior. The vehicle being steered is
+//  vehicles[index].
+//------------------------------------------------------------------------
+pub struct SteeringContext<'a> {
+    pub vehicles: &'a [Vehicle],
+    pub obstacles: &'a [Obstacle],
+    pub walls: &'a [Wall2D],
+    pub cell_space: &'a dyn SpatialIndex<Vehicle>,
+    pub crosshair: Vec2,
+    pub config: &'a ConfigLoader,
+    pub time_elapsed: f32,
+}
+
+impl SteeringContext<'_> {
+    // the displacement from one position to another. The spatial index knows
+    // whether the world wraps, so the flocking behaviors measure the same
+    // way with or without it being used for the neighbor search.
+    pub fn Offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        self.cell_space.Offset(from, to)
+    }
+
+    // true if the neighbor is within range of position. Like the spatial
+    // index it measures to the neighbor's centre, not its bounding radius.
+    pub fn WithinRange(&self, position: Vec2, neighbor: &Vehicle, range: f32) -> bool {
+        self.Offset(position, neighbor.position()).length_squared() < range * range
+    }
+}
+
+// the whole behavior is saved in world snapshots, apart from the feelers
+// and neighbors which are rebuilt each update
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SteeringBehavior {
     // the steering force created by the combined effect of all the selected behaviors
     pub m_vSteeringForce: Vec2,
 
-    // these can be used to keep track of friends, pursuers, or prey
-    m_pTargetAgent1: Option<Rc<RefCell<Vehicle>>>,
-    m_pTargetAgent2: Option<Rc<RefCell<Vehicle>>>,
+    // the pursuer or prey of evade and pursuit. Like the other vehicles
+    // below it is an index into GameWorld::m_Vehicles.
+    m_pTargetAgent1: Option<usize>,
+
+    // the vehicles the other behaviors steer by have slots of their own, so
+    // switching one of them on doesn't retarget evade or pursuit. Offset
+    // pursuit keeps station on the leader, interpose gets between the two
+    // agents and hide keeps out of sight of the hunter.
+    m_pLeader: Option<usize>,
+    m_pInterposeAgents: Option<(usize, usize)>,
+    m_pHunter: Option<usize>,
 
     // the current target
     pub m_vTarget: Vec2,
@@ -73,8 +134,20 @@ pub struct SteeringBehavior {
     m_dDBoxLength: f32,
 
     // a vertex buffer to contain the feelers rqd for wall avoidance
+    #[serde(skip)]
     m_Feelers: Vec<Vec2>,
 
+    // the neighbors the flocking behaviors steer by, found by
+    // CalculateNeighborhood and kept here so each vehicle has its own list
+    // to fill
+    #[serde(skip)]
+    m_Neighbors: Vec<usize>,
+
+    // how the neighbors are picked, and how many the k_nearest and hybrid
+    // modes take
+    m_Neighborhood: NeighborhoodMode,
+    m_iNumNearestNeighbors: usize,
+
     // the length of the 'feeler/s' used in wall detection
     m_dWallDetectionFeelerLength: f32,
 
@@ -133,12 +206,16 @@ pub struct SteeringBehavior {
 
     // what type of method is used to sum any active behavior
     m_SummingMethod: SummingMethod,
+
+    // this vehicle's own random stream, seeded from the world's rng. Wander
+    // jitter and the dithered summing method draw from it.
+    m_Rng: SimRng,
 }
 
 impl SteeringBehavior {
-    pub fn new() -> Self {
+    pub fn new(config: &ConfigLoader, rng: &mut SimRng) -> Self {
         let wander_radius = WANDER_RAD;
-        let theta = RandFloat() * TAU;
+        let theta = RandFloat(rng) * TAU;
         let wander_target = vec2(wander_radius * theta.cos(), wander_radius * theta.sin());
 
         let mut path = Path::default();
@@ -146,35 +223,41 @@ impl SteeringBehavior {
 
         SteeringBehavior {
             m_iFlags: 0,
-            m_dDBoxLength: CONFIG.MinDetectionBoxLength,
-            m_dWeightCohesion: CONFIG.CohesionWeight,
-            m_dWeightAlignment: CONFIG.AlignmentWeight,
-            m_dWeightSeparation: CONFIG.SeparationWeight,
-            m_dWeightObstacleAvoidance: CONFIG.ObstacleAvoidanceWeight,
-            m_dWeightWander: CONFIG.WanderWeight,
-            m_dWeightWallAvoidance: CONFIG.WallAvoidanceWeight,
-            m_dViewDistance: CONFIG.ViewDistance,
-            m_dWallDetectionFeelerLength: CONFIG.WallDetectionFeelerLength,
-            m_Feelers: vec![], // 3, ?
+            m_dDBoxLength: config.MinDetectionBoxLength,
+            m_dWeightCohesion: config.CohesionWeight,
+            m_dWeightAlignment: config.AlignmentWeight,
+            m_dWeightSeparation: config.SeparationWeight,
+            m_dWeightObstacleAvoidance: config.ObstacleAvoidanceWeight,
+            m_dWeightWander: config.WanderWeight,
+            m_dWeightWallAvoidance: config.WallAvoidanceWeight,
+            m_dViewDistance: config.ViewDistance,
+            m_dWallDetectionFeelerLength: config.WallDetectionFeelerLength,
+            m_Feelers: Vec::with_capacity(3),
+            m_Neighbors: vec![],
+            m_Neighborhood: config.Neighborhood,
+            m_iNumNearestNeighbors: config.NumNearestNeighbors as usize,
             m_Deceleration: Deceleration::normal,
             m_pTargetAgent1: None,
-            m_pTargetAgent2: None,
+            m_pLeader: None,
+            m_pInterposeAgents: None,
+            m_pHunter: None,
             m_dWanderDistance: WANDER_DIST,
             m_dWanderJitter: WANDER_JITTER_PER_SEC,
             m_dWanderRadius: wander_radius,
             wander_direction_time: 0.0,
             m_dWaypointSeekDistSq: WAYPOINT_SEEK_DIST * WAYPOINT_SEEK_DIST,
-            m_dWeightSeek: CONFIG.SeekWeight,
-            m_dWeightFlee: CONFIG.FleeWeight,
-            m_dWeightArrive: CONFIG.ArriveWeight,
-            m_dWeightPursuit: CONFIG.PursuitWeight,
-            m_dWeightOffsetPursuit: CONFIG.OffsetPursuitWeight,
-            m_dWeightInterpose: CONFIG.InterposeWeight,
-            m_dWeightHide: CONFIG.HideWeight,
-            m_dWeightEvade: CONFIG.EvadeWeight,
-            m_dWeightFollowPath: CONFIG.FollowPathWeight,
+            m_dWeightSeek: config.SeekWeight,
+            m_dWeightFlee: config.FleeWeight,
+            m_dWeightArrive: config.ArriveWeight,
+            m_dWeightPursuit: config.PursuitWeight,
+            m_dWeightOffsetPursuit: config.OffsetPursuitWeight,
+            m_dWeightInterpose: config.InterposeWeight,
+            m_dWeightHide: config.HideWeight,
+            m_dWeightEvade: config.EvadeWeight,
+            m_dWeightFollowPath: config.FollowPathWeight,
             m_bCellSpaceOn: false,
             m_SummingMethod: SummingMethod::prioritized,
+            m_Rng: SimRng::seed_from_u64(rng.gen()),
             m_vWanderTarget: wander_target,
             m_pPath: path,
             m_vSteeringForce: Default::default(),
@@ -183,6 +266,36 @@ impl SteeringBehavior {
         }
     }
 
+    //------------------------------ ApplyConfig -----------------------------
+    //
+    //  takes the behavior weights and ranges from a reloaded config. The
+    //  neighborhood can be set for each vehicle, so GameWorld::ApplyConfig
+    //  only passes it on when the config changes it.
+    //------------------------------------------------------------------------
+    pub fn ApplyConfig(&mut self, config: &ConfigLoader) {
+        self.m_dWeightCohesion = config.CohesionWeight;
+        self.m_dWeightAlignment = config.AlignmentWeight;
+        self.m_dWeightSeparation = config.SeparationWeight;
+        self.m_dWeightObstacleAvoidance = config.ObstacleAvoidanceWeight;
+        self.m_dWeightWander = config.WanderWeight;
+        self.m_dWeightWallAvoidance = config.WallAvoidanceWeight;
+        self.m_dWeightSeek = config.SeekWeight;
+        self.m_dWeightFlee = config.FleeWeight;
+        self.m_dWeightArrive = config.ArriveWeight;
+        self.m_dWeightPursuit = config.PursuitWeight;
+        self.m_dWeightOffsetPursuit = config.OffsetPursuitWeight;
+        self.m_dWeightInterpose = config.InterposeWeight;
+        self.m_dWeightHide = config.HideWeight;
+        self.m_dWeightEvade = config.EvadeWeight;
+        self.m_dWeightFollowPath = config.FollowPathWeight;
+        self.m_dViewDistance = config.ViewDistance;
+        self.m_dWallDetectionFeelerLength = config.WallDetectionFeelerLength;
+    }
+
+    pub fn ViewDistance(&self) -> f32 {
+        self.m_dViewDistance
+    }
+
     pub fn FlockingOn(&mut self) {
         self.CohesionOn();
         self.AlignmentOn();
@@ -197,25 +310,157 @@ impl SteeringBehavior {
         self.WanderOff();
     }
 
-    /*
-    void FleeOn(){m_iFlags |= flee;}
-    void SeekOn(){m_iFlags |= seek;}
-    void ArriveOn(){m_iFlags |= arrive;}
-     */
+    pub fn FleeOn(&mut self) {
+        self.m_iFlags |= BehaviorType::flee as i32;
+    }
+
+    pub fn FleeOff(&mut self) {
+        if self.On(BehaviorType::flee) {
+            self.m_iFlags ^= BehaviorType::flee as i32;
+        }
+    }
+
+    pub fn SeekOn(&mut self) {
+        self.m_iFlags |= BehaviorType::seek as i32;
+    }
+
+    pub fn SeekOff(&mut self) {
+        if self.On(BehaviorType::seek) {
+            self.m_iFlags ^= BehaviorType::seek as i32;
+        }
+    }
+
+    pub fn ArriveOn(&mut self) {
+        self.m_iFlags |= BehaviorType::arrive as i32;
+    }
+
+    pub fn ArriveOff(&mut self) {
+        if self.On(BehaviorType::arrive) {
+            self.m_iFlags ^= BehaviorType::arrive as i32;
+        }
+    }
+
+    pub fn SetDeceleration(&mut self, deceleration: Deceleration) {
+        self.m_Deceleration = deceleration;
+    }
 
     pub fn WanderOn(&mut self) {
         self.m_iFlags |= BehaviorType::wander as i32;
     }
-    pub fn PursuitOn(&mut self, target: Rc<RefCell<Vehicle>>) {
+    pub fn PursuitOn(&mut self, target: usize) {
         self.m_iFlags |= BehaviorType::pursuit as i32;
         self.m_pTargetAgent1 = Some(target);
     }
 
-    pub fn EvadeOn(&mut self, target: Rc<RefCell<Vehicle>>) {
+    pub fn PursuitOff(&mut self) {
+        if self.On(BehaviorType::pursuit) {
+            self.m_iFlags ^= BehaviorType::pursuit as i32;
+        }
+    }
+
+    pub fn EvadeOff(&mut self) {
+        if self.On(BehaviorType::evade) {
+            self.m_iFlags ^= BehaviorType::evade as i32;
+        }
+    }
+
+    pub fn InterposeOn(&mut self, agent_a: usize, agent_b: usize) {
+        self.m_iFlags |= BehaviorType::interpose as i32;
+        self.m_pInterposeAgents = Some((agent_a, agent_b));
+    }
+
+    pub fn InterposeOff(&mut self) {
+        if self.On(BehaviorType::interpose) {
+            self.m_iFlags ^= BehaviorType::interpose as i32;
+        }
+    }
+
+    pub fn HideOn(&mut self, hunter: usize) {
+        self.m_iFlags |= BehaviorType::hide as i32;
+        self.m_pHunter = Some(hunter);
+    }
+
+    pub fn HideOff(&mut self) {
+        if self.On(BehaviorType::hide) {
+            self.m_iFlags ^= BehaviorType::hide as i32;
+        }
+    }
+
+    pub fn isInterposeOn(&self) -> bool {
+        self.On(BehaviorType::interpose)
+    }
+
+    pub fn isHideOn(&self) -> bool {
+        self.On(BehaviorType::hide)
+    }
+
+    pub fn OffsetPursuitOn(&mut self, leader: usize, offset: Vec2) {
+        self.m_iFlags |= BehaviorType::offset_pursuit as i32;
+        self.m_vOffset = offset;
+        self.m_pLeader = Some(leader);
+    }
+
+    pub fn OffsetPursuitOff(&mut self) {
+        if self.On(BehaviorType::offset_pursuit) {
+            self.m_iFlags ^= BehaviorType::offset_pursuit as i32;
+        }
+    }
+
+    pub fn SetOffset(&mut self, offset: Vec2) {
+        self.m_vOffset = offset;
+    }
+
+    pub fn GetOffset(&self) -> Vec2 {
+        self.m_vOffset
+    }
+
+    pub fn EvadeOn(&mut self, target: usize) {
         self.m_iFlags |= BehaviorType::evade as i32;
         self.m_pTargetAgent1 = Some(target);
     }
 
+    pub fn FollowPathOn(&mut self) {
+        self.m_iFlags |= BehaviorType::follow_path as i32;
+    }
+
+    pub fn FollowPathOff(&mut self) {
+        if self.On(BehaviorType::follow_path) {
+            self.m_iFlags ^= BehaviorType::follow_path as i32;
+        }
+    }
+
+    pub fn SetPath(&mut self, new_path: Vec<Vec2>) {
+        self.m_pPath.Set(new_path);
+    }
+
+    pub fn CreateRandomPath(&mut self, rng: &mut SimRng, num_waypoints: i32, min_x: f32, min_y: f32, max_x: f32, max_y: f32) {
+        self.m_pPath.CreateRandomPath(rng, num_waypoints, min_x, min_y, max_x, max_y);
+    }
+
+    pub fn GetPath(&mut self) -> &mut Path {
+        &mut self.m_pPath
+    }
+
+    pub fn WallAvoidanceOn(&mut self) {
+        self.m_iFlags |= BehaviorType::wall_avoidance as i32;
+    }
+
+    pub fn WallAvoidanceOff(&mut self) {
+        if self.On(BehaviorType::wall_avoidance) {
+            self.m_iFlags ^= BehaviorType::wall_avoidance as i32;
+        }
+    }
+
+    pub fn ObstacleAvoidanceOn(&mut self) {
+        self.m_iFlags |= BehaviorType::obstacle_avoidance as i32;
+    }
+
+    pub fn ObstacleAvoidanceOff(&mut self) {
+        if self.On(BehaviorType::obstacle_avoidance) {
+            self.m_iFlags ^= BehaviorType::obstacle_avoidance as i32;
+        }
+    }
+
     pub fn CohesionOn(&mut self) {
         self.m_iFlags |= BehaviorType::cohesion as i32;
     }
@@ -226,58 +471,117 @@ impl SteeringBehavior {
         self.m_iFlags |= BehaviorType::alignment as i32;
     }
     pub fn CohesionOff(&mut self) {
-        self.m_iFlags ^= BehaviorType::cohesion as i32;
+        if self.On(BehaviorType::cohesion) {
+            self.m_iFlags ^= BehaviorType::cohesion as i32;
+        }
     }
     pub fn SeparationOff(&mut self) {
-        self.m_iFlags ^= BehaviorType::separation as i32;
+        if self.On(BehaviorType::separation) {
+            self.m_iFlags ^= BehaviorType::separation as i32;
+        }
     }
     pub fn AlignmentOff(&mut self) {
-        self.m_iFlags ^= BehaviorType::alignment as i32;
+        if self.On(BehaviorType::alignment) {
+            self.m_iFlags ^= BehaviorType::alignment as i32;
+        }
     }
     pub fn WanderOff(&mut self) {
-        self.m_iFlags ^= BehaviorType::wander as i32;
+        if self.On(BehaviorType::wander) {
+            self.m_iFlags ^= BehaviorType::wander as i32;
+        }
+    }
+
+    pub fn SetSummingMethod(&mut self, method: SummingMethod) {
+        self.m_SummingMethod = method;
+    }
+
+    pub fn SummingMethod(&self) -> SummingMethod {
+        self.m_SummingMethod
+    }
+
+    pub fn SetRng(&mut self, rng: SimRng) {
+        self.m_Rng = rng;
+    }
+
+    // the index of the target of evade and pursuit
+    pub fn Target(&self) -> Option<usize> {
+        self.m_pTargetAgent1
+    }
+
+    // sets the target of evade and pursuit without touching the behavior flags
+    pub fn SetTarget(&mut self, agent: Option<usize>) {
+        self.m_pTargetAgent1 = agent;
+    }
+
+    // the index of the leader of offset pursuit
+    pub fn Leader(&self) -> Option<usize> {
+        self.m_pLeader
+    }
+
+    // the indices of the agents interpose gets between
+    pub fn InterposeAgents(&self) -> Option<(usize, usize)> {
+        self.m_pInterposeAgents
+    }
+
+    // the index of the hunter hide keeps out of sight of
+    pub fn Hunter(&self) -> Option<usize> {
+        self.m_pHunter
     }
 
     pub fn isSpacePartitioningOn(&self) -> bool {
         self.m_bCellSpaceOn
     }
 
+    pub fn SetNeighborhood(&mut self, mode: NeighborhoodMode) {
+        self.m_Neighborhood = mode;
+    }
+
+    pub fn Neighborhood(&self) -> NeighborhoodMode {
+        self.m_Neighborhood
+    }
+
+    pub fn SetNumNearestNeighbors(&mut self, k: usize) {
+        self.m_iNumNearestNeighbors = k;
+    }
+
+    pub fn NumNearestNeighbors(&self) -> usize {
+        self.m_iNumNearestNeighbors
+    }
+
+    // true if the flocking behaviors work from the list of neighbors, which
+    // they do when the cell space finds them or when the neighborhood is
+    // more than a radius. Otherwise they check the range of every vehicle.
+    fn UsesNeighborList(&self) -> bool {
+        self.m_bCellSpaceOn || self.m_Neighborhood != NeighborhoodMode::metric
+    }
+
     //this function tests if a specific bit of m_iFlags is set
     pub fn On(&self, bt: BehaviorType) -> bool {
         (self.m_iFlags & bt as i32) == bt as i32
     }
 
-    pub fn Calculate(&mut self, vehicle: &Rc<RefCell<Vehicle>>) -> Vec2 {
+    //------------------------------ Calculate ---------------------------------
+    //
+    //  the combined force of the active behaviors on vehicle index of the
+    //  context
+    //------------------------------------------------------------------------
+    pub fn Calculate(&mut self, index: usize, ctx: &SteeringContext) -> Vec2 {
         // reset the steering force
         self.m_vSteeringForce.x = 0.0;
         self.m_vSteeringForce.y = 0.0;
 
-        if !self.m_bCellSpaceOn {
-            if self.On(BehaviorType::separation) || self.On(BehaviorType::alignment) || self.On(BehaviorType::cohesion) {
-                let world = vehicle.borrow().m_pWorld.clone();
-
-                world.borrow().TagVehiclesWithinViewRange(vehicle.clone(), self.m_dViewDistance);
-            }
-        } else {
-            // calculate neighbours in cell-space if any of the following 3 group
-            // behaviors are switched on
-            if self.On(BehaviorType::separation) || self.On(BehaviorType::alignment) || self.On(BehaviorType::cohesion) {
-                let position = vehicle.borrow().position();
-
-                vehicle
-                    .borrow()
-                    .m_pWorld
-                    .borrow()
-                    .m_pCellSpace
-                    .borrow_mut()
-                    .CalculateNeighbors(position, self.m_dViewDistance);
-            }
+        // calculate neighbours if any of the following 3 group behaviors are
+        // switched on
+        if self.UsesNeighborList()
+            && (self.On(BehaviorType::separation) || self.On(BehaviorType::alignment) || self.On(BehaviorType::cohesion))
+        {
+            self.CalculateNeighborhood(index, ctx);
         }
 
         let new_steering_force = match self.m_SummingMethod {
-            SummingMethod::weighted_average => self.CalculateWeightedSum(),
-            SummingMethod::prioritized => self.CalculatePrioritized(vehicle),
-            SummingMethod::dithered => self.CalculateDithered(),
+            SummingMethod::weighted_average => self.CalculateWeightedSum(index, ctx),
+            SummingMethod::prioritized => self.CalculatePrioritized(index, ctx),
+            SummingMethod::dithered => self.CalculateDithered(index, ctx),
         };
 
         self.m_vSteeringForce = new_steering_force;
@@ -285,18 +589,58 @@ impl SteeringBehavior {
         self.m_vSteeringForce
     }
 
+    //------------------------- CalculateNeighborhood ------------------------
+    //
+    //  fills m_Neighbors with the neighbors of vehicle index as picked by the
+    //  neighborhood mode. They are looked up in the spatial index when the
+    //  cell space is on, otherwise every vehicle is looked at. The k nearest
+    //  never count the vehicle itself or its evade target and are listed
+    //  nearest first.
+    //------------------------------------------------------------------------
+    fn CalculateNeighborhood(&mut self, index: usize, ctx: &SteeringContext) {
+        let position = ctx.vehicles[index].position();
+        let target = self.m_pTargetAgent1;
+        let include = |neighbor: usize| SteeringBehavior::IsNeighbor(index, target, neighbor);
+        let distance_squared = |neighbor: usize| ctx.Offset(position, ctx.vehicles[neighbor].position()).length_squared();
+
+        if self.m_bCellSpaceOn && self.m_Neighborhood == NeighborhoodMode::k_nearest {
+            ctx.cell_space
+                .CalculateNearestNeighbors(ctx.vehicles, position, self.m_iNumNearestNeighbors, &include, &mut self.m_Neighbors);
+            return;
+        }
+
+        if self.m_bCellSpaceOn {
+            ctx.cell_space
+                .CalculateNeighbors(ctx.vehicles, position, self.m_dViewDistance, &mut self.m_Neighbors);
+        } else {
+            let view_distance_squared = self.m_dViewDistance * self.m_dViewDistance;
+            let everyone = self.m_Neighborhood == NeighborhoodMode::k_nearest;
+
+            self.m_Neighbors.clear();
+            self.m_Neighbors
+                .extend((0..ctx.vehicles.len()).filter(|&neighbor| everyone || distance_squared(neighbor) < view_distance_squared));
+        }
+
+        if self.m_Neighborhood != NeighborhoodMode::metric {
+            self.m_Neighbors.retain(|&neighbor| include(neighbor));
+            self.m_Neighbors
+                .sort_by(|&a, &b| distance_squared(a).total_cmp(&distance_squared(b)).then(a.cmp(&b)));
+            self.m_Neighbors.truncate(self.m_iNumNearestNeighbors);
+        }
+    }
+
     //--------------------- AccumulateForce ----------------------------------
     //
     //  This function calculates how much of its max steering force the
     //  vehicle has left to apply and then applies that amount of the
     //  force to add.
     //------------------------------------------------------------------------
-    pub fn AccumulateForce(vehicle: &Rc<RefCell<Vehicle>>, running_total: &mut Vec2, force_to_add: Vec2) -> bool {
+    pub fn AccumulateForce(vehicle: &Vehicle, running_total: &mut Vec2, force_to_add: Vec2) -> bool {
         // calculate how much steering force the vehicle has used so far
         let magnitude_so_far = running_total.length();
 
         // calculate how much steering force remains to be used by this vehicle
-        let magnitude_remaining = vehicle.borrow().max_force() - magnitude_so_far;
+        let magnitude_remaining = vehicle.max_force() - magnitude_so_far;
 
         // return false if there is no more force left to use
         if magnitude_remaining <= 0.0 {
@@ -320,8 +664,120 @@ impl SteeringBehavior {
         return true;
     }
 
-    pub fn CalculateWeightedSum(&mut self) -> Vec2 {
-        todo!()
+    //---------------------- CalculateWeightedSum ----------------------------
+    //
+    //  this simply sums up all the active behaviors X their weights and
+    //  truncates the result to the max available steering force before
+    //  returning
+    //------------------------------------------------------------------------
+    pub fn CalculateWeightedSum(&mut self, index: usize, ctx: &SteeringContext) -> Vec2 {
+        let vehicle = &ctx.vehicles[index];
+
+        if self.On(BehaviorType::wall_avoidance) {
+            let force = self.WallAvoidance(vehicle, ctx.walls) * self.m_dWeightWallAvoidance;
+            self.m_vSteeringForce += force;
+        }
+
+        if self.On(BehaviorType::obstacle_avoidance) {
+            let force = self.ObstacleAvoidance(vehicle, ctx.obstacles, ctx.config.MinDetectionBoxLength) * self.m_dWeightObstacleAvoidance;
+            self.m_vSteeringForce += force;
+        }
+
+        if self.On(BehaviorType::evade) {
+            assert!(&self.m_pTargetAgent1.is_some(), "Evade target not assigned");
+
+            self.m_vSteeringForce += SteeringBehavior::Evade(vehicle, &ctx.vehicles[self.m_pTargetAgent1.unwrap()]) * self.m_dWeightEvade;
+        }
+
+        // these next three can be combined for flocking behavior (wander is
+        // also a good behavior to add into this mix)
+        if !self.UsesNeighborList() {
+            if self.On(BehaviorType::separation) {
+                self.m_vSteeringForce +=
+                    SteeringBehavior::Separation(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance) * self.m_dWeightSeparation;
+            }
+
+            if self.On(BehaviorType::alignment) {
+                self.m_vSteeringForce +=
+                    SteeringBehavior::Alignment(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance) * self.m_dWeightAlignment;
+            }
+
+            if self.On(BehaviorType::cohesion) {
+                self.m_vSteeringForce +=
+                    SteeringBehavior::Cohesion(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance) * self.m_dWeightCohesion;
+            }
+        } else {
+            if self.On(BehaviorType::separation) {
+                self.m_vSteeringForce +=
+                    SteeringBehavior::SeparationPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors) * self.m_dWeightSeparation;
+            }
+
+            if self.On(BehaviorType::alignment) {
+                self.m_vSteeringForce +=
+                    SteeringBehavior::AlignmentPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors) * self.m_dWeightAlignment;
+            }
+
+            if self.On(BehaviorType::cohesion) {
+                self.m_vSteeringForce +=
+                    SteeringBehavior::CohesionPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors) * self.m_dWeightCohesion;
+            }
+        }
+
+        if self.On(BehaviorType::wander) {
+            let force = self.Wander(vehicle, ctx.time_elapsed) * self.m_dWeightWander;
+            self.m_vSteeringForce += force;
+        }
+
+        if self.On(BehaviorType::seek) {
+            self.m_vSteeringForce += SteeringBehavior::Seek(vehicle, ctx.crosshair) * self.m_dWeightSeek;
+        }
+
+        if self.On(BehaviorType::flee) {
+            self.m_vSteeringForce += SteeringBehavior::Flee(vehicle, ctx.crosshair) * self.m_dWeightFlee;
+        }
+
+        if self.On(BehaviorType::arrive) {
+            self.m_vSteeringForce += SteeringBehavior::Arrive(vehicle, ctx.crosshair, self.m_Deceleration) * self.m_dWeightArrive;
+        }
+
+        if self.On(BehaviorType::pursuit) {
+            assert!(self.m_pTargetAgent1.is_some(), "pursuit target not assigned");
+
+            self.m_vSteeringForce +=
+                SteeringBehavior::Pursuit(vehicle, &ctx.vehicles[self.m_pTargetAgent1.unwrap()]) * self.m_dWeightPursuit;
+        }
+
+        if self.On(BehaviorType::offset_pursuit) {
+            assert!(self.m_pLeader.is_some(), "Offset pursuit leader not assigned");
+            assert!(self.m_vOffset != Vec2::ZERO, "No offset assigned");
+
+            self.m_vSteeringForce += SteeringBehavior::OffsetPursuit(vehicle, &ctx.vehicles[self.m_pLeader.unwrap()], self.m_vOffset)
+                * self.m_dWeightOffsetPursuit;
+        }
+
+        if self.On(BehaviorType::interpose) {
+            assert!(self.m_pInterposeAgents.is_some(), "Interpose agents not assigned");
+            let (agent_a, agent_b) = self.m_pInterposeAgents.unwrap();
+
+            self.m_vSteeringForce +=
+                SteeringBehavior::Interpose(vehicle, &ctx.vehicles[agent_a], &ctx.vehicles[agent_b]) * self.m_dWeightInterpose;
+        }
+
+        if self.On(BehaviorType::hide) {
+            assert!(self.m_pHunter.is_some(), "Hide hunter not assigned");
+
+            self.m_vSteeringForce +=
+                SteeringBehavior::Hide(vehicle, &ctx.vehicles[self.m_pHunter.unwrap()], ctx.obstacles) * self.m_dWeightHide;
+        }
+
+        if self.On(BehaviorType::follow_path) {
+            let force = self.FollowPath(vehicle) * self.m_dWeightFollowPath;
+            self.m_vSteeringForce += force;
+        }
+
+        self.m_vSteeringForce = Truncate(self.m_vSteeringForce, vehicle.max_force());
+
+        self.m_vSteeringForce
     }
 
     //---------------------- CalculatePrioritized ----------------------------
@@ -331,190 +787,382 @@ impl SteeringBehavior {
     //  is reached, at which time the function returns the steering force
     //  accumulated to that  point
     //------------------------------------------------------------------------
-    pub fn CalculatePrioritized(&mut self, vehicle: &Rc<RefCell<Vehicle>>) -> Vec2 {
-        let mut force: Vec2 = Vec2::default();
-        /*
-            if (On(wall_avoidance))
-            {
-                force = WallAvoidance(vehicle->World()->Walls()) *
-                m_dWeightWallAvoidance;
+    pub fn CalculatePrioritized(&mut self, index: usize, ctx: &SteeringContext) -> Vec2 {
+        let vehicle = &ctx.vehicles[index];
+
+        let mut force: Vec2 = Vec2::default();
+
+        if self.On(BehaviorType::wall_avoidance) {
+            force = self.WallAvoidance(vehicle, ctx.walls) * self.m_dWeightWallAvoidance;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::obstacle_avoidance) {
+            force = self.ObstacleAvoidance(vehicle, ctx.obstacles, ctx.config.MinDetectionBoxLength) * self.m_dWeightObstacleAvoidance;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::evade) {
+            assert!(&self.m_pTargetAgent1.is_some(), "Evade target not assigned");
+
+            force = SteeringBehavior::Evade(vehicle, &ctx.vehicles[self.m_pTargetAgent1.unwrap()]) * self.m_dWeightEvade;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::flee) {
+            force = SteeringBehavior::Flee(vehicle, ctx.crosshair) * self.m_dWeightFlee;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        // these next three can be combined for flocking behavior (wander is
+        // also a good behavior to add into this mix)
+        if !self.UsesNeighborList() {
+            if self.On(BehaviorType::separation) {
+                force = SteeringBehavior::Separation(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance) * self.m_dWeightSeparation;
+
+                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                    return self.m_vSteeringForce;
+                }
+            }
+
+            if self.On(BehaviorType::alignment) {
+                force = SteeringBehavior::Alignment(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance) * self.m_dWeightAlignment;
+
+                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                    return self.m_vSteeringForce;
+                }
+            }
+
+            if self.On(BehaviorType::cohesion) {
+                force = SteeringBehavior::Cohesion(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance) * self.m_dWeightCohesion;
+
+                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                    return self.m_vSteeringForce;
+                }
+            }
+        } else {
+            if self.On(BehaviorType::separation) {
+                force = SteeringBehavior::SeparationPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors) * self.m_dWeightSeparation;
+
+                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                    return self.m_vSteeringForce;
+                }
+            }
+
+            if self.On(BehaviorType::alignment) {
+                force = SteeringBehavior::AlignmentPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors) * self.m_dWeightAlignment;
+
+                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                    return self.m_vSteeringForce;
+                }
+            }
+
+            if self.On(BehaviorType::cohesion) {
+                force = SteeringBehavior::CohesionPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors) * self.m_dWeightCohesion;
+
+                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                    return self.m_vSteeringForce;
+                }
+            }
+        }
+
+        if self.On(BehaviorType::seek) {
+            force = SteeringBehavior::Seek(vehicle, ctx.crosshair) * self.m_dWeightSeek;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::arrive) {
+            force = SteeringBehavior::Arrive(vehicle, ctx.crosshair, self.m_Deceleration) * self.m_dWeightArrive;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::wander) {
+            force = self.Wander(vehicle, ctx.time_elapsed) * self.m_dWeightWander;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::pursuit) {
+            assert!(self.m_pTargetAgent1.is_some(), "pursuit target not assigned");
+
+            force = SteeringBehavior::Pursuit(vehicle, &ctx.vehicles[self.m_pTargetAgent1.unwrap()]) * self.m_dWeightPursuit;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::offset_pursuit) {
+            assert!(self.m_pLeader.is_some(), "Offset pursuit leader not assigned");
+            assert!(self.m_vOffset != Vec2::ZERO, "No offset assigned");
+
+            force = SteeringBehavior::OffsetPursuit(vehicle, &ctx.vehicles[self.m_pLeader.unwrap()], self.m_vOffset)
+                * self.m_dWeightOffsetPursuit;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::interpose) {
+            assert!(self.m_pInterposeAgents.is_some(), "Interpose agents not assigned");
+            let (agent_a, agent_b) = self.m_pInterposeAgents.unwrap();
+
+            force = SteeringBehavior::Interpose(vehicle, &ctx.vehicles[agent_a], &ctx.vehicles[agent_b]) * self.m_dWeightInterpose;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::hide) {
+            assert!(self.m_pHunter.is_some(), "Hide hunter not assigned");
+
+            force = SteeringBehavior::Hide(vehicle, &ctx.vehicles[self.m_pHunter.unwrap()], ctx.obstacles) * self.m_dWeightHide;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        if self.On(BehaviorType::follow_path) {
+            force = self.FollowPath(vehicle) * self.m_dWeightFollowPath;
+
+            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
+                return self.m_vSteeringForce;
+            }
+        }
+
+        return self.m_vSteeringForce;
+    }
+
+    //---------------------------- CalculateDithered ----------------------------
+    //
+    //  this method sums up the active behaviors by assigning a probability
+    //  of being calculated to each behavior. It then tests the first priority
+    //  to see if it should be calculated this simulation-step. If so, it
+    //  calculates the steering force resulting from this behavior. If it is
+    //  more than zero it returns the force. If zero, or if the behavior is
+    //  skipped it continues onto the next priority, and so on.
+    //------------------------------------------------------------------------
+    pub fn CalculateDithered(&mut self, index: usize, ctx: &SteeringContext) -> Vec2 {
+        let vehicle = &ctx.vehicles[index];
 
-                if (!SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force)) return self.m_vSteeringForce;
-            }
+        // reset the steering force
+        self.m_vSteeringForce = Vec2::default();
+
+        let config = *ctx.config;
+
+        if self.On(BehaviorType::wall_avoidance) && self.Dither(config.prWallAvoidance) {
+            self.m_vSteeringForce = self.WallAvoidance(vehicle, ctx.walls) * self.m_dWeightWallAvoidance / config.prWallAvoidance;
+
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
             }
+        }
 
-            if (On(obstacle_avoidance))
-            {
-                force = ObstacleAvoidance(vehicle->World()->Obstacles()) *
-                m_dWeightObstacleAvoidance;
+        if self.On(BehaviorType::obstacle_avoidance) && self.Dither(config.prObstacleAvoidance) {
+            let force = self.ObstacleAvoidance(vehicle, ctx.obstacles, ctx.config.MinDetectionBoxLength) * self.m_dWeightObstacleAvoidance
+                / config.prObstacleAvoidance;
+            self.m_vSteeringForce += force;
 
-                if (!SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force)) return self.m_vSteeringForce;
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
             }
+        }
+
+        if self.On(BehaviorType::flee) && self.Dither(config.prFlee) {
+            self.m_vSteeringForce += SteeringBehavior::Flee(vehicle, ctx.crosshair) * self.m_dWeightFlee / config.prFlee;
+
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
             }
-        */
-        if self.On(BehaviorType::evade) {
+        }
+
+        if self.On(BehaviorType::evade) && self.Dither(config.prEvade) {
             assert!(&self.m_pTargetAgent1.is_some(), "Evade target not assigned");
 
-            force = SteeringBehavior::Evade(vehicle, self.m_pTargetAgent1.as_mut().unwrap().borrow()) * self.m_dWeightEvade;
+            self.m_vSteeringForce +=
+                SteeringBehavior::Evade(vehicle, &ctx.vehicles[self.m_pTargetAgent1.unwrap()]) * self.m_dWeightEvade / config.prEvade;
 
-            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
-                return self.m_vSteeringForce;
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
             }
         }
 
-        if self.On(BehaviorType::flee) {
-            force = SteeringBehavior::Flee(vehicle, vehicle.borrow().m_pWorld.borrow().m_vCrosshair) * self.m_dWeightFlee;
+        if !self.UsesNeighborList() {
+            if self.On(BehaviorType::separation) && self.Dither(config.prSeparation) {
+                self.m_vSteeringForce += SteeringBehavior::Separation(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance)
+                    * self.m_dWeightSeparation
+                    / config.prSeparation;
 
-            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
-                return self.m_vSteeringForce;
+                if self.m_vSteeringForce != Vec2::ZERO {
+                    return Truncate(self.m_vSteeringForce, vehicle.max_force());
+                }
             }
-        }
 
-        // these next three can be combined for flocking behavior (wander is
-        // also a good behavior to add into this mix)
-        if !self.isSpacePartitioningOn() {
-            // if self.On(BehaviorType::separation)
-            // {
-            //     force = Separation(vehicle->World()->Agents()) * m_dWeightSeparation;
-            //
-            //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-            //         return self.m_vSteeringForce;
-            //     }
-            // }
-
-            // if self.On(BehaviorType::alignment)
-            // {
-            //     force = Alignment(vehicle->World()->Agents()) * m_dWeightAlignment;
-            //
-            //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-            //         return self.m_vSteeringForce;
-            //     }
-            // }
+            if self.On(BehaviorType::alignment) && self.Dither(config.prAlignment) {
+                self.m_vSteeringForce += SteeringBehavior::Alignment(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance)
+                    * self.m_dWeightAlignment
+                    / config.prAlignment;
 
-            if self.On(BehaviorType::cohesion) {
-                force = SteeringBehavior::Cohesion(vehicle, &self.m_pTargetAgent1, &vehicle.borrow().m_pWorld.borrow().m_Vehicles)
-                    * self.m_dWeightCohesion;
+                if self.m_vSteeringForce != Vec2::ZERO {
+                    return Truncate(self.m_vSteeringForce, vehicle.max_force());
+                }
+            }
 
-                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
-                    return self.m_vSteeringForce;
+            if self.On(BehaviorType::cohesion) && self.Dither(config.prCohesion) {
+                self.m_vSteeringForce += SteeringBehavior::Cohesion(index, self.m_pTargetAgent1, ctx, self.m_dViewDistance)
+                    * self.m_dWeightCohesion
+                    / config.prCohesion;
+
+                if self.m_vSteeringForce != Vec2::ZERO {
+                    return Truncate(self.m_vSteeringForce, vehicle.max_force());
                 }
             }
         } else {
-            if self.On(BehaviorType::separation) {
-                force = SteeringBehavior::SeparationPlus(vehicle, &vehicle.borrow().m_pWorld.borrow().m_pCellSpace.borrow().m_Neighbors)
-                    * self.m_dWeightSeparation;
+            if self.On(BehaviorType::separation) && self.Dither(config.prSeparation) {
+                self.m_vSteeringForce += SteeringBehavior::SeparationPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors)
+                    * self.m_dWeightSeparation
+                    / config.prSeparation;
 
-                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
-                    return self.m_vSteeringForce;
+                if self.m_vSteeringForce != Vec2::ZERO {
+                    return Truncate(self.m_vSteeringForce, vehicle.max_force());
                 }
             }
 
-            if self.On(BehaviorType::alignment) {
-                force = SteeringBehavior::AlignmentPlus(vehicle, &vehicle.borrow().m_pWorld.borrow().m_pCellSpace.borrow().m_Neighbors)
-                    * self.m_dWeightAlignment;
+            if self.On(BehaviorType::alignment) && self.Dither(config.prAlignment) {
+                self.m_vSteeringForce += SteeringBehavior::AlignmentPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors)
+                    * self.m_dWeightAlignment
+                    / config.prAlignment;
 
-                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
-                    return self.m_vSteeringForce;
+                if self.m_vSteeringForce != Vec2::ZERO {
+                    return Truncate(self.m_vSteeringForce, vehicle.max_force());
                 }
             }
 
-            if self.On(BehaviorType::cohesion) {
-                force = SteeringBehavior::CohesionPlus(vehicle, &vehicle.borrow().m_pWorld.borrow().m_pCellSpace.borrow().m_Neighbors)
-                    * self.m_dWeightCohesion;
+            if self.On(BehaviorType::cohesion) && self.Dither(config.prCohesion) {
+                self.m_vSteeringForce += SteeringBehavior::CohesionPlus(index, self.m_pTargetAgent1, ctx, &self.m_Neighbors)
+                    * self.m_dWeightCohesion
+                    / config.prCohesion;
 
-                if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
-                    return self.m_vSteeringForce;
+                if self.m_vSteeringForce != Vec2::ZERO {
+                    return Truncate(self.m_vSteeringForce, vehicle.max_force());
                 }
             }
         }
 
-        // if self.On(BehaviorType::seek)
-        // {
-        //     force = SteeringBehavior::Seek(vehicle.World().Crosshair()) * m_dWeightSeek;
-        //
-        //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-        //         return self.m_vSteeringForce;
-        //     }
-        // }
-        //
-        //
-        // if self.On(BehaviorType::arrive)
-        // {
-        //     force = self.Arrive(vehicle.World().Crosshair(), m_Deceleration) * m_dWeightArrive;
-        //
-        //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-        //         return self.m_vSteeringForce;
-        //     }
-        // }
+        if self.On(BehaviorType::wander) && self.Dither(config.prWander) {
+            let force = self.Wander(vehicle, ctx.time_elapsed) * self.m_dWeightWander / config.prWander;
+            self.m_vSteeringForce += force;
 
-        if self.On(BehaviorType::wander) {
-            force = self.Wander(vehicle) * self.m_dWeightWander;
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
+            }
+        }
 
-            if !SteeringBehavior::AccumulateForce(vehicle, &mut self.m_vSteeringForce, force) {
-                return self.m_vSteeringForce;
+        if self.On(BehaviorType::seek) && self.Dither(config.prSeek) {
+            self.m_vSteeringForce += SteeringBehavior::Seek(vehicle, ctx.crosshair) * self.m_dWeightSeek / config.prSeek;
+
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
             }
         }
 
-        // if self.On(BehaviorType::pursuit)
-        // {
-        //     assert(m_pTargetAgent1 && "pursuit target not assigned");
-        //
-        //     force = Pursuit(m_pTargetAgent1) * m_dWeightPursuit;
-        //
-        //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-        //         return self.m_vSteeringForce;
-        //     }
-        // }
-        //
-        // if self.On(BehaviorType::offset_pursuit)
-        // {
-        //     assert!(m_pTargetAgent1 && "pursuit target not assigned");
-        //     assert!(!m_vOffset.isZero() && "No offset assigned");
-        //
-        //     force = self.OffsetPursuit(m_pTargetAgent1, m_vOffset);
-        //
-        //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-        //         return self.m_vSteeringForce;
-        //     }
-        // }
-        //
-        // if self.On(BehaviorType::interpose)
-        // {
-        //     assert!(m_pTargetAgent1 && m_pTargetAgent2, "Interpose agents not assigned");
-        //
-        //     force = Interpose(m_pTargetAgent1, m_pTargetAgent2) * m_dWeightInterpose;
-        //
-        //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-        //         return self.m_vSteeringForce;
-        //     }
-        // }
-        //
-        // if self.On(BehaviorType::hide)
-        // {
-        //     assert!(m_pTargetAgent1 && "Hide target not assigned");
-        //
-        //     force = self.Hide(m_pTargetAgent1, vehicle.World().Obstacles()) * self.m_dWeightHide;
-        //
-        //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-        //         return self.m_vSteeringForce;
-        //     }
-        // }
-        //
-        //
-        // if self.On(BehaviorType::follow_path)
-        // {
-        //     force = FollowPath() * self.m_dWeightFollowPath;
-        //
-        //     if !SteeringBehavior::AccumulateForce(self.m_vSteeringForce, force) {
-        //         return self.m_vSteeringForce;
-        //     }
-        // }
+        if self.On(BehaviorType::arrive) && self.Dither(config.prArrive) {
+            self.m_vSteeringForce +=
+                SteeringBehavior::Arrive(vehicle, ctx.crosshair, self.m_Deceleration) * self.m_dWeightArrive / config.prArrive;
 
-        return self.m_vSteeringForce;
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
+            }
+        }
+
+        if self.On(BehaviorType::pursuit) && self.Dither(config.prPursuit) {
+            assert!(self.m_pTargetAgent1.is_some(), "pursuit target not assigned");
+
+            self.m_vSteeringForce +=
+                SteeringBehavior::Pursuit(vehicle, &ctx.vehicles[self.m_pTargetAgent1.unwrap()]) * self.m_dWeightPursuit / config.prPursuit;
+
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
+            }
+        }
+
+        if self.On(BehaviorType::offset_pursuit) && self.Dither(config.prOffsetPursuit) {
+            assert!(self.m_pLeader.is_some(), "Offset pursuit leader not assigned");
+            assert!(self.m_vOffset != Vec2::ZERO, "No offset assigned");
+
+            self.m_vSteeringForce += SteeringBehavior::OffsetPursuit(vehicle, &ctx.vehicles[self.m_pLeader.unwrap()], self.m_vOffset)
+                * self.m_dWeightOffsetPursuit
+                / config.prOffsetPursuit;
+
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
+            }
+        }
+
+        if self.On(BehaviorType::interpose) && self.Dither(config.prInterpose) {
+            assert!(self.m_pInterposeAgents.is_some(), "Interpose agents not assigned");
+            let (agent_a, agent_b) = self.m_pInterposeAgents.unwrap();
+
+            self.m_vSteeringForce += SteeringBehavior::Interpose(vehicle, &ctx.vehicles[agent_a], &ctx.vehicles[agent_b])
+                * self.m_dWeightInterpose
+                / config.prInterpose;
+
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
+            }
+        }
+
+        if self.On(BehaviorType::hide) && self.Dither(config.prHide) {
+            assert!(self.m_pHunter.is_some(), "Hide hunter not assigned");
+
+            self.m_vSteeringForce +=
+                SteeringBehavior::Hide(vehicle, &ctx.vehicles[self.m_pHunter.unwrap()], ctx.obstacles) * self.m_dWeightHide / config.prHide;
+
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
+            }
+        }
+
+        if self.On(BehaviorType::follow_path) && self.Dither(config.prFollowPath) {
+            let force = self.FollowPath(vehicle) * self.m_dWeightFollowPath / config.prFollowPath;
+            self.m_vSteeringForce += force;
+
+            if self.m_vSteeringForce != Vec2::ZERO {
+                return Truncate(self.m_vSteeringForce, vehicle.max_force());
+            }
+        }
+
+        self.m_vSteeringForce
     }
 
-    pub fn CalculateDithered(&mut self) -> Vec2 {
-        todo!()
+    // rolls the dither rng and returns true if a behavior with the given
+    // probability should be evaluated this simulation-step. The roll is
+    // below 1 so a behavior with a probability of 1 is always evaluated.
+    fn Dither(&mut self, probability: f32) -> bool {
+        self.m_Rng.gen::<f32>() < probability
     }
 
     /////////////////////////////////////////////////////////////////////////////// START OF BEHAVIORS
@@ -524,19 +1172,19 @@ impl SteeringBehavior {
     //  Given a target, this behavior returns a steering force which will
     //  direct the agent towards the target
     //------------------------------------------------------------------------
-    pub fn Seek(vehicle: &Rc<RefCell<Vehicle>>, TargetPos: Vec2) -> Vec2 {
-        let mut desired_velocity = TargetPos - vehicle.borrow().position();
+    pub fn Seek(vehicle: &Vehicle, TargetPos: Vec2) -> Vec2 {
+        let mut desired_velocity = TargetPos - vehicle.position();
         desired_velocity = desired_velocity.normalize_or_zero();
-        desired_velocity *= vehicle.borrow().max_speed();
+        desired_velocity *= vehicle.max_speed();
 
-        desired_velocity - vehicle.borrow().velocity()
+        desired_velocity - vehicle.velocity()
     }
 
     //----------------------------- Flee -------------------------------------
     //
     //  Does the opposite of Seek
     //------------------------------------------------------------------------
-    pub fn Flee(vehicle: &Rc<RefCell<Vehicle>>, TargetPos: Vec2) -> Vec2 {
+    pub fn Flee(vehicle: &Vehicle, TargetPos: Vec2) -> Vec2 {
         //only flee if the target is within 'panic distance'. Work in distance
         //squared space.
         /* const float PanicDistanceSq = 100.0f * 100.0;
@@ -546,11 +1194,11 @@ impl SteeringBehavior {
         }
         */
 
-        let mut desired_velocity = vehicle.borrow().position() - TargetPos;
+        let mut desired_velocity = vehicle.position() - TargetPos;
         desired_velocity = desired_velocity.normalize_or_zero();
-        desired_velocity *= vehicle.borrow().max_speed();
+        desired_velocity *= vehicle.max_speed();
 
-        return desired_velocity - vehicle.borrow().velocity();
+        desired_velocity - vehicle.velocity()
     }
 
     //--------------------------- Arrive -------------------------------------
@@ -558,7 +1206,7 @@ impl SteeringBehavior {
     //  This behavior is similar to seek but it attempts to arrive at the
     //  target with a zero velocity
     //------------------------------------------------------------------------
-    pub fn Arrive(vehicle: Vehicle, TargetPos: Vec2, deceleration: Deceleration) -> Vec2 {
+    pub fn Arrive(vehicle: &Vehicle, TargetPos: Vec2, deceleration: Deceleration) -> Vec2 {
         let ToTarget = TargetPos - vehicle.position();
 
         // calculate the distance to the target
@@ -592,16 +1240,15 @@ impl SteeringBehavior {
     //  this behavior creates a force that steers the agent towards the
     //  evader
     //------------------------------------------------------------------------
-    pub fn Pursuit(&self, vehicle: &Rc<RefCell<Vehicle>>, evader: Vehicle) -> Vec2 {
+    pub fn Pursuit(vehicle: &Vehicle, evader: &Vehicle) -> Vec2 {
         // if the evader is ahead and facing the agent then we can just seek
         // for the evader's current position.
-        let ToEvader = evader.position() - vehicle.borrow().position();
+        let ToEvader = evader.position() - vehicle.position();
 
-        let RelativeHeading = vehicle.borrow().heading().dot(evader.heading());
+        let RelativeHeading = vehicle.heading().dot(evader.heading());
 
-        if (ToEvader.dot(vehicle.borrow().heading()) > 0.0) & &(RelativeHeading < -0.95)
         //acos(0.95)=18 degs
-        {
+        if (ToEvader.dot(vehicle.heading()) > 0.0) && (RelativeHeading < -0.95) {
             return SteeringBehavior::Seek(vehicle, evader.position());
         }
 
@@ -610,7 +1257,7 @@ impl SteeringBehavior {
         // the lookahead time is proportional to the distance between the evader
         // and the pursuer; and is inversely proportional to the sum of the
         // agent's velocities
-        let LookAheadTime = ToEvader.length() / (vehicle.borrow().max_speed() + evader.speed());
+        let LookAheadTime = ToEvader.length() / (vehicle.max_speed() + evader.speed());
 
         // now seek to the predicted future position of the evader
         return SteeringBehavior::Seek(vehicle, evader.position() + evader.velocity() * LookAheadTime);
@@ -621,10 +1268,10 @@ impl SteeringBehavior {
     //  similar to pursuit except the agent Flees from the estimated future
     //  position of the pursuer
     //------------------------------------------------------------------------
-    pub fn Evade(vehicle: &Rc<RefCell<Vehicle>>, pursuer: Ref<Vehicle>) -> Vec2 {
+    pub fn Evade(vehicle: &Vehicle, pursuer: &Vehicle) -> Vec2 {
         /* Not necessary to include the check for facing direction this time */
 
-        let ToPursuer = pursuer.position() - vehicle.borrow().position();
+        let ToPursuer = pursuer.position() - vehicle.position();
 
         // uncomment the following two lines to have Evade only consider pursuers
         // within a 'threat range'
@@ -636,7 +1283,7 @@ impl SteeringBehavior {
         // the lookahead time is proportional to the distance between the pursuer
         // and the pursuer; and is inversely proportional to the sum of the
         // agents' velocities
-        let LookAheadTime = ToPursuer.length() / (vehicle.borrow().max_speed() + pursuer.speed());
+        let LookAheadTime = ToPursuer.length() / (vehicle.max_speed() + pursuer.speed());
 
         // now flee away from predicted future position of the pursuer
         return SteeringBehavior::Flee(vehicle, pursuer.position() + pursuer.velocity() * LookAheadTime);
@@ -646,28 +1293,28 @@ impl SteeringBehavior {
     //
     //  This behavior makes the agent wander about randomly
     //------------------------------------------------------------------------
-    pub fn Wander(&mut self, vehicle: &Rc<RefCell<Vehicle>>) -> Vec2 {
+    pub fn Wander(&mut self, vehicle: &Vehicle, time_elapsed: f32) -> Vec2 {
         // use a timer to slow down the frequency of direction changes
-        self.wander_direction_time -= vehicle.borrow().m_dTimeElapsed;
+        self.wander_direction_time -= time_elapsed;
 
         // if self.wander_direction_time < 0.0 {
-        self.wander_direction_time = RandInRange(0.05, 0.3);
+        self.wander_direction_time = RandInRange(&mut self.m_Rng, 0.05, 0.3);
 
         // this behavior is dependent on the update rate, so this line must
         // be included when using time independent framerate.
-        let jitter_this_time_slice = self.m_dWanderJitter * vehicle.borrow().m_dTimeElapsed;
+        let jitter_this_time_slice = self.m_dWanderJitter * time_elapsed;
 
         // first, add a small random vector to the target's position
-        let x_rand = RandomClamped() * jitter_this_time_slice;
-        let y_rand = RandomClamped() * jitter_this_time_slice;
+        let x_rand = RandomClamped(&mut self.m_Rng) * jitter_this_time_slice;
+        let y_rand = RandomClamped(&mut self.m_Rng) * jitter_this_time_slice;
 
         // use a normal distribution for turns
         // let normal: Normal<f32> = Normal::new(0.0, 0.1).unwrap();
         // let x_rand = normal.sample(&mut thread_rng()) * jitter_this_time_slice;
         // let y_rand = normal.sample(&mut thread_rng()) * jitter_this_time_slice;
-        let mut rand_vec = vec2(x_rand, y_rand);
+        let rand_vec = vec2(x_rand, y_rand);
 
-        // if vehicle.borrow().id() == 0 {
+        // if vehicle.id() == 0 {
         //     rand_vec = vec2(0.0, 0.0);
         //     self.m_vWanderTarget = rand_vec;
         // } else {
@@ -686,17 +1333,12 @@ impl SteeringBehavior {
         let wander_target = self.m_vWanderTarget + vec2(self.m_dWanderDistance, 0.0);
 
         // project the target into world space
-        let world_target = PointToWorldSpace(
-            wander_target,
-            vehicle.borrow().heading(),
-            vehicle.borrow().side(),
-            vehicle.borrow().position(),
-        );
+        let world_target = PointToWorldSpace(wander_target, vehicle.heading(), vehicle.side(), vehicle.position());
 
         //and steer towards it
-        let mut steer_force = world_target - vehicle.borrow().position();
+        let steer_force = world_target - vehicle.position();
 
-        // if vehicle.borrow().id() == 0 {
+        // if vehicle.id() == 0 {
         //     // steer_force *= 70.0;
         //     println!("\nrand_vec: {:?}", rand_vec);
         //     println!("self.m_vWanderTarget: {:?}", self.m_vWanderTarget);
@@ -713,109 +1355,90 @@ impl SteeringBehavior {
     //  Given a vector of CObstacles, this method returns a steering force
     //  that will prevent the agent colliding with the closest obstacle
     //------------------------------------------------------------------------
-    pub fn ObstacleAvoidance(obstacles: Vec<impl EntityMovable>) -> Vec2 {
-        todo!();
-        /*
-        //the detection box length is proportional to the agent's velocity
-            m_dDBoxLength = Prm.MinDetectionBoxLength +
-            (vehicle.Speed() / vehicle.MaxSpeed()) *
-            Prm.MinDetectionBoxLength;
+    pub fn ObstacleAvoidance(&mut self, vehicle: &Vehicle, obstacles: &[Obstacle], min_box_length: f32) -> Vec2 {
+        // the detection box length is proportional to the agent's velocity
+        self.m_dDBoxLength = min_box_length + (vehicle.speed() / vehicle.max_speed()) * min_box_length;
 
-        //tag all obstacles within range of the box for processing
-            vehicle->World() -> TagObstaclesWithinViewRange(vehicle, m_dDBoxLength);
+        // this will keep track of the closest intersecting obstacle (CIB)
+        let mut closest_intersecting_obstacle: Option<&Obstacle> = None;
 
-        //this will keep track of the closest intersecting obstacle (CIB)
-            BaseGameEntity* ClosestIntersectingObstacle = NULL;
+        // this will be used to track the distance to the CIB
+        let mut dist_to_closest_ip = f32::MAX;
 
-        //this will be used to track the distance to the CIB
-            float DistToClosestIP = Maxfloat;
+        // this will record the transformed local coordinates of the CIB
+        let mut local_pos_of_closest_obstacle = Vec2::default();
 
-        //this will record the transformed local coordinates of the CIB
-            Vec2 LocalPosOfClosestObstacle;
+        for obstacle in obstacles {
+            // only obstacles within range of the box need processing
+            if !WithinRange(vehicle.position(), obstacle, self.m_dDBoxLength) {
+                continue;
+            }
 
-            std::vector<BaseGameEntity* >::const_iterator curOb = obstacles.begin();
+            // calculate this obstacle's position in local space
+            let local_pos = PointToLocalSpace(obstacle.position(), vehicle.heading(), vehicle.side(), vehicle.position());
 
-            while (curOb != obstacles.end())
-            {
-            //if the obstacle has been tagged within range proceed
-            if (( * curOb) -> IsTagged())
-            {
-            //calculate this obstacle's position in local space
-            Vec2 LocalPos = PointToLocalSpace(( * curOb).Pos(),
-            vehicle -> Heading(),
-            vehicle -> Side(),
-            vehicle.Pos());
-
-        //if the local position has a negative x value then it must lay
-        //behind the agent. (in which case it can be ignored)
-            if (LocalPos.x > = 0)
-            {
-            //if the distance from the x axis to the object's position is less
-        //than its radius + half the width of the detection box then there
-        //is a potential intersection.
-            float ExpandedRadius = ( * curOb) -> BRadius() + vehicle -> BRadius();
+            // if the local position has a negative x value then it must lay
+            // behind the agent. (in which case it can be ignored)
+            if local_pos.x < 0.0 {
+                continue;
+            }
 
-            if (fabs(LocalPos.y) < ExpandedRadius)
-            {
-            //now to do a line/circle intersection test. The center of the
-        //circle is represented by (cX, cY). The intersection points are
-        //given by the formula x = cX +/-sqrt(r^2-cY^2) for y=0.
-        //We only need to look at the smallest positive value of x because
-        //that will be the closest point of intersection.
-            float cX = LocalPos.x;
-            float cY = LocalPos.y;
+            // if the distance from the x axis to the object's position is less
+            // than its radius + half the width of the detection box then there
+            // is a potential intersection.
+            let expanded_radius = obstacle.bounding_radius() + vehicle.bounding_radius();
 
-        //we only need to calculate the sqrt part of the above equation once
-            float SqrtPart = sqrt(ExpandedRadius* ExpandedRadius - cY * cY);
+            if local_pos.y.abs() < expanded_radius {
+                // now to do a line/circle intersection test. The center of the
+                // circle is represented by (cX, cY). The intersection points are
+                // given by the formula x = cX +/-sqrt(r^2-cY^2) for y=0.
+                // We only need to look at the smallest positive value of x because
+                // that will be the closest point of intersection.
+                let cX = local_pos.x;
+                let cY = local_pos.y;
 
-            float ip = cX - SqrtPart;
+                // we only need to calculate the sqrt part of the above equation once
+                let sqrt_part = (expanded_radius * expanded_radius - cY * cY).sqrt();
 
-            if (ip < = 0.0)
-            {
-            ip = cX + SqrtPart;
-            }
+                let mut ip = cX - sqrt_part;
 
-            //test to see if this is the closest so far. If it is keep a
-        //record of the obstacle and its local coordinates
-            if (ip < DistToClosestIP)
-            {
-            DistToClosestIP = ip;
-            ClosestIntersectingObstacle = * curOb;
-            LocalPosOfClosestObstacle = LocalPos;
-            }
-            }
-            }
-            }
+                if ip <= 0.0 {
+                    ip = cX + sqrt_part;
+                }
 
-            + + curOb;
+                // test to see if this is the closest so far. If it is keep a
+                // record of the obstacle and its local coordinates
+                if ip < dist_to_closest_ip {
+                    dist_to_closest_ip = ip;
+                    closest_intersecting_obstacle = Some(obstacle);
+                    local_pos_of_closest_obstacle = local_pos;
+                }
             }
+        }
 
-        //if we have found an intersecting obstacle, calculate a steering
-        //force away from it
-            Vec2 SteeringForce;
+        // if we have found an intersecting obstacle, calculate a steering
+        // force away from it
+        let mut steering_force = Vec2::default();
 
-            if (ClosestIntersectingObstacle)
-            {
-            //the closer the agent is to an object, the stronger the
-        //steering force should be
-            float multiplier = 1.0 + (m_dDBoxLength - LocalPosOfClosestObstacle.x) / m_dDBoxLength;
+        if let Some(obstacle) = closest_intersecting_obstacle {
+            let obstacle_radius = obstacle.bounding_radius();
 
-        //calculate the lateral force
-            SteeringForce.y = (ClosestIntersectingObstacle -> BRadius() - LocalPosOfClosestObstacle.y) * multiplier;
+            // the closer the agent is to an object, the stronger the
+            // steering force should be
+            let multiplier = 1.0 + (self.m_dDBoxLength - local_pos_of_closest_obstacle.x) / self.m_dDBoxLength;
 
-        //apply a braking force proportional to the obstacles distance from
-        //the vehicle.
-            const float BrakingWeight = 0.2;
+            // calculate the lateral force
+            steering_force.y = (obstacle_radius - local_pos_of_closest_obstacle.y) * multiplier;
 
-            SteeringForce.x = (ClosestIntersectingObstacle ->BRadius() - LocalPosOfClosestObstacle.x) * BrakingWeight;
-            }
+            // apply a braking force proportional to the obstacles distance from
+            // the vehicle.
+            const BRAKING_WEIGHT: f32 = 0.2;
 
-        //finally, convert the steering vector from local to world space
-            return VectorToWorldSpace(SteeringForce,
-            vehicle.Heading(),
-            vehicle->Side());
+            steering_force.x = (obstacle_radius - local_pos_of_closest_obstacle.x) * BRAKING_WEIGHT;
+        }
 
-                */
+        // finally, convert the steering vector from local to world space
+        VectorToWorldSpace(steering_force, vehicle.heading(), vehicle.side())
     }
 
     //--------------------------- WallAvoidance --------------------------------
@@ -823,117 +1446,94 @@ impl SteeringBehavior {
     //  This returns a steering force that will keep the agent away from any
     //  walls it may encounter
     //------------------------------------------------------------------------
-    pub fn WallAvoidance(walls: Vec<Wall2D>) -> Vec2 {
-        todo!();
-        /*
-        //the feelers are contained in a std::vector, m_Feelers
-        CreateFeelers();
+    pub fn WallAvoidance(&mut self, vehicle: &Vehicle, walls: &[Wall2D]) -> Vec2 {
+        // the feelers are contained in a vector, m_Feelers
+        self.CreateFeelers(vehicle);
 
-        float DistToThisIP    = 0.0;
-        float DistToClosestIP = Maxfloat;
+        let position = vehicle.position();
 
-        //this will hold an index into the vector of walls
-        int ClosestWall = - 1;
+        let mut steering_force = Vec2::default();
 
-        Vec2 SteeringForce,
-        point,         //used for storing temporary info
-        ClosestPoint;  //holds the closest intersection point
+        // examine each feeler in turn
+        for feeler in &self.m_Feelers {
+            let mut dist_to_closest_ip = f32::MAX;
 
-        //examine each feeler in turn
-        for (unsigned int flr=0; flr<m_Feelers.size(); + + flr)
-        {
-        //run through each wall checking for any intersection points
-        for (unsigned int w = 0; w <walls.size(); + + w)
-        {
-        if (LineIntersection2D(vehicle.Pos(),
-        m_Feelers[flr],
-        walls[w].From(),
-        walls[w].To(),
-        DistToThisIP,
-        point))
-        {
-        //is this the closest found so far? If so keep a record
-        if (DistToThisIP < DistToClosestIP)
-        {
-        DistToClosestIP = DistToThisIP;
-        ClosestWall = w;
-        ClosestPoint = point;                }
-        }
-        }//next wall
+            // this will hold the closest wall and the closest intersection point
+            let mut closest: Option<(&Wall2D, Vec2)> = None;
 
+            // run through each wall checking for any intersection points
+            for wall in walls {
+                if let Some((dist_to_this_ip, point)) = LineIntersection2D(position, *feeler, wall.From(), wall.To()) {
+                    // is this the closest found so far? If so keep a record
+                    if dist_to_this_ip < dist_to_closest_ip {
+                        dist_to_closest_ip = dist_to_this_ip;
+                        closest = Some((wall, point));
+                    }
+                }
+            }
 
-        //if an intersection point has been detected, calculate a force
-        //that will direct the agent away
-        if (ClosestWall > = 0)
-        {
-        //calculate by what distance the projected position of the agent
-        //will overshoot the wall
-        Vec2 OverShoot = m_Feelers[flr] - ClosestPoint;
+            // if an intersection point has been detected, calculate a force
+            // that will direct the agent away
+            if let Some((wall, closest_point)) = closest {
+                // calculate by what distance the projected position of the agent
+                // will overshoot the wall
+                let over_shoot = *feeler - closest_point;
 
-        //create a force in the direction of the wall normal, with a
-        //magnitude of the overshoot
-        SteeringForce = walls[ClosestWall].Normal() * OverShoot.Length();
+                // create a force in the direction of the wall normal, with a
+                // magnitude of the overshoot
+                steering_force = wall.Normal() * over_shoot.length();
+            }
         }
 
-        }//next feeler
-
-        return SteeringForce;
-
-             */
+        steering_force
     }
 
     //------------------------------- CreateFeelers --------------------------
     //
     //  Creates the antenna utilized by WallAvoidance
     //------------------------------------------------------------------------
-    pub fn CreateFeelers() {
-        todo!();
-        /*
-        //feeler pointing straight in front
-        m_Feelers[0] = vehicle.Pos() + m_dWallDetectionFeelerLength * vehicle -> Heading();
+    pub fn CreateFeelers(&mut self, vehicle: &Vehicle) {
+        let position = vehicle.position();
+        let heading = vehicle.heading();
+
+        self.m_Feelers.clear();
 
-        //feeler to left
-        Vec2 temp = vehicle -> Heading();
-        Vec2DRotateAroundOrigin(temp, HalfPi * 3.5f);
-        m_Feelers[1] = vehicle.Pos() + m_dWallDetectionFeelerLength / 2.0f * temp;
+        // feeler pointing straight in front
+        self.m_Feelers.push(position + self.m_dWallDetectionFeelerLength * heading);
 
-        //feeler to right
-        temp = vehicle -> Heading();
-        Vec2DRotateAroundOrigin(temp, HalfPi * 0.5f);
-        m_Feelers[2] = vehicle.Pos() + m_dWallDetectionFeelerLength /2.0f * temp;
+        // feeler to left
+        let temp = Vec2DRotateAroundOrigin(heading, FRAC_PI_2 * 3.5);
+        self.m_Feelers.push(position + self.m_dWallDetectionFeelerLength / 2.0 * temp);
 
-             */
+        // feeler to right
+        let temp = Vec2DRotateAroundOrigin(heading, FRAC_PI_2 * 0.5);
+        self.m_Feelers.push(position + self.m_dWallDetectionFeelerLength / 2.0 * temp);
     }
 
     //---------------------------- Separation --------------------------------
     //
     // this calculates a force repelling from the other neighbors
     //------------------------------------------------------------------------
-    pub fn Separation(neighbors: &Vec<Rc<RefCell<Vehicle>>>) -> Vec2 {
-        todo!();
-        /*
-        Vec2 SteeringForce;
-
-        for (unsigned int a=0; a<neighbors.size(); + + a)
-        {
-        //make sure this agent isn't included in the calculations and that
-        //the agent being examined is close enough. ***also make sure it doesn't
-        //include the evade target ***
-        if ((neighbors[a] != vehicle) & & neighbors[a] -> IsTagged() & &
-        (neighbors[a] != m_pTargetAgent1))
-        {
-        Vec2 ToAgent = vehicle.Pos() - neighbors[a].Pos();
+    pub fn Separation(vehicle_index: usize, m_pTargetAgent1: Option<usize>, ctx: &SteeringContext, view_distance: f32) -> Vec2 {
+        let vehicle = &ctx.vehicles[vehicle_index];
+        let mut SteeringForce = Vec2::default();
 
-        //scale the force inversely proportional to the agents distance
-        //from its neighbor.
-        SteeringForce += Vec2DNormalize(ToAgent) / ToAgent.Length();
+        for (index, neighbor) in ctx.vehicles.iter().enumerate() {
+            // make sure this agent isn't included in the calculations and that
+            // the agent being examined is close enough. ***also make sure it doesn't
+            // include the evade target ***
+            if SteeringBehavior::IsNeighbor(vehicle_index, m_pTargetAgent1, index)
+                && ctx.WithinRange(vehicle.position(), neighbor, view_distance)
+            {
+                let to_agent = -ctx.Offset(vehicle.position(), neighbor.position());
 
-        }
+                // scale the force inversely proportional to the agents distance
+                // from its neighbor.
+                SteeringForce += to_agent.normalize_or_zero() / to_agent.length();
+            }
         }
 
-        return SteeringForce;
-
-             */
+        SteeringForce
     }
 
     //---------------------------- Alignment ---------------------------------
@@ -941,42 +1541,43 @@ impl SteeringBehavior {
     //  returns a force that attempts to align this agents heading with that
     //  of its neighbors
     //------------------------------------------------------------------------
-    pub fn Alignment(neighbors: &Vec<Rc<RefCell<Vehicle>>>) -> Vec2 {
-        todo!();
-        /*
-        //used to record the average heading of the neighbors
-        Vec2 AverageHeading;
+    pub fn Alignment(vehicle_index: usize, m_pTargetAgent1: Option<usize>, ctx: &SteeringContext, view_distance: f32) -> Vec2 {
+        let vehicles = ctx.vehicles;
 
-        //used to count the number of vehicles in the neighborhood
-        int    NeighborCount = 0;
+        // used to record the average heading of the neighbors
+        let mut AverageHeading = Vec2::default();
 
-        //iterate through all the tagged vehicles and sum their heading vectors
-        for (unsigned int a=0; a<neighbors.size(); + + a)
-        {
-        //make sure *this* agent isn't included in the calculations and that
-        //the agent being examined  is close enough ***also make sure it doesn't
-        //include any evade target ***
-        if ((neighbors[a] != vehicle) & & neighbors[a] -> IsTagged() & &
-        (neighbors[a] != m_pTargetAgent1))
-        {
-        AverageHeading += neighbors[a] -> Heading();
+        // used to count the number of vehicles in the neighborhood
+        let mut NeighborCount: f32 = 0.0;
 
-        + + NeighborCount;
-        }
+        // iterate through all the tagged vehicles and sum their heading vectors
+        for (index, neighbor) in vehicles.iter().enumerate() {
+            // make sure *this* agent isn't included in the calculations and that
+            // the agent being examined  is close enough ***also make sure it doesn't
+            // include any evade target ***
+            if SteeringBehavior::IsNeighbor(vehicle_index, m_pTargetAgent1, index)
+                && ctx.WithinRange(vehicles[vehicle_index].position(), neighbor, view_distance)
+            {
+                AverageHeading += neighbor.heading();
+                NeighborCount += 1.0;
+            }
         }
 
-        //if the neighborhood contained one or more vehicles, average their
-        //heading vectors.
-        if (NeighborCount > 0)
-        {
-        AverageHeading /= (float)NeighborCount;
-
-        AverageHeading -= vehicle-> Heading();
+        // if the neighborhood contained one or more vehicles, average their
+        // heading vectors.
+        if NeighborCount > 0.0 {
+            AverageHeading /= NeighborCount;
+            AverageHeading -= vehicles[vehicle_index].heading();
         }
 
-        return AverageHeading;
+        AverageHeading
+    }
 
-             */
+    // true if the vehicle at index is neither the vehicle itself nor its
+    // current target agent. Both the tagged and the partitioned flocking
+    // behaviors use this so that the two modes agree on who is in the flock.
+    fn IsNeighbor(vehicle_index: usize, m_pTargetAgent1: Option<usize>, index: usize) -> bool {
+        index != vehicle_index && m_pTargetAgent1 != Some(index)
     }
 
     //-------------------------------- Cohesion ------------------------------
@@ -984,11 +1585,10 @@ impl SteeringBehavior {
     //  returns a steering force that attempts to move the agent towards the
     //  center of mass of the agents in its immediate area
     //------------------------------------------------------------------------
-    pub fn Cohesion(
-        vehicle: &Rc<RefCell<Vehicle>>,
-        m_pTargetAgent1: &Option<Rc<RefCell<Vehicle>>>,
-        neighbors: &Vec<Rc<RefCell<Vehicle>>>,
-    ) -> Vec2 {
+    pub fn Cohesion(vehicle_index: usize, m_pTargetAgent1: Option<usize>, ctx: &SteeringContext, view_distance: f32) -> Vec2 {
+        let vehicles = ctx.vehicles;
+        let position = vehicles[vehicle_index].position();
+
         // first find the center of mass of all the agents
         let mut center_of_mass: Vec2 = Default::default();
         let mut SteeringForce: Vec2 = Default::default();
@@ -996,18 +1596,15 @@ impl SteeringBehavior {
         let mut NeighborCount: i32 = 0;
 
         //iterate through the neighbors and sum up all the position vectors
-        for neighbor in neighbors {
+        for (index, neighbor) in vehicles.iter().enumerate() {
             // make sure *this* agent isn't included in the calculations and that
             // the agent being examined is close enough ***also make sure it doesn't
-            // include the evade target ***
-            let is_target_agent = if let Some(agent) = m_pTargetAgent1 {
-                agent.borrow().id() == neighbor.borrow().id()
-            } else {
-                false
-            };
-
-            if (neighbor.borrow().id() != vehicle.borrow().id()) && neighbor.borrow().is_tagged() && (!is_target_agent) {
-                center_of_mass += neighbor.borrow().position();
+            // include the evade target ***. A neighbor seen across an edge
+            // of the world counts as being just over the edge. The offsets
+            // are summed rather than the positions so a tight school's center
+            // isn't lost to rounding.
+            if SteeringBehavior::IsNeighbor(vehicle_index, m_pTargetAgent1, index) && ctx.WithinRange(position, neighbor, view_distance) {
+                center_of_mass += ctx.Offset(position, neighbor.position());
 
                 NeighborCount += 1;
             }
@@ -1015,10 +1612,10 @@ impl SteeringBehavior {
 
         if NeighborCount > 0 {
             // the center of mass is the average of the sum of positions
-            center_of_mass = center_of_mass.div(NeighborCount as f32);
+            center_of_mass = position + center_of_mass.div(NeighborCount as f32);
 
             // now seek towards that position
-            SteeringForce = SteeringBehavior::Seek(vehicle, center_of_mass);
+            SteeringForce = SteeringBehavior::Seek(&vehicles[vehicle_index], center_of_mass);
         }
 
         // the magnitude of cohesion is usually much larger than separation or
@@ -1027,7 +1624,9 @@ impl SteeringBehavior {
     }
 
     /* NOTE: the next three behaviors are the same as the above three, except
-    that they use a cell-space partition to find the neighbors
+    that they steer by a list of neighbors, given as indices into the
+    context's vehicles. The list comes from the cell-space partition or is
+    picked by the neighborhood mode, see CalculateNeighborhood.
     */
 
     //---------------------------- Separation --------------------------------
@@ -1036,13 +1635,14 @@ impl SteeringBehavior {
     //
     //  USES SPACIAL PARTITIONING
     //------------------------------------------------------------------------
-    pub fn SeparationPlus(vehicle: &Rc<RefCell<Vehicle>>, neighbors: &Vec<Rc<RefCell<dyn EntityMovable>>>) -> Vec2 {
+    pub fn SeparationPlus(vehicle_index: usize, m_pTargetAgent1: Option<usize>, ctx: &SteeringContext, neighbors: &[usize]) -> Vec2 {
+        let vehicle = &ctx.vehicles[vehicle_index];
         let mut SteeringForce = Vec2::default();
 
         // iterate through the neighbors and sum up all the position vectors
-        for pv in neighbors.iter() {
-            if pv.borrow().id() != vehicle.borrow().id() {
-                let to_agent = vehicle.borrow().position() - pv.borrow().position();
+        for &index in neighbors {
+            if SteeringBehavior::IsNeighbor(vehicle_index, m_pTargetAgent1, index) {
+                let to_agent = -ctx.Offset(vehicle.position(), ctx.vehicles[index].position());
                 // scale the force inversely proportional to the agents distance from its neighbor.
                 SteeringForce += to_agent.normalize_or_zero() / to_agent.length();
             }
@@ -1057,23 +1657,25 @@ impl SteeringBehavior {
     //
     //  USES SPACIAL PARTITIONING
     //------------------------------------------------------------------------
-    pub fn AlignmentPlus(vehicle: &Rc<RefCell<Vehicle>>, neighbors: &Vec<Rc<RefCell<dyn EntityMovable>>>) -> Vec2 {
+    pub fn AlignmentPlus(vehicle_index: usize, m_pTargetAgent1: Option<usize>, ctx: &SteeringContext, neighbors: &[usize]) -> Vec2 {
+        let vehicles = ctx.vehicles;
+
         // This will record the average heading of the neighbors
         let mut AverageHeading = Vec2::default();
 
         // This count the number of vehicles in the neighborhood
         let mut NeighborCount: f32 = 0.0;
 
-        for pv in neighbors.iter() {
-            if pv.borrow().id() != vehicle.borrow().id() {
-                AverageHeading += pv.borrow().heading();
+        for &index in neighbors {
+            if SteeringBehavior::IsNeighbor(vehicle_index, m_pTargetAgent1, index) {
+                AverageHeading += vehicles[index].heading();
                 NeighborCount += 1.0;
             }
         }
 
         if NeighborCount > 0.0 {
             AverageHeading /= NeighborCount;
-            AverageHeading -= vehicle.borrow().heading();
+            AverageHeading -= vehicles[vehicle_index].heading();
         }
 
         AverageHeading
@@ -1086,7 +1688,10 @@ impl SteeringBehavior {
     //
     //  USES SPACIAL PARTITIONING
     //------------------------------------------------------------------------
-    pub fn CohesionPlus(vehicle: &Rc<RefCell<Vehicle>>, neighbors: &Vec<Rc<RefCell<dyn EntityMovable>>>) -> Vec2 {
+    pub fn CohesionPlus(vehicle_index: usize, m_pTargetAgent1: Option<usize>, ctx: &SteeringContext, neighbors: &[usize]) -> Vec2 {
+        let vehicles = ctx.vehicles;
+        let position = vehicles[vehicle_index].position();
+
         // first find the center of mass of all the agents
         let mut CenterOfMass = Vec2::default();
         let mut SteeringForce = Vec2::default();
@@ -1094,20 +1699,21 @@ impl SteeringBehavior {
         let mut NeighborCount = 0;
 
         // iterate through the neighbors and sum up all the position vectors
-        for pv in neighbors.iter() {
+        for &index in neighbors {
             //make sure *this* agent isn't included in the calculations and that
-            //the agent being examined is close enough
-            if pv.borrow().id() != vehicle.borrow().id() {
-                CenterOfMass += pv.borrow().position();
+            //the agent being examined is close enough. The evade target is left
+            //out as well so this matches the non-partitioned version.
+            if SteeringBehavior::IsNeighbor(vehicle_index, m_pTargetAgent1, index) {
+                CenterOfMass += ctx.Offset(position, vehicles[index].position());
                 NeighborCount += 1;
             }
         }
 
         if NeighborCount > 0 {
             //the center of mass is the average of the sum of positions
-            CenterOfMass /= NeighborCount as f32;
+            CenterOfMass = position + CenterOfMass / NeighborCount as f32;
             //now seek towards that position
-            SteeringForce = SteeringBehavior::Seek(vehicle, CenterOfMass);
+            SteeringForce = SteeringBehavior::Seek(&vehicles[vehicle_index], CenterOfMass);
         }
 
         //the magnitude of cohesion is usually much larger than separation or
@@ -1120,74 +1726,56 @@ impl SteeringBehavior {
     //  Given two agents, this method returns a force that attempts to
     //  position the vehicle between them
     //------------------------------------------------------------------------
-    pub fn Interpose(AgentA: &Vehicle, AgentB: &Vehicle) -> Vec2 {
-        todo!();
-        /*
-        //first we need to figure out where the two agents are going to be at
-        //time T in the future. This is approximated by determining the time
-        //taken to reach the mid way point at the current time at at max speed.
-        Vec2 MidPoint = (AgentA.Pos() + AgentB.Pos()) / 2.0;
+    pub fn Interpose(vehicle: &Vehicle, AgentA: &Vehicle, AgentB: &Vehicle) -> Vec2 {
+        // first we need to figure out where the two agents are going to be at
+        // time T in the future. This is approximated by determining the time
+        // taken to reach the mid way point at the current time at at max speed.
+        let mid_point = (AgentA.position() + AgentB.position()) / 2.0;
 
-        float TimeToReachMidPoint = Vec2DDistance(vehicle.Pos(), MidPoint) /
-        vehicle.MaxSpeed();
+        let time_to_reach_mid_point = vehicle.position().distance(mid_point) / vehicle.max_speed();
 
-        //now we have T, we assume that agent A and agent B will continue on a
-        //straight trajectory and extrapolate to get their future positions
-        Vec2 APos = AgentA.Pos() + AgentA.Velocity() * TimeToReachMidPoint;
-        Vec2 BPos = AgentB.Pos() + AgentB.Velocity() * TimeToReachMidPoint;
+        // now we have T, we assume that agent A and agent B will continue on a
+        // straight trajectory and extrapolate to get their future positions
+        let APos = AgentA.position() + AgentA.velocity() * time_to_reach_mid_point;
+        let BPos = AgentB.position() + AgentB.velocity() * time_to_reach_mid_point;
 
-        //calculate the mid point of these predicted positions
-        MidPoint = (APos + BPos) / 2.0;
+        // calculate the mid point of these predicted positions
+        let mid_point = (APos + BPos) / 2.0;
 
-        //then steer to Arrive at it
-        return Arrive(MidPoint, fast);
-
-             */
+        // then steer to Arrive at it
+        SteeringBehavior::Arrive(vehicle, mid_point, Deceleration::fast)
     }
 
     //--------------------------- Hide ---------------------------------------
     //
+    //  Finds the closest hiding spot behind an obstacle and arrives at it.
+    //  Evades the hunter if there are no obstacles to hide behind.
     //------------------------------------------------------------------------
-    pub fn Hide(hunter: &Vehicle, obstacles: Vec<impl EntityMovable>) -> Vec2 {
-        todo!();
-        /*
-        float    DistToClosest = Maxfloat;
-        Vec2 BestHidingSpot;
+    pub fn Hide(vehicle: &Vehicle, hunter: &Vehicle, obstacles: &[Obstacle]) -> Vec2 {
+        let mut dist_to_closest = f32::MAX;
+        let mut best_hiding_spot = Vec2::default();
 
-        std::vector<BaseGameEntity* >::const_iterator curOb = obstacles.begin();
-        std::vector<BaseGameEntity* >::const_iterator closest;
-
-        while (curOb != obstacles.end())
-        {
-        //calculate the position of the hiding spot for this obstacle
-        Vec2 HidingSpot = GetHidingPosition(( * curOb).Pos(),
-        ( * curOb) -> BRadius(),
-        hunter.Pos());
+        for obstacle in obstacles {
+            // calculate the position of the hiding spot for this obstacle
+            let hiding_spot = SteeringBehavior::GetHidingPosition(obstacle.position(), obstacle.bounding_radius(), hunter.position());
 
-        //work in distance-squared space to find the closest hiding
-        //spot to the agent
-        float dist = Vec2DDistanceSq(HidingSpot, vehicle.Pos());
+            // work in distance-squared space to find the closest hiding
+            // spot to the agent
+            let dist = hiding_spot.distance_squared(vehicle.position());
 
-        if (dist < DistToClosest)
-        {
-        DistToClosest = dist;
-        BestHidingSpot = HidingSpot;
-        closest = curOb;
+            if dist < dist_to_closest {
+                dist_to_closest = dist;
+                best_hiding_spot = hiding_spot;
+            }
         }
-        + + curOb;
 
-        }//end while
-
-        //if no suitable obstacles found then Evade the hunter
-        if (DistToClosest == MaxFloat)
-        {
-        return Evade(hunter);
+        // if no suitable obstacles found then Evade the hunter
+        if dist_to_closest == f32::MAX {
+            return SteeringBehavior::Evade(vehicle, hunter);
         }
 
-        //else use Arrive on the hiding spot
-        return Arrive(BestHidingSpot, fast);
-
-             */
+        // else use Arrive on the hiding spot
+        SteeringBehavior::Arrive(vehicle, best_hiding_spot, Deceleration::fast)
     }
 
     //------------------------- GetHidingPosition ----------------------------
@@ -1197,22 +1785,17 @@ impl SteeringBehavior {
     //  away from its bounding radius and directly opposite the hunter
     //------------------------------------------------------------------------
     pub fn GetHidingPosition(posOb: Vec2, radiusOb: f32, posHunter: Vec2) -> Vec2 {
-        todo!();
-        /*
-        //calculate how far away the agent is to be from the chosen obstacle's
-        //bounding radius
-        const float DistanceFromBoundary = 30.0;
-        float       DistAway    = radiusOb + DistanceFromBoundary;
+        // calculate how far away the agent is to be from the chosen obstacle's
+        // bounding radius
+        const DISTANCE_FROM_BOUNDARY: f32 = 30.0;
+        let dist_away = radiusOb + DISTANCE_FROM_BOUNDARY;
 
-        //calculate the heading toward the object from the hunter
-        Vec2 ToOb(posOb - posHunter);
-        ToOb.normalize_or_zero();
+        // calculate the heading toward the object from the hunter
+        let to_ob = (posOb - posHunter).normalize_or_zero();
 
-        //scale it to size and add to the obstacles position to get
-        //the hiding spot.
-        return (ToOb * DistAway) + posOb;
-
-             */
+        // scale it to size and add to the obstacles position to get
+        // the hiding spot.
+        (to_ob * dist_away) + posOb
     }
 
     //------------------------------- FollowPath -----------------------------
@@ -1222,27 +1805,24 @@ impl SteeringBehavior {
     // 'Seek' behavior to move to the next waypoint - unless it is the last
     //  waypoint, in which case it 'Arrives'
     //------------------------------------------------------------------------
-    pub fn FollowPath() -> Vec2 {
-        todo!();
-        /*
-        //move to next target if close enough to current target (working in
-        //distance squared space)
-        if (Vec2DDistanceSq(m_pPath->CurrentWaypoint(), vehicle.Pos()) <
-            m_dWaypointSeekDistSq)
-        {
-            m_pPath -> SetNextWaypoint();
+    pub fn FollowPath(&mut self, vehicle: &Vehicle) -> Vec2 {
+        // a vehicle that hasn't been given a path stays where it is steered
+        // by its other behaviors
+        if self.m_pPath.GetPath().is_empty() {
+            return Vec2::ZERO;
         }
 
-        if (!m_pPath -> Finished())
-        {
-            return Seek(m_pPath->CurrentWaypoint());
-        }
-        else
-        {
-            return Arrive(m_pPath->CurrentWaypoint(), normal);
+        // move to next target if close enough to current target (working in
+        // distance squared space)
+        if self.m_pPath.CurrentWaypoint().distance_squared(vehicle.position()) < self.m_dWaypointSeekDistSq {
+            self.m_pPath.SetNextWaypoint();
         }
 
-         */
+        if !self.m_pPath.Finished() {
+            SteeringBehavior::Seek(vehicle, self.m_pPath.CurrentWaypoint())
+        } else {
+            SteeringBehavior::Arrive(vehicle, self.m_pPath.CurrentWaypoint(), Deceleration::normal)
+        }
     }
 
     //------------------------- Offset Pursuit -------------------------------
@@ -1250,26 +1830,19 @@ impl SteeringBehavior {
     //  Produces a steering force that keeps a vehicle at a specified offset
     //  from a leader vehicle
     //------------------------------------------------------------------------
-    pub fn OffsetPursuit(leader: &Vehicle, offset: Vec2) -> Vec2 {
-        todo!();
-        /*
-        //calculate the offset's position in world space
-        Vec2 WorldOffsetPos = PointToWorldSpace(offset,
-        leader.Heading(),
-        leader->Side(),
-        leader.Pos());
-
-        Vec2 ToOffset = WorldOffsetPos - vehicle.Pos();
+    pub fn OffsetPursuit(vehicle: &Vehicle, leader: &Vehicle, offset: Vec2) -> Vec2 {
+        // calculate the offset's position in world space
+        let world_offset_pos = PointToWorldSpace(offset, leader.heading(), leader.side(), leader.position());
 
-        //the lookahead time is propotional to the distance between the leader
-        //and the pursuer; and is inversely proportional to the sum of both
-        //agent's velocities
-        float LookAheadTime = ToOffset.Length() / (vehicle.MaxSpeed() + leader.Speed());
+        let to_offset = world_offset_pos - vehicle.position();
 
-        //now Arrive at the predicted future position of the offset
-        return Arrive(WorldOffsetPos + leader.Velocity() * LookAheadTime, fast);
+        // the lookahead time is propotional to the distance between the leader
+        // and the pursuer; and is inversely proportional to the sum of both
+        // agent's velocities
+        let look_ahead_time = to_offset.length() / (vehicle.max_speed() + leader.speed());
 
-             */
+        // now Arrive at the predicted future position of the offset
+        SteeringBehavior::Arrive(vehicle, world_offset_pos + leader.velocity() * look_ahead_time, Deceleration::fast)
     }
 
     // bool KeyDown(char key)
@@ -1572,3 +2145,411 @@ impl SteeringBehavior {
         */
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::ConfigLoader;
+    use crate::entity_traits::EntityMovable;
+    use crate::obstacle::Obstacle;
+    use crate::spatial_index::BruteForce;
+    use crate::steering_behavior::{SteeringBehavior, SteeringContext, SummingMethod};
+    use crate::utils::SimRng;
+    use crate::vehicle::Vehicle;
+    use crate::wall_2d::Wall2D;
+    use glam::{vec2, Vec2};
+    use rand::SeedableRng;
+
+    // a fish with the default properties moving with velocity, or facing
+    // along x if it isn't moving
+    fn Fish(config: &ConfigLoader, position: Vec2, velocity: Vec2) -> Vehicle {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut vehicle = Vehicle::new(
+            config,
+            position,
+            0.0,
+            velocity,
+            config.VehicleMass,
+            config.MaxSteeringForce,
+            config.MaxSpeed,
+            config.MaxTurnRatePerSecond,
+            config.Scale,
+            &mut rng,
+        );
+        vehicle.heading = if velocity == Vec2::ZERO {
+            vec2(1.0, 0.0)
+        } else {
+            velocity.normalize()
+        };
+        vehicle.side_vec = vehicle.heading.perp();
+        vehicle
+    }
+
+    // the steering force on the first of vehicles
+    fn Steer(
+        steering: &mut SteeringBehavior,
+        config: &ConfigLoader,
+        vehicles: &[Vehicle],
+        obstacles: &[Obstacle],
+        crosshair: Vec2,
+    ) -> Vec2 {
+        let cell_space = BruteForce::new(1000.0, 800.0);
+        let ctx = SteeringContext {
+            vehicles,
+            obstacles,
+            walls: &[],
+            cell_space: &cell_space,
+            crosshair,
+            config,
+            time_elapsed: 1.0 / 60.0,
+        };
+
+        steering.Calculate(0, &ctx)
+    }
+
+    #[test]
+    pub fn test_weighted_sum() {
+        // seek outweighs flee, but flee comes first in priority order
+        let config = ConfigLoader {
+            SeekWeight: 2.0 * ConfigLoader::default().FleeWeight,
+            ..ConfigLoader::default()
+        };
+        let vehicles = [Fish(&config, vec2(100.0, 100.0), Vec2::ZERO)];
+        let crosshair = vec2(300.0, 100.0);
+
+        let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+        steering.SeekOn();
+        steering.FleeOn();
+
+        steering.SetSummingMethod(SummingMethod::weighted_average);
+        assert_eq!(steering.SummingMethod(), SummingMethod::weighted_average);
+
+        // the weighted forces are summed then truncated to the max force
+        let force = Steer(&mut steering, &config, &vehicles, &[], crosshair);
+        assert!((force - vec2(config.MaxSteeringForce, 0.0)).length() < 1e-3, "{force}");
+
+        // whereas prioritized spends all the force on fleeing
+        steering.SetSummingMethod(SummingMethod::prioritized);
+        let force = Steer(&mut steering, &config, &vehicles, &[], crosshair);
+        assert!((force - vec2(-config.MaxSteeringForce, 0.0)).length() < 1e-3, "{force}");
+    }
+
+    #[test]
+    pub fn test_dithered_sum() {
+        // light weights so the forces aren't truncated and show which
+        // behavior was picked
+        let config = ConfigLoader {
+            SeekWeight: 1.0,
+            ArriveWeight: 1.0,
+            prSeek: 0.5,
+            prArrive: 0.5,
+            ..ConfigLoader::default()
+        };
+        let vehicles = [Fish(&config, vec2(100.0, 100.0), Vec2::ZERO)];
+        let crosshair = vec2(110.0, 100.0);
+
+        let run = |seed: u64| {
+            let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+            steering.SetRng(SimRng::seed_from_u64(seed));
+            steering.SetSummingMethod(SummingMethod::dithered);
+            steering.SeekOn();
+            steering.ArriveOn();
+
+            (0..100)
+                .map(|_| Steer(&mut steering, &config, &vehicles, &[], crosshair))
+                .collect::<Vec<_>>()
+        };
+
+        // the same rng picks the same behaviors
+        let forces = run(5);
+        assert_eq!(forces, run(5));
+        assert_ne!(forces, run(6));
+
+        // seek, arrive and neither each get picked some of the time
+        let seek = SteeringBehavior::Seek(&vehicles[0], crosshair) / config.prSeek;
+        assert!(forces.contains(&seek));
+        assert!(forces.contains(&Vec2::ZERO));
+        assert!(forces.iter().any(|&force| force != seek && force != Vec2::ZERO));
+
+        // a behavior with a probability of 1 is evaluated every time
+        let config = ConfigLoader { prSeek: 1.0, ..config };
+        let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+        steering.SetSummingMethod(SummingMethod::dithered);
+        steering.SeekOn();
+
+        for _ in 0..1000 {
+            let force = Steer(&mut steering, &config, &vehicles, &[], crosshair);
+            assert_eq!(force, SteeringBehavior::Seek(&vehicles[0], crosshair));
+        }
+
+        // the behaviors after arrive are dithered too. Pursuit is picked
+        // half the time and hide, which always passes, the rest.
+        let config = ConfigLoader {
+            PursuitWeight: 1.0,
+            HideWeight: 1.0,
+            prPursuit: 0.5,
+            prHide: 1.0,
+            ..config
+        };
+        let vehicles = [
+            Fish(&config, vec2(100.0, 100.0), Vec2::ZERO),
+            Fish(&config, vec2(150.0, 120.0), vec2(0.0, 30.0)),
+        ];
+        let obstacles = [Obstacle::new(vec2(200.0, 200.0), 20.0)];
+
+        let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+        steering.SetRng(SimRng::seed_from_u64(5));
+        steering.SetSummingMethod(SummingMethod::dithered);
+        steering.PursuitOn(1);
+        steering.HideOn(1);
+
+        let pursuit = SteeringBehavior::Pursuit(&vehicles[0], &vehicles[1]) / config.prPursuit;
+        let hide = SteeringBehavior::Hide(&vehicles[0], &vehicles[1], &obstacles);
+        let forces: Vec<Vec2> = (0..100)
+            .map(|_| Steer(&mut steering, &config, &vehicles, &obstacles, crosshair))
+            .collect();
+        assert!(forces.iter().all(|&force| force == pursuit || force == hide));
+        assert!(forces.contains(&pursuit) && forces.contains(&hide));
+    }
+
+    #[test]
+    pub fn test_follow_path() {
+        let config = ConfigLoader::default();
+        let mut vehicle = Fish(&config, vec2(100.0, 100.0), Vec2::ZERO);
+
+        let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+        steering.FollowPathOn();
+
+        // without a path there is nothing to follow
+        let force = Steer(&mut steering, &config, std::slice::from_ref(&vehicle), &[], Vec2::ZERO);
+        assert_eq!(force, Vec2::ZERO);
+
+        let waypoints = vec![vec2(200.0, 100.0), vec2(200.0, 200.0), vec2(300.0, 200.0)];
+        steering.SetPath(waypoints.clone());
+        steering.GetPath().LoopOff();
+
+        let mut closest = [f32::MAX; 3];
+
+        for _ in 0..1200 {
+            let force = Steer(&mut steering, &config, std::slice::from_ref(&vehicle), &[], Vec2::ZERO);
+            vehicle.Update(force, 1.0 / 60.0, 1000, 800, None);
+
+            for (closest, waypoint) in closest.iter_mut().zip(&waypoints) {
+                *closest = closest.min(vehicle.position.distance(*waypoint));
+            }
+        }
+
+        // it passes by each waypoint in turn and comes to rest on the last
+        assert!(closest.iter().all(|&distance| distance < 25.0), "{closest:?}");
+        assert!(vehicle.position.distance(waypoints[2]) < 1.0, "{}", vehicle.position);
+        assert!(vehicle.speed() < config.MaxSpeed / 10.0, "{}", vehicle.velocity);
+    }
+
+    #[test]
+    pub fn test_arrive() {
+        let config = ConfigLoader::default();
+        let mut vehicle = Fish(&config, vec2(100.0, 100.0), Vec2::ZERO);
+        let target = vec2(400.0, 100.0);
+
+        let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+        steering.ArriveOn();
+
+        let mut track = vec![];
+        for _ in 0..600 {
+            let force = Steer(&mut steering, &config, std::slice::from_ref(&vehicle), &[], target);
+            vehicle.Update(force, 1.0 / 60.0, 1000, 800, None);
+            track.push((vehicle.position.distance(target), vehicle.speed()));
+        }
+
+        // it swims at full speed while far off, slows as it closes in and
+        // comes to rest on the target
+        assert!(track
+            .iter()
+            .any(|&(distance, speed)| distance > 100.0 && speed > config.MaxSpeed - 1.0));
+        for &(distance, speed) in &track {
+            assert!(distance > 20.0 || speed < config.MaxSpeed / 2.0, "{distance} {speed}");
+        }
+        assert!(vehicle.position.distance(target) < 1.0, "{}", vehicle.position);
+        assert!(vehicle.speed() < config.MaxSpeed / 10.0, "{}", vehicle.velocity);
+    }
+
+    #[test]
+    pub fn test_pursuit() {
+        let config = ConfigLoader::default();
+        let evader = || Fish(&config, vec2(300.0, 100.0), vec2(0.0, 50.0));
+        let pursuer = || Fish(&config, vec2(100.0, 100.0), Vec2::ZERO);
+        let (evader_start, pursuer_start) = (evader(), pursuer());
+
+        // it leads the evader, steering for where it will be rather than
+        // where it is
+        let look_ahead_time = 200.0 / (config.MaxSpeed + 50.0);
+        let ahead = evader_start.position + evader_start.velocity * look_ahead_time;
+        let force = SteeringBehavior::Pursuit(&pursuer_start, &evader_start);
+        assert_eq!(force, SteeringBehavior::Seek(&pursuer_start, ahead));
+
+        // so it catches the evader sooner than seeking it does
+        let catch_up = |pursue: bool| {
+            let mut vehicles = [pursuer(), evader()];
+            let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+            if pursue {
+                steering.PursuitOn(1);
+            } else {
+                steering.SeekOn();
+            }
+
+            (0..600)
+                .position(|_| {
+                    let force = Steer(&mut steering, &config, &vehicles, &[], vehicles[1].position);
+                    vehicles[0].Update(force, 1.0 / 60.0, 1000, 800, None);
+                    vehicles[1].Update(Vec2::ZERO, 1.0 / 60.0, 1000, 800, None);
+                    vehicles[0].position.distance(vehicles[1].position) < 5.0
+                })
+                .unwrap()
+        };
+
+        assert!(catch_up(true) < catch_up(false));
+    }
+
+    #[test]
+    pub fn test_interpose() {
+        let config = ConfigLoader::default();
+        let vehicles = [
+            Fish(&config, vec2(200.0, 300.0), Vec2::ZERO),
+            Fish(&config, vec2(100.0, 100.0), vec2(0.0, 20.0)),
+            Fish(&config, vec2(300.0, 100.0), vec2(0.0, 20.0)),
+            Fish(&config, vec2(250.0, 350.0), Vec2::ZERO),
+        ];
+
+        // it heads for where the mid point will be by the time it gets there
+        let time_to_mid_point = 200.0 / config.MaxSpeed;
+        let mid_point = vec2(200.0, 100.0 + 20.0 * time_to_mid_point);
+        let force = SteeringBehavior::Interpose(&vehicles[0], &vehicles[1], &vehicles[2]);
+        assert!(
+            force.normalize().abs_diff_eq((mid_point - vehicles[0].position).normalize(), 1e-5),
+            "{force}"
+        );
+
+        // interposing doesn't retarget evade, and both steer the vehicle
+        let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+        steering.EvadeOn(3);
+        steering.InterposeOn(1, 2);
+        assert_eq!(steering.Target(), Some(3));
+        assert_eq!(steering.InterposeAgents(), Some((1, 2)));
+
+        steering.SetSummingMethod(SummingMethod::weighted_average);
+        let force = Steer(&mut steering, &config, &vehicles, &[], Vec2::ZERO);
+        let evade = SteeringBehavior::Evade(&vehicles[0], &vehicles[3]) * config.EvadeWeight;
+        let interpose = SteeringBehavior::Interpose(&vehicles[0], &vehicles[1], &vehicles[2]) * config.InterposeWeight;
+        let expected = (evade + interpose).clamp_length_max(config.MaxSteeringForce);
+        assert!(force.abs_diff_eq(expected, 1e-3), "{force} {expected}");
+    }
+
+    #[test]
+    pub fn test_hide() {
+        // the hiding spot is on the far side of the obstacle from the hunter
+        let spot = SteeringBehavior::GetHidingPosition(vec2(200.0, 200.0), 20.0, vec2(100.0, 200.0));
+        assert_eq!(spot, vec2(250.0, 200.0));
+
+        let config = ConfigLoader::default();
+        let vehicles = [
+            Fish(&config, vec2(160.0, 260.0), Vec2::ZERO),
+            Fish(&config, vec2(100.0, 200.0), Vec2::ZERO),
+        ];
+
+        // it arrives at the spot behind the closest obstacle
+        let obstacles = [Obstacle::new(vec2(200.0, 200.0), 20.0), Obstacle::new(vec2(600.0, 600.0), 20.0)];
+        let force = SteeringBehavior::Hide(&vehicles[0], &vehicles[1], &obstacles);
+        assert!(
+            force.normalize().abs_diff_eq((spot - vehicles[0].position).normalize(), 1e-5),
+            "{force}"
+        );
+
+        // and evades the hunter when there is nothing to hide behind
+        let force = SteeringBehavior::Hide(&vehicles[0], &vehicles[1], &[]);
+        assert_ne!(force, Vec2::ZERO);
+        assert_eq!(force, SteeringBehavior::Evade(&vehicles[0], &vehicles[1]));
+
+        // hiding doesn't retarget evade
+        let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+        steering.EvadeOn(2);
+        steering.HideOn(1);
+        assert_eq!(steering.Target(), Some(2));
+        assert_eq!(steering.Hunter(), Some(1));
+    }
+
+    // a fish swimming at full speed straight at a wall turns back before
+    // reaching it. It is moved without a tank, so nothing but the steering
+    // keeps it off the wall.
+    #[test]
+    pub fn test_wall_avoidance() {
+        let config = ConfigLoader::default();
+        let walls = [Wall2D::new(vec2(300.0, 0.0), vec2(300.0, 800.0))];
+        let cell_space = BruteForce::new(1000.0, 800.0);
+
+        let run = |avoid: bool| {
+            let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+            if avoid {
+                steering.WallAvoidanceOn();
+            }
+
+            let mut vehicle = Fish(&config, vec2(150.0, 400.0), vec2(config.MaxSpeed, 0.0));
+            let mut furthest = 0.0f32;
+
+            for _ in 0..300 {
+                let ctx = SteeringContext {
+                    vehicles: std::slice::from_ref(&vehicle),
+                    obstacles: &[],
+                    walls: &walls,
+                    cell_space: &cell_space,
+                    crosshair: Vec2::ZERO,
+                    config: &config,
+                    time_elapsed: 1.0 / 60.0,
+                };
+                let force = steering.Calculate(0, &ctx);
+                vehicle.Update(force, 1.0 / 60.0, 1000, 800, None);
+                furthest = furthest.max(vehicle.position.x);
+            }
+
+            (furthest, vehicle.velocity)
+        };
+
+        let (furthest, velocity) = run(true);
+        assert!(furthest < 300.0, "{furthest}");
+        assert!(velocity.x < 0.0, "{velocity}");
+
+        // without avoidance the same fish swims straight through
+        let (furthest, _) = run(false);
+        assert!(furthest > 300.0, "{furthest}");
+    }
+
+    #[test]
+    pub fn test_obstacle_avoidance() {
+        let config = ConfigLoader::default();
+        let mut steering = SteeringBehavior::new(&config, &mut SimRng::seed_from_u64(1));
+
+        // at half speed the detection box is 1.5 times its minimum length
+        let vehicle = Fish(&config, vec2(100.0, 100.0), vec2(config.MaxSpeed / 2.0, 0.0));
+        let box_length = config.MinDetectionBoxLength * 1.5;
+
+        // an obstacle in the box, a little to the side, turns the vehicle
+        // and brakes it
+        let ahead = Obstacle::new(vec2(100.0 + box_length / 2.0, 105.0), 10.0);
+        let force = steering.ObstacleAvoidance(&vehicle, &[ahead], config.MinDetectionBoxLength);
+        assert!(force.dot(vehicle.heading) < 0.0, "{force}");
+        assert!(force.dot(vehicle.side_vec).abs() > 0.0, "{force}");
+
+        // the vehicle's own bounding radius counts too, so one just clear of
+        // its centre line is still avoided rather than grazed
+        assert!(vehicle.bounding_radius > 3.0);
+        let grazed = Obstacle::new(vec2(100.0 + box_length / 2.0, 113.0), 10.0);
+        let force = steering.ObstacleAvoidance(&vehicle, &[grazed], config.MinDetectionBoxLength);
+        assert!(force.dot(vehicle.side_vec) < 0.0, "{force}");
+
+        // beyond the end of the box, behind the vehicle, or off to the side
+        // of its path it is ignored
+        for position in [vec2(100.0 + box_length + 20.0, 100.0), vec2(70.0, 100.0), vec2(130.0, 130.0)] {
+            let obstacle = Obstacle::new(position, 10.0);
+            let force = steering.ObstacleAvoidance(&vehicle, &[obstacle], config.MinDetectionBoxLength);
+            assert_eq!(force, Vec2::ZERO, "{position}");
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//------------------------------------------------------------------------
+//
+//  Parameter sweeps. A sweep file lists config overrides to try, either
+//  as a grid of values for each key or as a list of runs, and every
+//  combination is run headless with each of the seeds. The school metrics
+//  averaged over the last few steps of each run are written out as CSV.
+//
+//      base = "assets/config/schooling.toml"   # optional, else CONFIG
+//      steps = 1200
+//      measure_steps = 300                      # optional, default 1
+//      seeds = [1, 2, 3]                        # optional, default [0]
+//
+//      [grid]
+//      SeparationWeight = [100.0, 200.0, 400.0]
+//      CohesionWeight = [200.0, 400.0]
+//
+//      [[runs]]
+//      AlignmentWeight = 0.0
+//
+//      [[runs]]
+//      AlignmentWeight = 200.0
+//
+//  When both are given every run is crossed with every point of the grid,
+//  the above being 2 x 3 x 2 = 12 combinations and 36 runs.
+//
+//------------------------------------------------------------------------
+
+use crate::configuration::{ConfigError, ConfigLoader};
+use crate::game_world::GameWorld;
+use crate::metrics::SchoolMetrics;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SweepFile {
+    base: Option<PathBuf>,
+    steps: usize,
+    #[serde(default = "default_measure_steps")]
+    measure_steps: usize,
+    #[serde(default = "default_seeds")]
+    seeds: Vec<u64>,
+    #[serde(default)]
+    grid: toml::Table,
+    #[serde(default)]
+    runs: Vec<toml::Table>,
+}
+
+fn default_measure_steps() -> usize {
+    1
+}
+
+fn default_seeds() -> Vec<u64> {
+    vec![0]
+}
+
+#[derive(Debug)]
+pub enum SweepError {
+    // the sweep file could not be read
+    Io(PathBuf, io::Error),
+    // the file is not valid TOML or has an unknown key
+    Parse(toml::de::Error),
+    // the base config could not be loaded
+    Base(ConfigError),
+    // a combination of overrides is not a valid config
+    Combination(String, ConfigError),
+    // a value the sweep can't be run with
+    Invalid(String),
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SweepError::Io(path, error) => write!(f, "could not read sweep file {}: {}", path.display(), error),
+            SweepError::Parse(error) => write!(f, "could not parse sweep: {}", error),
+            SweepError::Base(error) => write!(f, "could not load sweep base config: {}", error),
+            SweepError::Combination(overrides, error) => write!(f, "sweep combination {{{}}} is not valid: {}", overrides, error),
+            SweepError::Invalid(reason) => write!(f, "invalid sweep: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SweepError {}
+
+// one point of the sweep, the keys it sets and the config they make
+pub struct Combination {
+    pub overrides: toml::Table,
+    pub config: ConfigLoader,
+}
+
+pub struct Sweep {
+    m_iSteps: usize,
+    m_iMeasureSteps: usize,
+    m_Seeds: Vec<u64>,
+    m_Combinations: Vec<Combination>,
+
+    // every key set by any combination, the columns of the CSV
+    m_Keys: Vec<String>,
+}
+
+impl Sweep {
+    pub fn load(path: impl AsRef<Path>) -> Result<Sweep, SweepError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|error| SweepError::Io(path.to_path_buf(), error))?;
+        Sweep::from_toml_str(&text)
+    }
+
+    pub fn from_toml_str(text: &str) -> Result<Sweep, SweepError> {
+        let file: SweepFile = toml::from_str(text).map_err(SweepError::Parse)?;
+
+        if file.steps == 0 {
+            return Err(SweepError::Invalid("steps must be greater than zero".to_string()));
+        }
+        if file.measure_steps == 0 || file.measure_steps > file.steps {
+            return Err(SweepError::Invalid("measure_steps must be between one and steps".to_string()));
+        }
+        if file.seeds.is_empty() {
+            return Err(SweepError::Invalid("seeds must list at least one seed".to_string()));
+        }
+
+        let base = match &file.base {
+            Some(path) => ConfigLoader::load(path).map_err(SweepError::Base)?,
+            None => ConfigLoader::default(),
+        };
+
+        let mut combinations = vec![];
+        let mut keys = BTreeSet::new();
+
+        for overrides in Sweep::Expand(&file.grid, &file.runs)? {
+            let config = base
+                .with_overrides(&overrides)
+                .map_err(|error| SweepError::Combination(Sweep::Describe(&overrides), error))?;
+
+            keys.extend(overrides.keys().cloned());
+            combinations.push(Combination { overrides, config });
+        }
+
+        Ok(Sweep {
+            m_iSteps: file.steps,
+            m_iMeasureSteps: file.measure_steps,
+            m_Seeds: file.seeds,
+            m_Combinations: combinations,
+            m_Keys: keys.into_iter().collect(),
+        })
+    }
+
+    //-------------------------------- Expand --------------------------------
+    //
+    //  every point of the grid crossed with every run. A missing grid or
+    //  list of runs counts as a single empty one.
+    //------------------------------------------------------------------------
+    fn Expand(grid: &toml::Table, runs: &[toml::Table]) -> Result<Vec<toml::Table>, SweepError> {
+        let mut points = vec![toml::Table::new()];
+
+        for (key, values) in grid {
+            let values = match values.as_array() {
+                Some(values) if !values.is_empty() => values,
+                _ => return Err(SweepError::Invalid(format!("grid key {} must be a list of values", key))),
+            };
+
+            points = points
+                .iter()
+                .flat_map(|point| {
+                    values.iter().map(move |value| {
+                        let mut point = point.clone();
+                        point.insert(key.clone(), value.clone());
+                        point
+                    })
+                })
+                .collect();
+        }
+
+        if runs.is_empty() {
+            return Ok(points);
+        }
+
+        let mut combinations = vec![];
+        for run in runs {
+            for point in &points {
+                let mut combination = run.clone();
+                combination.extend(point.iter().map(|(key, value)| (key.clone(), value.clone())));
+                combinations.push(combination);
+            }
+        }
+
+        Ok(combinations)
+    }
+
+    fn Describe(overrides: &toml::Table) -> String {
+        overrides
+            .iter()
+            .map(|(key, value)| format!("{} = {}", key, value))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    pub fn Combinations(&self) -> &[Combination] {
+        &self.m_Combinations
+    }
+
+    pub fn Seeds(&self) -> &[u64] {
+        &self.m_Seeds
+    }
+
+    pub fn NumRuns(&self) -> usize {
+        self.m_Combinations.len() * self.m_Seeds.len()
+    }
+
+    //-------------------------------- RunOne --------------------------------
+    //
+    //  runs a combination headless with a seed in a cx by cy world and
+    //  returns the metrics averaged over the last measure_steps steps
+    //------------------------------------------------------------------------
+    pub fn RunOne(&self, combination: usize, seed: u64, cx: i32, cy: i32) -> MeanMetrics {
+        let config = ConfigLoader {
+            Seed: Some(seed),
+            ..self.m_Combinations[combination].config
+        };
+
+        let mut game_world = GameWorld::with_config(cx, cy, config);
+        let mut mean = MeanMetrics::default();
+
+        for step in 0..self.m_iSteps {
+            game_world.Step(config.FixedTimeStep);
+
+            if step >= self.m_iSteps - self.m_iMeasureSteps {
+                mean.Add(&game_world.Metrics());
+            }
+        }
+
+        mean
+    }
+
+    pub fn WriteCsvHeader(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut columns = vec!["combination".to_string(), "seed".to_string()];
+        columns.extend(self.m_Keys.iter().cloned());
+        writeln!(writer, "{},{}", columns.join(","), SchoolMetrics::CSV_HEADER)
+    }
+
+    // a row for a run of combination, with the value of every swept key
+    // whether this combination set it or kept the base value
+    pub fn WriteCsvRow(&self, writer: &mut impl Write, combination: usize, seed: u64, mean: &MeanMetrics) -> io::Result<()> {
+        let config = toml::Table::try_from(ConfigLoader {
+            Seed: None,
+            ..self.m_Combinations[combination].config
+        })
+        .expect("every key but Seed fits in TOML");
+
+        let mut columns = vec![combination.to_string(), seed.to_string()];
+        for key in &self.m_Keys {
+            columns.push(config.get(key).map(|value| value.to_string()).unwrap_or_default());
+        }
+
+        writeln!(writer, "{},{}", columns.join(","), mean.CsvRow())
+    }
+}
+
+// the metrics of several steps averaged. Subgroups are averaged too so
+// they are kept as a float.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct MeanMetrics {
+    pub polarization: f32,
+    pub milling: f32,
+    pub mean_nearest_neighbour: f32,
+    pub extent: f32,
+    pub subgroups: f32,
+    m_iCount: usize,
+}
+
+impl MeanMetrics {
+    pub fn Add(&mut self, metrics: &SchoolMetrics) {
+        let count = self.m_iCount as f32;
+        let mean = |current: f32, value: f32| (current * count + value) / (count + 1.0);
+
+        self.polarization = mean(self.polarization, metrics.polarization);
+        self.milling = mean(self.milling, metrics.milling);
+        self.mean_nearest_neighbour = mean(self.mean_nearest_neighbour, metrics.mean_nearest_neighbour);
+        self.extent = mean(self.extent, metrics.extent);
+        self.subgroups = mean(self.subgroups, metrics.subgroups as f32);
+        self.m_iCount += 1;
+    }
+
+    pub fn Count(&self) -> usize {
+        self.m_iCount
+    }
+
+    // the values in the order of SchoolMetrics::CSV_HEADER
+    pub fn CsvRow(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.polarization, self.milling, self.mean_nearest_neighbour, self.extent, self.subgroups
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::ConfigError;
+    use crate::sweep::{Sweep, SweepError};
+
+    #[test]
+    pub fn test_grid_crossed_with_runs() {
+        let sweep = Sweep::from_toml_str(
+            "steps = 10\n\
+             seeds = [1, 2]\n\
+             [grid]\n\
+             SeparationWeight = [100.0, 300]\n\
+             CohesionWeight = [50.0, 150.0, 250.0]\n\
+             [[runs]]\n\
+             NumAgents = 20\n\
+             [[runs]]\n\
+             NumAgents = 30\n\
+             CohesionWeight = 999.0\n",
+        )
+        .unwrap();
+
+        assert_eq!(sweep.Combinations().len(), 12);
+        assert_eq!(sweep.NumRuns(), 24);
+
+        let first = &sweep.Combinations()[0].config;
+        assert_eq!((first.NumAgents, first.CohesionWeight, first.SeparationWeight), (20, 50.0, 100.0));
+
+        // the grid wins over a run setting the same key
+        let last = &sweep.Combinations()[11].config;
+        assert_eq!((last.NumAgents, last.CohesionWeight, last.SeparationWeight), (30, 250.0, 300.0));
+
+        let mut csv = vec![];
+        sweep.WriteCsvHeader(&mut csv).unwrap();
+        assert!(String::from_utf8(csv)
+            .unwrap()
+            .starts_with("combination,seed,CohesionWeight,NumAgents,SeparationWeight,"));
+
+        let result = Sweep::from_toml_str("steps = 10\n[grid]\nNumAgents = [10, -1]\n");
+        assert!(matches!(
+            result,
+            Err(SweepError::Combination(_, ConfigError::Invalid { key: "NumAgents", .. }))
+        ));
+        assert!(matches!(
+            Sweep::from_toml_str("steps = 10\n[grid]\nNoSuchWeight = [1.0]\n"),
+            Err(SweepError::Combination(_, ConfigError::Parse(_)))
+        ));
+    }
+
+    #[test]
+    pub fn test_runs_are_reproducible() {
+        let sweep = Sweep::from_toml_str("steps = 40\nmeasure_steps = 10\nseeds = [7]\n[grid]\nNumAgents = [25]\n").unwrap();
+
+        let first = sweep.RunOne(0, 7, 1000, 800);
+        let second = sweep.RunOne(0, 7, 1000, 800);
+
+        assert_eq!(first.Count(), 10);
+        assert_eq!(first, second);
+        assert!(first.subgroups >= 1.0);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::c2d_matrix::C2DMatrix;
-use glam::{Mat2, Vec2};
+use glam::{vec2, Mat2, Vec2};
 
 //-------------------------- Vec2DRotateAroundOrigin --------------------------
 //
@@ -35,6 +35,33 @@ pub fn PointToWorldSpace(point: Vec2, AgentHeading: Vec2, AgentSide: Vec2, Agent
     return TransPoint;
 }
 
+//--------------------- VectorToWorldSpace --------------------------------
+//
+//  Transforms a vector from the agent's local space into world space
+//------------------------------------------------------------------------
+pub fn VectorToWorldSpace(vec: Vec2, AgentHeading: Vec2, AgentSide: Vec2) -> Vec2 {
+    //create a transformation matrix
+    let mut matTransform = C2DMatrix::identity();
+
+    //rotate
+    matTransform = matTransform.Rotate(AgentHeading, AgentSide);
+
+    //now transform the vertices
+    matTransform.TransformVector2Ds(vec)
+}
+
+//--------------------- PointToLocalSpace --------------------------------
+//
+//  Transforms a point from world space into the agent's local space. This
+//  is the inverse of the rotate and translate used by PointToWorldSpace.
+//------------------------------------------------------------------------
+pub fn PointToLocalSpace(point: Vec2, AgentHeading: Vec2, AgentSide: Vec2, AgentPosition: Vec2) -> Vec2 {
+    let Tx = -AgentPosition.dot(AgentHeading);
+    let Ty = -AgentPosition.dot(AgentSide);
+
+    vec2(point.dot(AgentHeading) + Tx, point.dot(AgentSide) + Ty)
+}
+
 /*
 //--------------------------- WorldTransform -----------------------------
 //
@@ -64,3 +91,22 @@ inline std::vector<Vector2D> WorldTransform(std::vector<Vector2D> &points,
     return TranVector2Ds;
 }
  */
+
+#[cfg(test)]
+mod tests {
+    use crate::transformations::{PointToLocalSpace, PointToWorldSpace};
+    use glam::vec2;
+
+    #[test]
+    pub fn test_local_space_round_trip() {
+        let heading = vec2(1.0, 1.0).normalize();
+        let side = heading.perp();
+        let position = vec2(100.0, 50.0);
+
+        let point = vec2(130.0, 20.0);
+        let local = PointToLocalSpace(point, heading, side, position);
+        let world = PointToWorldSpace(local, heading, side, position);
+
+        assert!(world.distance(point) < 0.001, "{:?} != {:?}", world, point);
+    }
+}
@@ -1,5 +1,6 @@
 use glam::Vec2;
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
 use rand_distr::{Distribution, Normal};
 use std::cmp::Ordering;
 use std::ops::Mul;
@@ -30,30 +31,35 @@ pub fn f32_max(a: f32, b: f32) -> f32 {
     }
 }
 
-pub fn RandInt(x: i32, y: i32) -> i32 {
-    thread_rng().gen_range(x..=y)
+// the random number generator used throughout the simulation. ChaCha8 gives
+// the same stream for a seed on every platform, so a seeded world replays
+// exactly.
+pub type SimRng = ChaCha8Rng;
+
+pub fn RandInt<R: Rng + ?Sized>(rng: &mut R, x: i32, y: i32) -> i32 {
+    rng.gen_range(x..=y)
 }
 
-pub fn RandFloat() -> f32 {
-    thread_rng().gen_range(0.0..=1.0)
+pub fn RandFloat<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    rng.gen_range(0.0..=1.0)
 }
 
-pub fn RandInRange(x: f32, y: f32) -> f32 {
-    thread_rng().gen_range(x..=y)
+pub fn RandInRange<R: Rng + ?Sized>(rng: &mut R, x: f32, y: f32) -> f32 {
+    rng.gen_range(x..=y)
 }
 
-pub fn RandBool() -> bool {
-    thread_rng().gen_range(0.0..=1.0) > 0.5
+pub fn RandBool<R: Rng + ?Sized>(rng: &mut R) -> bool {
+    rng.gen_range(0.0..=1.0) > 0.5
 }
 
 //returns a random float in the range -1 < n < 1
-pub fn RandomClamped() -> f32 {
-    thread_rng().gen_range(-1.0..=1.0)
+pub fn RandomClamped<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    rng.gen_range(-1.0..=1.0)
 }
 
-pub fn rand_normal_distribution() -> f32 {
+pub fn rand_normal_distribution<R: Rng + ?Sized>(rng: &mut R) -> f32 {
     let normal: Normal<f32> = Normal::new(2.0, 0.2).unwrap();
-    let v = normal.sample(&mut thread_rng());
+    let v = normal.sample(rng);
     v
 }
 
@@ -83,10 +89,59 @@ pub fn WrapAround(pos: &mut Vec2, MaxX: i32, MaxY: i32) {
     }
 }
 
+//---------------------------- LerpWrapped -------------------------------
+//
+//  blends between two positions in a wrapped world. When the positions
+//  are more than half the world apart the shorter way round is across the
+//  seam, so the blend goes that way and the result is wrapped back in.
+//------------------------------------------------------------------------
+pub fn LerpWrapped(from: Vec2, to: Vec2, alpha: f32, MaxX: i32, MaxY: i32) -> Vec2 {
+    let mut from = from;
+    let delta = to - from;
+
+    if delta.x.abs() > MaxX as f32 / 2.0 {
+        from.x += MaxX as f32 * delta.x.signum();
+    }
+    if delta.y.abs() > MaxY as f32 / 2.0 {
+        from.y += MaxY as f32 * delta.y.signum();
+    }
+
+    let mut position = from.lerp(to, alpha);
+    WrapAround(&mut position, MaxX, MaxY);
+    position
+}
+
+// the displacement from one point to another the short way round a world
+// that wraps at its edges
+pub fn WrappedOffset(from: Vec2, to: Vec2, world: Vec2) -> Vec2 {
+    let mut offset = to - from;
+
+    if offset.x.abs() > world.x / 2.0 {
+        offset.x -= world.x * offset.x.signum();
+    }
+    if offset.y.abs() > world.y / 2.0 {
+        offset.y -= world.y * offset.y.signum();
+    }
+
+    offset
+}
+
+//blends between two unit headings, keeping the result unit length
+pub fn LerpHeading(from: Vec2, to: Vec2, alpha: f32) -> Vec2 {
+    let heading = from.lerp(to, alpha);
+
+    if heading.length_squared() > 0.00000001 {
+        heading.normalize()
+    } else {
+        to
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::{rand_normal_distribution, Truncate, WrapAround};
+    use crate::utils::{rand_normal_distribution, SimRng, Truncate, WrapAround};
     use glam::vec2;
+    use rand::SeedableRng;
 
     #[test]
     pub fn test_truncate() {
@@ -109,8 +164,9 @@ mod tests {
 
     #[test]
     pub fn test_distribution() {
+        let mut rng = SimRng::seed_from_u64(0);
         for i in 0..1000 {
-            let x = rand_normal_distribution() - 2.0;
+            let x = rand_normal_distribution(&mut rng) - 2.0;
             println!("{x}");
         }
     }
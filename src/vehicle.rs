@@ -1,15 +1,25 @@
-use crate::configuration::CONFIG;
+use crate::configuration::ConfigLoader;
 use crate::entity_traits::{next_valid_id, EntityBase, EntityMovable, EntitySteerable};
-use crate::game_world::GameWorld;
 use crate::smoother::Smoother;
+use crate::snapshot::VehicleSnapshot;
 use crate::steering_behavior::SteeringBehavior;
-use crate::utils::{RandInRange, Truncate, WrapAround};
-use glam::{vec2, vec3, Mat4, Vec2};
+use crate::utils::{LerpHeading, LerpWrapped, RandInRange, SimRng, Truncate, WrapAround};
+use crate::wall_2d::Wall2D;
+use glam::{vec2, Vec2};
+#[cfg(feature = "render")]
+use glam::{vec3, Mat4};
+#[cfg(feature = "render")]
 use small_gl_core::model::Model;
+#[cfg(feature = "render")]
 use small_gl_core::shader::Shader;
-use std::cell::RefCell;
-use std::rc::Rc;
 
+// the fish model is about this long either side of its centre, so scaled
+// by the vehicle's scale it gives the bounding radius
+const MODEL_RADIUS: f32 = 0.32;
+
+// A vehicle is plain data kept in GameWorld::m_Vehicles. Its steering is
+// kept alongside in GameWorld::m_Steering at the same index, and other
+// vehicles, such as a pursuit target, are referred to by that index.
 pub struct Vehicle {
     // EntityBase
     pub id: i32,
@@ -29,28 +39,27 @@ pub struct Vehicle {
     pub max_turn_rate: f32,
 
     // EntitySteerable
-    pub m_pSteering: RefCell<SteeringBehavior>,
     m_pHeadingSmoother: Smoother<Vec2>,
     m_vSmoothedHeading: Vec2,
     m_bSmoothingOn: bool,
 
+    // position and displayed heading before the last update, rendering
+    // blends from these to the current values
+    m_vPrevPosition: Vec2,
+    m_vPrevHeading: Vec2,
+
     // temp
     height: f32, // create some depth
 
     //keeps a track of the most recent update time. (some of the
     //steering behaviors make use of this - see Wander)
     pub m_dTimeElapsed: f32,
-
-    pub m_pWorld: Rc<RefCell<GameWorld>>,
-
-    //sprite_model: SpriteModel,
-    model: Model,
 }
 
 impl Vehicle {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        world: Rc<RefCell<GameWorld>>,
+        config: &ConfigLoader,
         position: Vec2,
         rotation: f32,
         velocity: Vec2,
@@ -59,19 +68,19 @@ impl Vehicle {
         max_speed: f32,
         max_turn_rate: f32,
         scale: f32,
-        model: Model,
-    ) -> Rc<RefCell<Vehicle>> {
+        rng: &mut SimRng,
+    ) -> Vehicle {
         let heading = vec2(rotation.sin(), -rotation.cos());
 
-        let heading_smoother = Smoother::new(CONFIG.NumSamplesForSmoothing, vec2(0.0, 0.0));
+        let heading_smoother = Smoother::new(config.NumSamplesForSmoothing, vec2(0.0, 0.0));
 
-        let vehicle = Rc::new(RefCell::new(Vehicle {
+        Vehicle {
             id: next_valid_id(),
             entity_type: 0,
             tag: false,
             position,
             scale: vec2(scale, scale),
-            bounding_radius: 0.0,
+            bounding_radius: scale * MODEL_RADIUS,
             velocity,
             heading,
             side_vec: Default::default(),
@@ -79,75 +88,116 @@ impl Vehicle {
             max_speed,
             max_force,
             max_turn_rate,
-            m_pWorld: world,
-            m_pSteering: RefCell::new(SteeringBehavior::new()),
             m_pHeadingSmoother: heading_smoother,
             m_vSmoothedHeading: Default::default(),
             m_bSmoothingOn: true,
+            m_vPrevPosition: position,
+            m_vPrevHeading: heading,
             m_dTimeElapsed: 0.0,
-            model,
-            height: RandInRange(0.0, 50.0),
-        }));
+            height: RandInRange(rng, 0.0, 50.0),
+        }
+    }
 
-        let id = vehicle.borrow().id();
-        if id == 0 {
-            vehicle.borrow().m_pSteering.borrow_mut().m_vWanderTarget = vec2(0.0, 0.0);
+    // copies out the vehicle's state along with the steering that drives it
+    pub fn Snapshot(&self, steering: &SteeringBehavior) -> VehicleSnapshot {
+        VehicleSnapshot {
+            id: self.id,
+            entity_type: self.entity_type,
+            tag: self.tag,
+            position: self.position,
+            scale: self.scale,
+            bounding_radius: self.bounding_radius,
+            velocity: self.velocity,
+            heading: self.heading,
+            side_vec: self.side_vec,
+            mass: self.mass,
+            max_speed: self.max_speed,
+            max_force: self.max_force,
+            max_turn_rate: self.max_turn_rate,
+            steering: steering.clone(),
+            heading_smoother: self.m_pHeadingSmoother.clone(),
+            smoothed_heading: self.m_vSmoothedHeading,
+            smoothing_on: self.m_bSmoothingOn,
+            prev_position: self.m_vPrevPosition,
+            prev_heading: self.m_vPrevHeading,
+            height: self.height,
+            time_elapsed: self.m_dTimeElapsed,
         }
+    }
 
-        vehicle
+    // recreates a vehicle from a snapshot, its steering is snapshot.steering
+    pub fn FromSnapshot(snapshot: &VehicleSnapshot) -> Vehicle {
+        Vehicle {
+            id: snapshot.id,
+            entity_type: snapshot.entity_type,
+            tag: snapshot.tag,
+            position: snapshot.position,
+            scale: snapshot.scale,
+            bounding_radius: snapshot.bounding_radius,
+            velocity: snapshot.velocity,
+            heading: snapshot.heading,
+            side_vec: snapshot.side_vec,
+            mass: snapshot.mass,
+            max_speed: snapshot.max_speed,
+            max_force: snapshot.max_force,
+            max_turn_rate: snapshot.max_turn_rate,
+            m_pHeadingSmoother: snapshot.heading_smoother.clone(),
+            m_vSmoothedHeading: snapshot.smoothed_heading,
+            m_bSmoothingOn: snapshot.smoothing_on,
+            m_vPrevPosition: snapshot.prev_position,
+            m_vPrevHeading: snapshot.prev_heading,
+            height: snapshot.height,
+            m_dTimeElapsed: snapshot.time_elapsed,
+        }
     }
 
     //------------------------------ Update ----------------------------------
     //
-    //  Updates the vehicle's position from a series of steering behaviors
+    //  Moves the vehicle on by the combined force of its steering behaviors,
+    //  calculated by GameWorld::Step, and wraps it around the cx by cy world,
+    //  or keeps it inside the walls of the tank if there is one. Returns the
+    //  position it moved from.
     //------------------------------------------------------------------------
-    pub fn Update(vehicle: &Rc<RefCell<Vehicle>>, time_elapsed: f32) -> Vec2 {
+    pub fn Update(&mut self, steering_force: Vec2, time_elapsed: f32, cx: i32, cy: i32, tank: Option<&[Wall2D]>) -> Vec2 {
         // update the time elapsed
-        vehicle.borrow_mut().m_dTimeElapsed = time_elapsed;
+        self.m_dTimeElapsed = time_elapsed;
 
         // keep a record of its old position so we can update its cell later in this method
-        let old_pos = vehicle.borrow().position();
+        let old_pos = self.position;
 
-        // calculate the combined force from each steering behavior in the vehicle's list
-        let steering_force = vehicle.borrow().m_pSteering.borrow_mut().Calculate(vehicle);
+        // and of the state being rendered for interpolation
+        self.m_vPrevPosition = old_pos;
+        self.m_vPrevHeading = self.DisplayHeading();
 
         // Acceleration = Force/Mass
-        let acceleration = steering_force / vehicle.borrow().mass;
+        let acceleration = steering_force / self.mass;
 
         // update velocity
-        vehicle.borrow_mut().velocity += acceleration * time_elapsed;
+        self.velocity += acceleration * time_elapsed;
 
         // make sure vehicle does not exceed maximum velocity
-        // vehicle.m_vVelocity.Truncate(vehicle.m_dMaxSpeed);
-        let velocity = vehicle.borrow().velocity;
-        let max_speed = vehicle.borrow().max_speed;
-        let truncated_velocity = Truncate(velocity, max_speed);
-        vehicle.borrow_mut().velocity = truncated_velocity;
+        self.velocity = Truncate(self.velocity, self.max_speed);
 
         // update the position
-        let travel_distance = vehicle.borrow().velocity * time_elapsed;
-        vehicle.borrow_mut().position += travel_distance;
+        self.position += self.velocity * time_elapsed;
 
         // update the heading if the vehicle has a non zero velocity
-        if vehicle.borrow().velocity.length_squared() > 0.00000001 {
-            let normalize = vehicle.borrow().velocity.normalize_or_zero();
-            vehicle.borrow_mut().heading = normalize;
-
-            let prep = vehicle.borrow().heading.perp();
-            vehicle.borrow_mut().side_vec = prep;
+        if self.velocity.length_squared() > 0.00000001 {
+            self.heading = self.velocity.normalize_or_zero();
+            self.side_vec = self.heading.perp();
         }
 
         //EnforceNonPenetrationConstraint(this, World()->Agents());
 
-        //treat the screen as a toroid
-        let cx = vehicle.borrow().m_pWorld.borrow().cxClient();
-        let cy = vehicle.borrow().m_pWorld.borrow().cyClient();
-        WrapAround(&mut vehicle.borrow_mut().position, cx, cy);
+        //a fish that gets past its feelers is put back inside the tank,
+        //otherwise treat the screen as a toroid
+        match tank {
+            Some(walls) => KeepInside(&mut self.position, walls),
+            None => WrapAround(&mut self.position, cx, cy),
+        }
 
-        if vehicle.borrow().m_bSmoothingOn {
-            let heading = vehicle.borrow().heading;
-            let smoothed_heading = vehicle.borrow_mut().m_pHeadingSmoother.update(heading);
-            vehicle.borrow_mut().m_vSmoothedHeading = smoothed_heading;
+        if self.m_bSmoothingOn {
+            self.m_vSmoothedHeading = self.m_pHeadingSmoother.update(self.heading);
         }
 
         old_pos
@@ -157,35 +207,45 @@ impl Vehicle {
         self.m_vSmoothedHeading
     }
 
-    pub fn render(&mut self, shader: &Shader) {
-        let mut angle = 0.0f32;
-
+    // the heading the vehicle is drawn with
+    pub fn DisplayHeading(&self) -> Vec2 {
         if self.m_bSmoothingOn {
-            angle = self.m_vSmoothedHeading.x.acos().to_degrees();
-            if self.m_vSmoothedHeading.y < 0.0 {
-                angle = 360.0 - angle;
-            }
+            self.m_vSmoothedHeading
         } else {
-            angle = self.heading.x.acos().to_degrees();
-            if self.heading.y < 0.0 {
-                angle = 360.0 - angle;
-            }
+            self.heading
         }
+    }
 
-        // fix model orientation
-        angle += 90.0;
-        angle *= -1.0;
+    //------------------------- InterpolatedPosition -------------------------
+    //
+    //  the position alpha of the way from the previous update to the current
+    //  one. A vehicle that wrapped around an edge is blended across the seam
+    //  rather than back through the middle of the world.
+    //------------------------------------------------------------------------
+    pub fn InterpolatedPosition(&self, alpha: f32, cx: i32, cy: i32) -> Vec2 {
+        LerpWrapped(self.m_vPrevPosition, self.position, alpha, cx, cy)
+    }
+
+    pub fn InterpolatedHeading(&self, alpha: f32) -> Vec2 {
+        LerpHeading(self.m_vPrevHeading, self.DisplayHeading(), alpha)
+    }
 
-        // let position = vec3(self.position.x, self.position.y, 0.0);
-        let position = vec3(self.position.x - 400.0, self.height, self.position.y - 400.0);
-        let scale = vec3(self.scale.x, self.scale.y, self.scale.x);
+    // how far above the sea bed the fish swims, it only affects drawing
+    pub fn Height(&self) -> f32 {
+        self.height
+    }
 
-        let mut model_transform = Mat4::from_translation(position);
-        model_transform *= Mat4::from_axis_angle(vec3(0.0, 1.0, 0.0), angle.to_radians());
-        model_transform *= Mat4::from_scale(scale);
-        shader.set_mat4("model", &model_transform);
+    // the model is owned by the viewer so the vehicle itself carries no
+    // graphics state and can be simulated without a GL context
+    //
+    // alpha is how far the world is between its last two updates, cx and cy
+    // its size
+    #[cfg(feature = "render")]
+    pub fn render(&self, shader: &Shader, model: &Model, alpha: f32, cx: i32, cy: i32) {
+        let position = self.InterpolatedPosition(alpha, cx, cy);
+        let heading = self.InterpolatedHeading(alpha);
 
-        self.model.render(shader);
+        RenderFish(shader, model, position, heading, self.height, self.scale);
 
         // println!("fish id: {}   position: {}", self.ID(), position);
 
@@ -265,6 +325,50 @@ impl Vehicle {
     }
 }
 
+//------------------------------ KeepInside ------------------------------
+//
+//  pushes position back across any wall it has got behind. The walls must
+//  face inwards and enclose a convex tank with no corner sharper than a
+//  right angle, like the one GameWorld::CreateWalls builds. Then pushing
+//  the position back in past one wall never takes it out past another.
+//------------------------------------------------------------------------
+pub fn KeepInside(position: &mut Vec2, walls: &[Wall2D]) {
+    for wall in walls {
+        let depth = (*position - wall.From()).dot(wall.Normal());
+        if depth < 0.0 {
+            *position -= depth * wall.Normal();
+        }
+    }
+}
+
+//------------------------------ RenderFish ------------------------------
+//
+//  draws the fish model at a world position facing along heading. Used for
+//  live vehicles and for recordings played back in the viewer.
+//------------------------------------------------------------------------
+#[cfg(feature = "render")]
+pub fn RenderFish(shader: &Shader, model: &Model, position: Vec2, heading: Vec2, height: f32, scale: Vec2) {
+    let mut angle = heading.x.acos().to_degrees();
+    if heading.y < 0.0 {
+        angle = 360.0 - angle;
+    }
+
+    // fix model orientation
+    angle += 90.0;
+    angle *= -1.0;
+
+    // let position = vec3(self.position.x, self.position.y, 0.0);
+    let position = vec3(position.x - 400.0, height, position.y - 400.0);
+    let scale = vec3(scale.x, scale.y, scale.x);
+
+    let mut model_transform = Mat4::from_translation(position);
+    model_transform *= Mat4::from_axis_angle(vec3(0.0, 1.0, 0.0), angle.to_radians());
+    model_transform *= Mat4::from_scale(scale);
+    shader.set_mat4("model", &model_transform);
+
+    model.render(shader);
+}
+
 impl EntityBase for Vehicle {
     fn id(&self) -> i32 {
         self.id
@@ -297,12 +401,14 @@ impl EntityBase for Vehicle {
         self.scale
     }
 
+    // the bounding radius grows and shrinks with the scale
     fn set_scale_vec(&mut self, val: Vec2) {
+        self.bounding_radius *= val.x.max(val.y) / self.scale.x.max(self.scale.y);
         self.scale = val;
     }
 
     fn set_scale_float(&mut self, val: f32) {
-        self.scale = vec2(val, val);
+        self.set_scale_vec(vec2(val, val));
     }
 }
 
@@ -0,0 +1,387 @@
+//------------------------------------------------------------------------
+//
+//  The GLFW/OpenGL window that draws the school. Only built with the
+//  "render" feature, the simulation itself does not depend on it.
+//
+//------------------------------------------------------------------------
+
+use crate::shapes::mesh_plane::build_vertexes_and_indices;
+use crate::{SCR_HEIGHT, SCR_WIDTH};
+use glam::{vec3, Mat4};
+use glfw::{Action, Context, Key};
+use log::error;
+use schooling_fishes::configuration::ConfigWatcher;
+use schooling_fishes::vehicle::RenderFish;
+use schooling_fishes::{GameWorld, Player};
+use small_gl_core::camera::{Camera, CameraMovement};
+use small_gl_core::gl;
+use small_gl_core::mesh::{Color, Mesh};
+use small_gl_core::model::ModelBuilder;
+use small_gl_core::shader::Shader;
+use small_gl_core::texture::{Texture, TextureConfig};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// seconds between checks of the config file for edits
+const CONFIG_POLL_INTERVAL: f32 = 1.0;
+
+// frames skipped by one press of an arrow key with shift held during replay
+const SCRUB_FRAMES_WITH_SHIFT: i32 = 60;
+
+// what the viewer shows
+pub enum Scene {
+    // the simulation running live. F5 saves the world to snapshot_path and
+    // F9 loads it back
+    Live {
+        game_world: GameWorld,
+        config_watcher: Option<ConfigWatcher>,
+        snapshot_path: PathBuf,
+    },
+    // a recording played back. Space pauses, the arrow keys scrub a frame at
+    // a time, or further with shift held, and Home goes back to the start
+    Replay(Player),
+}
+
+struct State {
+    camera: Camera,
+    run: bool,
+    delta_time: f32,
+    frame_time: f32,
+    firstMouse: bool,
+    lastX: f32,
+    lastY: f32,
+    window_scale: (f32, f32),
+    viewport_width: f32,
+    viewport_height: f32,
+    save_snapshot: bool,
+    load_snapshot: bool,
+    toggle_pause: bool,
+    scrub_frames: i32,
+    rewind: bool,
+}
+
+fn error_callback(err: glfw::Error, description: String) {
+    error!("GLFW error {:?}: {:?}", err, description);
+}
+
+pub fn run(mut scene: Scene) {
+    let mut glfw = glfw::init(error_callback).unwrap();
+
+    glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+
+    // for Apple
+    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+
+    let (mut window, events) = glfw
+        .create_window(SCR_WIDTH as u32, SCR_HEIGHT as u32, "LearnOpenGL", glfw::WindowMode::Windowed)
+        .expect("Failed to create GLFW window.");
+
+    // Turn on all GLFW polling so that we can receive all WindowEvents
+    window.set_all_polling(true);
+    window.make_current();
+
+    // Initialize glad: load all OpenGL function pointers
+    // --------------------------------------------------
+    gl::load(|e| glfw.get_proc_address_raw(e) as *const std::os::raw::c_void);
+
+    // perspective setting
+    // //let camera = Camera::camera_vec3(vec3(300.0, 300.0, 500.0));
+    let camera = Camera::camera_vec3_up_yaw_pitch(
+        // vec3(400.0, 400.0, 700.0), for current x,y world
+        vec3(0.0, 170.0, 500.0), // for xz world
+        vec3(0.0, 1.0, 0.0),
+        -90.0, // seems camera starts by looking down the x-axis, so needs to turn left to see the plane
+        -20.0,
+    );
+
+    // let camera = Camera::camera_vec3_up_yaw_pitch(
+    //     vec3(400.0, -200.0, 50.0),
+    //     vec3(0.0, 1.0, 0.0),
+    //     -90.0, // seems camera starts by looking down the x-axis, so needs to turn left to see the plane
+    //     90.0);
+
+    // for ortho perspective
+    // let camera = Camera::camera_vec3(vec3(0.0, 0.0, 55.0));
+
+    // Initialize the world state
+    let mut state = State {
+        camera,
+        run: true,
+        delta_time: 0.0,
+        frame_time: 0.0,
+        firstMouse: true,
+        lastX: SCR_WIDTH / 2.0,
+        lastY: SCR_HEIGHT / 2.0,
+        window_scale: window.get_content_scale(),
+        viewport_width: SCR_WIDTH,
+        viewport_height: SCR_HEIGHT,
+        save_snapshot: false,
+        load_snapshot: false,
+        toggle_pause: false,
+        scrub_frames: 0,
+        rewind: false,
+    };
+
+    let shader_texture = Shader::new("assets/shaders/camera_texture.vert", "assets/shaders/camera_texture.frag").unwrap();
+
+    let wavy_shader = Shader::new("assets/shaders/wavy_texture.vert", "assets/shaders/wavy_texture.frag").unwrap();
+
+    let model_shader = Shader::new("assets/shaders/basic_model.vert", "assets/shaders/basic_model.frag").unwrap();
+    let wiggle_shader = Shader::new("assets/shaders/wiggle_shader.vert", "assets/shaders/wiggle_shader.frag").unwrap();
+
+    let model_shader = Rc::new(model_shader);
+
+    let water_texture = Rc::new(Texture::new("assets/images/water_texture.png", &TextureConfig::new().set_flipv(true)).unwrap());
+
+    let sand_texture = Rc::new(Texture::new("assets/images/ground_0024_color_1k.jpg", &TextureConfig::new()).unwrap());
+
+    let (vertices, indices) = build_vertexes_and_indices(500, 500, Color::white());
+    let surface_mesh = Mesh::new(vertices.clone(), indices.clone(), &water_texture, false);
+    let bottom_mesh = Mesh::new(vertices, indices, &sand_texture, false);
+
+    let big_fish = "assets/models/BarramundiFish/glTF/BarramundiFish.gltf";
+    let fish_model = ModelBuilder::new("big_fish", big_fish).build().unwrap();
+
+    unsafe {
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    }
+
+    let mut last_config_poll = 0.0f32;
+
+    // render loop
+    while !window.should_close() {
+        let current_time = glfw.get_time() as f32;
+
+        if state.run {
+            state.delta_time = current_time - state.frame_time;
+        } else {
+            state.delta_time = 0.0;
+        }
+        state.frame_time = current_time;
+
+        glfw.poll_events();
+        for (_, event) in glfw::flush_messages(&events) {
+            handle_window_event(&mut window, event, &mut state);
+        }
+
+        match &mut scene {
+            Scene::Live {
+                game_world,
+                config_watcher,
+                snapshot_path,
+            } => {
+                if let Some(watcher) = config_watcher.as_mut() {
+                    if current_time - last_config_poll > CONFIG_POLL_INTERVAL {
+                        last_config_poll = current_time;
+                        reload_config(game_world, watcher);
+                    }
+                }
+
+                if state.save_snapshot {
+                    state.save_snapshot = false;
+                    match game_world.SaveSnapshot(&*snapshot_path) {
+                        Ok(()) => println!("saved {}", snapshot_path.display()),
+                        Err(error) => println!("{}", error),
+                    }
+                }
+
+                if state.load_snapshot {
+                    state.load_snapshot = false;
+                    match GameWorld::LoadSnapshot(&*snapshot_path) {
+                        Ok(restored) => {
+                            println!("loaded {}", snapshot_path.display());
+                            stop_recording(game_world);
+                            *game_world = restored;
+                        }
+                        Err(error) => println!("{}", error),
+                    }
+                }
+
+                game_world.Update(state.delta_time);
+            }
+            Scene::Replay(player) => {
+                if state.toggle_pause {
+                    state.toggle_pause = false;
+                    player.TogglePause();
+                }
+
+                if state.rewind {
+                    state.rewind = false;
+                    player.Seek(0);
+                }
+
+                if state.scrub_frames != 0 {
+                    player.StepFrames(state.scrub_frames);
+                    state.scrub_frames = 0;
+                }
+
+                player.Update(state.delta_time);
+            }
+        }
+
+        unsafe {
+            gl::ClearColor(0.0, 0.02, 0.45, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+
+        let view = state.camera.get_view_matrix();
+        let projection = Mat4::perspective_rh_gl(
+            state.camera.zoom.to_radians(),
+            state.viewport_width / state.viewport_height,
+            0.1,
+            2000.0,
+        );
+        // let projection = Mat4::orthographic_rh_gl(0.0, 600.0, 0.0, 600.0, 0.1, 100.0);
+        // let projection = Mat4::orthographic_rh_gl(0.0, 1000.0, 0.0, 1000.0, 0.0, 1000.0);
+
+        // bottom
+        shader_texture.use_shader_with(&projection, &view);
+        shader_texture.set_float("alpha", 1.0);
+        bottom_mesh.render(&shader_texture, vec3(-2500.0, -5.0, -2500.0), 0.0, vec3(10.0, 1.0, 10.0));
+
+        // fish
+        wiggle_shader.use_shader_with(&projection, &view);
+        wiggle_shader.set_vec3("nosePos", &vec3(0.0, 0.0, -0.3));
+        wiggle_shader.set_float("time", state.frame_time);
+        match &scene {
+            Scene::Live { game_world, .. } => game_world.render(&wiggle_shader, &fish_model),
+            Scene::Replay(player) => {
+                let vehicles = &player.Recording().header.vehicles;
+                for (vehicle, sample) in vehicles.iter().zip(player.Samples()) {
+                    RenderFish(
+                        &wiggle_shader,
+                        &fish_model,
+                        sample.position,
+                        sample.heading,
+                        vehicle.height,
+                        vehicle.scale,
+                    );
+                }
+            }
+        }
+
+        // surface
+        wavy_shader.use_shader_with(&projection, &view);
+        wavy_shader.set_float("alpha", 0.4);
+        wavy_shader.set_float("current_time", current_time);
+        surface_mesh.render(&wavy_shader, vec3(-750.0, 100.0, -750.0), 0.0, vec3(3.0, 1.0, 3.0));
+
+        window.swap_buffers();
+    }
+
+    if let Scene::Live { game_world, .. } = &mut scene {
+        stop_recording(game_world);
+    }
+}
+
+// finishes any recording the world is making and reports how it went
+fn stop_recording(game_world: &mut GameWorld) {
+    match game_world.StopRecording() {
+        Ok(Some(frames)) => println!("recorded {} frames", frames),
+        Ok(None) => {}
+        Err(error) => println!("{}", error),
+    }
+}
+
+// applies an edited config file to the running world on its next update
+fn reload_config(game_world: &mut GameWorld, watcher: &mut ConfigWatcher) {
+    match watcher.poll() {
+        Some(Ok(config)) => {
+            println!("reloaded {}", watcher.path().display());
+            for key in game_world.ReloadConfig(config) {
+                println!("  {} can't be changed while running, restart to apply it", key);
+            }
+        }
+        Some(Err(error)) => println!("{}", error),
+        None => {}
+    }
+}
+
+//
+// GLFW maps callbacks to events.
+//
+fn handle_window_event(window: &mut glfw::Window, event: glfw::WindowEvent, state: &mut State) {
+    match event {
+        glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
+        glfw::WindowEvent::Key(Key::F5, _, Action::Press, _) => state.save_snapshot = true,
+        glfw::WindowEvent::Key(Key::F9, _, Action::Press, _) => state.load_snapshot = true,
+        glfw::WindowEvent::Key(Key::Space, _, Action::Press, _) => state.toggle_pause = true,
+        glfw::WindowEvent::Key(Key::Home, _, Action::Press, _) => state.rewind = true,
+        glfw::WindowEvent::Key(key @ (Key::Left | Key::Right), _, Action::Press | Action::Repeat, modifiers) => {
+            let frames = if modifiers.contains(glfw::Modifiers::Shift) {
+                SCRUB_FRAMES_WITH_SHIFT
+            } else {
+                1
+            };
+            state.scrub_frames += if key == Key::Left { -frames } else { frames };
+        }
+        glfw::WindowEvent::FramebufferSize(width, height) => {
+            framebuffer_size_event(window, state, width, height);
+        }
+        glfw::WindowEvent::Key(Key::W, _, _, _) => {
+            state.camera.process_keyboard(CameraMovement::Forward, state.delta_time);
+        }
+        glfw::WindowEvent::Key(Key::S, _, _, _) => {
+            state.camera.process_keyboard(CameraMovement::Backward, state.delta_time);
+        }
+        glfw::WindowEvent::Key(Key::A, _, _, _) => {
+            state.camera.process_keyboard(CameraMovement::Left, state.delta_time);
+        }
+        glfw::WindowEvent::Key(Key::D, _, _, _) => {
+            state.camera.process_keyboard(CameraMovement::Right, state.delta_time);
+        }
+        glfw::WindowEvent::Key(Key::Q, _, _, _) => {
+            state.camera.process_keyboard(CameraMovement::Up, state.delta_time);
+        }
+        glfw::WindowEvent::Key(Key::Z, _, _, _) => {
+            state.camera.process_keyboard(CameraMovement::Down, state.delta_time);
+        }
+        glfw::WindowEvent::CursorPos(xpos, ypos) => mouse_handler(state, xpos, ypos),
+        glfw::WindowEvent::Scroll(xoffset, ysoffset) => scroll_handler(state, xoffset, ysoffset),
+        _evt => {
+            // println!("WindowEvent: {:?}", _evt);
+        }
+    }
+}
+
+// glfw: whenever the window size changed (by OS or user resize) this event fires.
+// ---------------------------------------------------------------------------------------------
+fn framebuffer_size_event(_window: &mut glfw::Window, state: &mut State, width: i32, height: i32) {
+    println!("resize: width, height: {}, {}", width, height);
+    set_view_port(state, width, height);
+}
+
+fn set_view_port(state: &mut State, width: i32, height: i32) {
+    unsafe {
+        gl::Viewport(0, 0, width, height);
+    }
+
+    state.viewport_width = width as f32 / state.window_scale.0;
+    state.viewport_height = height as f32 / state.window_scale.1;
+}
+
+fn mouse_handler(state: &mut State, xposIn: f64, yposIn: f64) {
+    let xpos = xposIn as f32;
+    let ypos = yposIn as f32;
+
+    if state.firstMouse {
+        state.lastX = xpos;
+        state.lastY = ypos;
+        state.firstMouse = false;
+    }
+
+    let xoffset = xpos - state.lastX;
+    let yoffset = state.lastY - ypos; // reversed since y-coordinates go from bottom to top
+
+    state.lastX = xpos;
+    state.lastY = ypos;
+
+    state.camera.process_mouse_movement(xoffset, yoffset, true);
+}
+
+fn scroll_handler(state: &mut State, _xoffset: f64, yoffset: f64) {
+    state.camera.process_mouse_scroll(yoffset as f32);
+}
@@ -1,8 +1,107 @@
-#[derive(Debug)]
-pub struct Wall2D {}
+//------------------------------------------------------------------------
+//
+//  Name:   Wall2D.h
+//
+//  Desc:   class to create and render 2D walls. Defined as the two
+//          vectors A - B with a perpendicular normal.
+//
+//  Original Author: Mat Buckland (fup@ai-junkie.com)
+//
+//------------------------------------------------------------------------
+
+use glam::{vec2, Vec2};
+#[cfg(feature = "render")]
+use small_gl_core::gl;
+
+#[derive(Debug, Clone)]
+pub struct Wall2D {
+    m_vA: Vec2,
+    m_vB: Vec2,
+    m_vN: Vec2,
+}
 
 impl Wall2D {
-    pub(crate) fn Render(&self, _p0: bool) {
-        todo!()
+    pub fn new(A: Vec2, B: Vec2) -> Self {
+        let mut wall = Wall2D {
+            m_vA: A,
+            m_vB: B,
+            m_vN: Vec2::default(),
+        };
+        wall.CalculateNormal();
+        wall
+    }
+
+    pub fn with_normal(A: Vec2, B: Vec2, N: Vec2) -> Self {
+        Wall2D { m_vA: A, m_vB: B, m_vN: N }
+    }
+
+    fn CalculateNormal(&mut self) {
+        let temp = (self.m_vB - self.m_vA).normalize_or_zero();
+
+        self.m_vN = vec2(-temp.y, temp.x);
+    }
+
+    pub fn From(&self) -> Vec2 {
+        self.m_vA
+    }
+
+    pub fn SetFrom(&mut self, v: Vec2) {
+        self.m_vA = v;
+        self.CalculateNormal();
+    }
+
+    pub fn To(&self) -> Vec2 {
+        self.m_vB
+    }
+
+    pub fn SetTo(&mut self, v: Vec2) {
+        self.m_vB = v;
+        self.CalculateNormal();
+    }
+
+    pub fn Normal(&self) -> Vec2 {
+        self.m_vN
+    }
+
+    pub fn SetNormal(&mut self, n: Vec2) {
+        self.m_vN = n;
+    }
+
+    pub fn Center(&self) -> Vec2 {
+        (self.m_vA + self.m_vB) / 2.0
+    }
+
+    #[cfg(feature = "render")]
+    pub fn Render(&self, render_normals: bool) {
+        // same world to xz-plane mapping the vehicles are drawn with
+        let from = [self.m_vA.x - 400.0, 0.0, self.m_vA.y - 400.0];
+        let to = [self.m_vB.x - 400.0, 0.0, self.m_vB.y - 400.0];
+        let color = [0.5, 0.5, 0.5];
+
+        unsafe {
+            gl::LineWidth(1.0);
+            gl::Begin(gl::LINES);
+            gl::Color3fv(color.as_ptr());
+            gl::Vertex3fv(from.as_ptr());
+            gl::Vertex3fv(to.as_ptr());
+            gl::End();
+        }
+
+        //render the normals if rqd
+        if render_normals {
+            let mid = self.Center();
+            let normal_end = mid + self.m_vN * 5.0;
+
+            let start = [mid.x - 400.0, 0.0, mid.y - 400.0];
+            let end = [normal_end.x - 400.0, 0.0, normal_end.y - 400.0];
+
+            unsafe {
+                gl::Begin(gl::LINES);
+                gl::Color3fv(color.as_ptr());
+                gl::Vertex3fv(start.as_ptr());
+                gl::Vertex3fv(end.as_ptr());
+                gl::End();
+            }
+        }
     }
 }